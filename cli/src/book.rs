@@ -0,0 +1,470 @@
+//! PDF booklets: one page per play, with its name, diagram, legend and notes.
+
+use crate::raster::Rasterizer;
+use clap::ValueEnum;
+use pdf_writer::{Content, Filter, Finish, Name, Pdf, Rect, Ref, Str};
+use playbook_lang_core::{diagnostic, Renderer};
+use std::path::{Path, PathBuf};
+
+/// Page margins and the room between blocks, in points.
+const MARGIN: f32 = 40.0;
+const GAP: f32 = 12.0;
+/// Part of the page left to the diagrams; the rest is for the legend and notes.
+const DIAGRAM_SHARE: f32 = 0.6;
+const LEGEND_WIDTH: f32 = 320.0;
+/// Resolution diagrams are rasterized at.
+const DPI: f32 = 200.0;
+
+const TITLE_SIZE: f32 = 18.0;
+const HEADING_SIZE: f32 = 12.0;
+const TEXT_SIZE: f32 = 10.0;
+/// Distance between lines of text, relative to the font size.
+const LEADING: f32 = 1.3;
+
+const REGULAR: Name = Name(b"F1");
+const BOLD: Name = Name(b"F2");
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Paper {
+    A4,
+    Letter,
+}
+
+impl Paper {
+    /// Width and height in points.
+    fn size(self) -> (f32, f32) {
+        match self {
+            Paper::A4 => (595.0, 842.0),
+            Paper::Letter => (612.0, 792.0),
+        }
+    }
+}
+
+/// How the phases of a play are laid out on its page.
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum Layout {
+    /// Side by side, like the SVG.
+    Strip,
+    /// Two per row, each with its title.
+    Grid,
+}
+
+/// A play to print, already checked for errors.
+pub struct Play {
+    pub name: String,
+    pub source: String,
+    /// Where the play is, for its imports.
+    pub path: PathBuf,
+}
+
+impl Play {
+    /// A play named by its `meta` section, or else after its file.
+    pub fn new(path: &Path, source: String) -> Self {
+        let (playbook, _) = diagnostic::analyze(&source);
+        let name = playbook.meta.and_then(|meta| meta.name).unwrap_or_else(|| {
+            path.file_stem()
+                .map_or_else(String::new, |stem| stem.to_string_lossy().into_owned())
+        });
+        Self {
+            name,
+            source,
+            path: path.to_path_buf(),
+        }
+    }
+
+    /// Numbers of the phases, as written.
+    fn phase_numbers(&self) -> Vec<u32> {
+        let (playbook, _) = diagnostic::analyze(&self.source);
+        playbook.phases.iter().map(|phase| phase.number).collect()
+    }
+
+    /// The `meta` details and notes, then comments not attached to a player or
+    /// an action, phase comments last.
+    fn notes(&self) -> Vec<String> {
+        let (playbook, _) = diagnostic::analyze(&self.source);
+        let meta = playbook.meta.unwrap_or_default();
+        let mut notes: Vec<String> = [
+            meta.call.map(|call| format!("Call: {}", call)),
+            meta.category
+                .map(|category| format!("Category: {}", category)),
+            meta.formation
+                .map(|formation| format!("Formation: {}", formation)),
+            (!meta.tags.is_empty()).then(|| format!("Tags: {}", meta.tags.join(", "))),
+            meta.notes,
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        notes.extend(playbook.comments.into_iter().map(|c| c.text));
+        for phase in playbook.phases {
+            for comment in phase.comments {
+                notes.push(format!("Phase {}: {}", phase.number, comment.text));
+            }
+        }
+        notes
+    }
+}
+
+/// Paths listed in a manifest, one per line, relative to `dir`.
+/// Blank lines and lines starting with `#` are skipped.
+pub fn parse_manifest(content: &str, dir: &Path) -> Vec<PathBuf> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| dir.join(line))
+        .collect()
+}
+
+/// Prints `plays` into a PDF, one page each.
+pub fn booklet(
+    plays: &[Play],
+    renderer: &Renderer,
+    paper: Paper,
+    layout: Layout,
+) -> Result<Vec<u8>, String> {
+    let mut booklet = Booklet::new(paper);
+    let rasterizer = Rasterizer::new();
+    for play in plays {
+        booklet.add_play(play, renderer, layout, &rasterizer)?;
+    }
+    Ok(booklet.finish())
+}
+
+struct Booklet {
+    pdf: Pdf,
+    paper: Paper,
+    last_id: i32,
+    page_tree: Ref,
+    /// Regular and bold Helvetica, shared by every page.
+    fonts: (Ref, Ref),
+    pages: Vec<Ref>,
+}
+
+/// A diagram ready to be placed: its SVG and pixel size.
+struct Panel {
+    title: Option<String>,
+    svg: String,
+    size: (f32, f32),
+}
+
+impl Booklet {
+    fn new(paper: Paper) -> Self {
+        Self {
+            pdf: Pdf::new(),
+            paper,
+            last_id: 3,
+            page_tree: Ref::new(1),
+            fonts: (Ref::new(2), Ref::new(3)),
+            pages: Vec::new(),
+        }
+    }
+
+    fn next_id(&mut self) -> Ref {
+        self.last_id += 1;
+        Ref::new(self.last_id)
+    }
+
+    fn add_play(
+        &mut self,
+        play: &Play,
+        renderer: &Renderer,
+        layout: Layout,
+        rasterizer: &Rasterizer,
+    ) -> Result<(), String> {
+        let renderer = &renderer.clone().with_file(&play.path);
+        let (page_width, page_height) = self.paper.size();
+        let width = page_width - 2.0 * MARGIN;
+        let mut content = Content::new();
+        let mut images = Vec::new();
+        let mut top = page_height - MARGIN;
+
+        top -= TITLE_SIZE;
+        text(&mut content, BOLD, TITLE_SIZE, (MARGIN, top), &play.name);
+        top -= GAP;
+
+        // Diagrams, as large as the width and the diagram share of the page allow
+        let svgs = match layout {
+            Layout::Strip => vec![renderer.render(&play.source)?],
+            Layout::Grid => renderer.render_phases(&play.source)?,
+        };
+        let titled = svgs.len() > 1;
+        let numbers = play.phase_numbers();
+        let panels = svgs
+            .into_iter()
+            .enumerate()
+            .map(|(i, svg)| {
+                Ok(Panel {
+                    title: numbers
+                        .get(i)
+                        .filter(|_| titled)
+                        .map(|number| format!("Phase {}", number)),
+                    size: rasterizer.size(&svg)?,
+                    svg,
+                })
+            })
+            .collect::<Result<Vec<Panel>, String>>()?;
+        let columns = if layout == Layout::Grid { 2 } else { 1 }.min(panels.len());
+        let rows: Vec<&[Panel]> = panels.chunks(columns).collect();
+        let title_height = if titled { TEXT_SIZE * LEADING } else { 0.0 };
+        let fixed = (title_height + GAP) * rows.len() as f32 - GAP;
+        let mut cell = (width - GAP * (columns - 1) as f32) / columns as f32;
+        let tallest = |row: &[Panel]| {
+            row.iter()
+                .map(|panel| panel.size.1 / panel.size.0)
+                .fold(0.0, f32::max)
+        };
+        let ratios: f32 = rows.iter().map(|row| tallest(row)).sum();
+        let budget = (page_height - 2.0 * MARGIN) * DIAGRAM_SHARE;
+        if cell * ratios + fixed > budget {
+            cell = (budget - fixed) / ratios;
+        }
+        let left = MARGIN + (width - cell * columns as f32 - GAP * (columns - 1) as f32) / 2.0;
+        for row in rows {
+            let height = cell * tallest(row);
+            for (column, panel) in row.iter().enumerate() {
+                let x = left + (cell + GAP) * column as f32;
+                if let Some(title) = &panel.title {
+                    text(
+                        &mut content,
+                        REGULAR,
+                        TEXT_SIZE,
+                        (x, top - TEXT_SIZE),
+                        title,
+                    );
+                }
+                let size = (cell, cell * panel.size.1 / panel.size.0);
+                let corner = (x, top - title_height - size.1);
+                let image = self.image(rasterizer, panel, size.0)?;
+                place(&mut content, images.len(), corner, size);
+                images.push(image);
+            }
+            top -= title_height + height + GAP;
+        }
+
+        if let Some(legend) = renderer.render_legend(&play.source)? {
+            let panel = Panel {
+                title: None,
+                size: rasterizer.size(&legend)?,
+                svg: legend,
+            };
+            let legend_width = LEGEND_WIDTH.min(width);
+            let size = (legend_width, legend_width * panel.size.1 / panel.size.0);
+            let image = self.image(rasterizer, &panel, size.0)?;
+            place(&mut content, images.len(), (MARGIN, top - size.1), size);
+            images.push(image);
+            top -= size.1 + GAP;
+        }
+
+        let notes = play.notes();
+        if !notes.is_empty() {
+            top -= HEADING_SIZE;
+            text(&mut content, BOLD, HEADING_SIZE, (MARGIN, top), "Notes");
+            top -= GAP / 2.0;
+            let lines: Vec<String> = notes
+                .iter()
+                .flat_map(|note| wrap(note, TEXT_SIZE, width))
+                .collect();
+            let fits = ((top - MARGIN) / (TEXT_SIZE * LEADING)).max(0.0) as usize;
+            for (i, line) in lines.iter().take(fits).enumerate() {
+                top -= TEXT_SIZE * LEADING;
+                let line = if i + 1 == fits && lines.len() > fits {
+                    "..."
+                } else {
+                    line.as_str()
+                };
+                text(&mut content, REGULAR, TEXT_SIZE, (MARGIN, top), line);
+            }
+        }
+
+        let content_id = self.next_id();
+        let page_id = self.next_id();
+        self.pdf.stream(content_id, &content.finish());
+        let mut page = self.pdf.page(page_id);
+        page.media_box(Rect::new(0.0, 0.0, page_width, page_height));
+        page.parent(self.page_tree);
+        page.contents(content_id);
+        let mut resources = page.resources();
+        let mut fonts = resources.fonts();
+        fonts.pair(REGULAR, self.fonts.0);
+        fonts.pair(BOLD, self.fonts.1);
+        fonts.finish();
+        let mut objects = resources.x_objects();
+        for (i, image) in images.iter().enumerate() {
+            objects.pair(Name(image_name(i).as_bytes()), *image);
+        }
+        objects.finish();
+        resources.finish();
+        page.finish();
+        self.pages.push(page_id);
+        Ok(())
+    }
+
+    /// Adds `panel` as an image `width` points wide, at `DPI`.
+    fn image(&mut self, rasterizer: &Rasterizer, panel: &Panel, width: f32) -> Result<Ref, String> {
+        let scale = width / 72.0 * DPI / panel.size.0;
+        let pixmap = rasterizer.pixmap(&panel.svg, scale)?;
+        // Premultiplied colors over a white page
+        let rgb: Vec<u8> = pixmap
+            .pixels()
+            .iter()
+            .flat_map(|pixel| {
+                let paper = 255 - pixel.alpha();
+                [
+                    pixel.red() + paper,
+                    pixel.green() + paper,
+                    pixel.blue() + paper,
+                ]
+            })
+            .collect();
+        let data = miniz_oxide::deflate::compress_to_vec_zlib(&rgb, 6);
+        let id = self.next_id();
+        let mut image = self.pdf.image_xobject(id, &data);
+        image.filter(Filter::FlateDecode);
+        image.width(pixmap.width() as i32);
+        image.height(pixmap.height() as i32);
+        image.color_space().device_rgb();
+        image.bits_per_component(8);
+        image.finish();
+        Ok(id)
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        let catalog = self.next_id();
+        self.pdf.catalog(catalog).pages(self.page_tree);
+        self.pdf
+            .pages(self.page_tree)
+            .kids(self.pages.iter().copied())
+            .count(self.pages.len() as i32);
+        for (id, font) in [
+            (self.fonts.0, "Helvetica"),
+            (self.fonts.1, "Helvetica-Bold"),
+        ] {
+            self.pdf
+                .type1_font(id)
+                .base_font(Name(font.as_bytes()))
+                .encoding_predefined(Name(b"WinAnsiEncoding"));
+        }
+        self.pdf.finish()
+    }
+}
+
+fn image_name(index: usize) -> String {
+    format!("Im{}", index)
+}
+
+/// Draws image number `index` of the page with its lower-left corner at `corner`.
+fn place(content: &mut Content, index: usize, corner: (f32, f32), size: (f32, f32)) {
+    content.save_state();
+    content.transform([size.0, 0.0, 0.0, size.1, corner.0, corner.1]);
+    content.x_object(Name(image_name(index).as_bytes()));
+    content.restore_state();
+}
+
+/// One line of text with its baseline starting at `at`.
+fn text(content: &mut Content, font: Name, size: f32, at: (f32, f32), line: &str) {
+    content.begin_text();
+    content.set_font(font, size);
+    content.next_line(at.0, at.1);
+    content.show(Str(&win_ansi(line)));
+    content.end_text();
+}
+
+/// Breaks `text` into lines about `width` points long.
+/// Helvetica is narrower than this estimate for most text, so lines rarely overflow.
+fn wrap(text: &str, size: f32, width: f32) -> Vec<String> {
+    let max_chars = (width / (size * 0.55)) as usize;
+    let mut lines = Vec::new();
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        for word in paragraph.split_whitespace() {
+            if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > max_chars {
+                lines.push(std::mem::take(&mut line));
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(word);
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+/// `text` in the encoding of the standard PDF fonts; other characters become `?`.
+fn win_ansi(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|c| match c {
+            ' '..='~' | '\u{a0}'..='\u{ff}' => c as u8,
+            '‘' => 0x91,
+            '’' => 0x92,
+            '“' => 0x93,
+            '”' => 0x94,
+            '•' => 0x95,
+            '–' => 0x96,
+            '—' => 0x97,
+            '…' => 0x85,
+            _ => b'?',
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_booklet_pages() {
+        let source = r#"
+            // Horns entry
+            players = { p1, p2 }
+            state = { baller = p1, position = { p1 = (0, 60), p2 = (90, -80) } }
+            phase 1 = { pass = { p1 -> p2 } }
+            // Clear out
+            phase 2 = { move = { p1 -> (-60, 0) } }
+        "#;
+        let play = Play::new(Path::new("plays/horns.playbook"), source.to_string());
+        assert_eq!(play.name, "horns");
+        assert_eq!(play.notes(), ["Horns entry", "Phase 2: Clear out"]);
+
+        let plays = [play, Play::new(Path::new("b.playbook"), source.to_string())];
+        let pdf = booklet(&plays, &Renderer::new(), Paper::A4, Layout::Grid).unwrap();
+        let pdf = String::from_utf8_lossy(&pdf);
+        assert!(pdf.starts_with("%PDF"));
+        assert_eq!(pdf.matches("/Type /Page\n").count(), 2);
+        // Two phases and the legend on each page
+        assert_eq!(pdf.matches("/Subtype /Image").count(), 6);
+        assert!(pdf.contains("(Phase 2: Clear out)"));
+
+        let named = format!(
+            "meta = {{ name = \"Horns Flare\", call = \"41\", tags = [\"ATO\", \"horns\"], notes = \"Late clock\" }}\n{}",
+            source
+        );
+        let play = Play::new(Path::new("plays/horns.playbook"), named);
+        assert_eq!(play.name, "Horns Flare");
+        assert_eq!(
+            play.notes(),
+            [
+                "Call: 41",
+                "Tags: ATO, horns",
+                "Late clock",
+                "Horns entry",
+                "Phase 2: Clear out"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_manifest() {
+        let manifest = "# Offense\nhorns.playbook\n\n  press/  \n";
+        assert_eq!(
+            parse_manifest(manifest, Path::new("season")),
+            [
+                PathBuf::from("season/horns.playbook"),
+                PathBuf::from("season/press/")
+            ]
+        );
+        assert_eq!(wrap("a bb ccc", 10.0, 33.0), ["a bb", "ccc"]);
+        assert_eq!(win_ansi("café – 3×"), b"caf\xe9 \x96 3\xd7");
+    }
+}
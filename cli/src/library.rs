@@ -0,0 +1,319 @@
+//! Libraries of plays: an index of what each play is and does, searched from the command line.
+
+use crate::book::Play;
+use clap::ValueEnum;
+use playbook_lang_core::ast::{Meta, MoveKind, PassKind};
+use playbook_lang_core::import::FileLoader;
+use playbook_lang_core::{diagnostic, Renderer};
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+
+/// Kinds of actions, as written in playbooks.
+pub const ACTION_NAMES: &[&str] = &[
+    "move", "dribble", "cut", "screen", "pass", "handoff", "shot", "switch", "hedge", "help",
+    "trap", "closeout",
+];
+
+/// How a list of plays is written.
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum Listing {
+    /// One play per line: path, name and tags.
+    Text,
+    Json,
+    /// A static page with the diagram of every play.
+    Html,
+}
+
+/// What one play is and does.
+pub struct Entry {
+    pub path: PathBuf,
+    pub play: Play,
+    pub meta: Meta,
+    pub players: Vec<String>,
+    pub defense: Vec<String>,
+    pub phases: usize,
+    /// How many actions of each kind, in `ACTION_NAMES` order; kinds not used are left out.
+    pub actions: Vec<(&'static str, usize)>,
+}
+
+impl Entry {
+    /// Indexes a play that was already checked for errors.
+    pub fn new(path: &Path, source: String) -> Self {
+        let (playbook, _) = diagnostic::analyze_file(&source, path, &FileLoader);
+        let mut counts = vec![0; ACTION_NAMES.len()];
+        let mut count = |name: &str| {
+            if let Some(i) = ACTION_NAMES.iter().position(|n| *n == name) {
+                counts[i] += 1;
+            }
+        };
+        for action in playbook.phase_actions() {
+            for m in &action.moves {
+                count(match m.kind {
+                    MoveKind::Move => "move",
+                    MoveKind::Dribble => "dribble",
+                    MoveKind::Cut => "cut",
+                });
+            }
+            action.screens.iter().for_each(|_| count("screen"));
+            for pass in &action.passes {
+                count(match pass.kind {
+                    PassKind::Pass => "pass",
+                    PassKind::Handoff => "handoff",
+                });
+            }
+            action.shots.iter().for_each(|_| count("shot"));
+            for defense in &action.defense {
+                count(defense.kind.name());
+            }
+        }
+        let actions = ACTION_NAMES
+            .iter()
+            .zip(counts)
+            .filter(|(_, count)| *count > 0)
+            .map(|(name, count)| (*name, count))
+            .collect();
+
+        Self {
+            path: path.to_path_buf(),
+            meta: playbook.meta.unwrap_or_default(),
+            players: playbook.players,
+            defense: playbook.defense,
+            phases: playbook.phases.len().max(1),
+            actions,
+            play: Play::new(path, source),
+        }
+    }
+
+    pub fn has(&self, action: &str) -> bool {
+        self.actions.iter().any(|(name, _)| *name == action)
+    }
+
+    fn to_json(&self) -> Value {
+        let actions: serde_json::Map<String, Value> = self
+            .actions
+            .iter()
+            .map(|(name, count)| (name.to_string(), json!(count)))
+            .collect();
+        json!({
+            "path": self.path.display().to_string(),
+            "name": self.play.name,
+            "call": self.meta.call,
+            "category": self.meta.category,
+            "formation": self.meta.formation,
+            "tags": self.meta.tags,
+            "notes": self.meta.notes,
+            "players": self.players,
+            "defense": self.defense,
+            "phases": self.phases,
+            "actions": actions,
+        })
+    }
+}
+
+/// Plays to keep. Every condition given must hold; letters are compared without case.
+#[derive(Default)]
+pub struct Query {
+    /// Tags the play must all have.
+    pub tags: Vec<String>,
+    /// Kinds of actions the play must all use.
+    pub has: Vec<String>,
+    pub formation: Option<String>,
+    pub category: Option<String>,
+    /// Found in the name, call or notes.
+    pub text: Option<String>,
+}
+
+impl Query {
+    pub fn matches(&self, entry: &Entry) -> bool {
+        let meta = &entry.meta;
+        let same = |wanted: &Option<String>, value: &Option<String>| {
+            wanted.as_ref().is_none_or(|wanted| {
+                value
+                    .as_ref()
+                    .is_some_and(|value| value.eq_ignore_ascii_case(wanted))
+            })
+        };
+        self.tags
+            .iter()
+            .all(|tag| meta.tags.iter().any(|t| t.eq_ignore_ascii_case(tag)))
+            && self.has.iter().all(|action| entry.has(action))
+            && same(&self.formation, &meta.formation)
+            && same(&self.category, &meta.category)
+            && self.text.as_ref().is_none_or(|text| {
+                let text = text.to_lowercase();
+                [
+                    Some(&entry.play.name),
+                    meta.call.as_ref(),
+                    meta.notes.as_ref(),
+                ]
+                .into_iter()
+                .flatten()
+                .any(|field| field.to_lowercase().contains(&text))
+            })
+    }
+}
+
+/// `entries` written as `listing`; the HTML gallery draws each play with `renderer`.
+pub fn list(entries: &[Entry], listing: Listing, renderer: &Renderer) -> String {
+    match listing {
+        Listing::Text => entries
+            .iter()
+            .map(|entry| {
+                let mut line = format!("{}\t{}", entry.path.display(), entry.play.name);
+                if !entry.meta.tags.is_empty() {
+                    line.push_str(&format!("\t[{}]", entry.meta.tags.join(", ")));
+                }
+                line + "\n"
+            })
+            .collect(),
+        Listing::Json => {
+            let entries: Vec<Value> = entries.iter().map(Entry::to_json).collect();
+            serde_json::to_string_pretty(&entries).expect("JSON values serialize") + "\n"
+        }
+        Listing::Html => gallery(entries, renderer),
+    }
+}
+
+/// A page of figures, one per play, with a box to filter them by name, tag or formation.
+fn gallery(entries: &[Entry], renderer: &Renderer) -> String {
+    let mut html = String::from(concat!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Playbook</title>\n",
+        "<style>\n",
+        "body { font-family: sans-serif; margin: 2em; }\n",
+        "main { display: grid; grid-template-columns: repeat(auto-fill, minmax(320px, 1fr)); gap: 1.5em; }\n",
+        "figure { margin: 0; }\n",
+        "figure svg { width: 100%; height: auto; }\n",
+        "figcaption small { display: block; color: gray; }\n",
+        "</style>\n</head>\n<body>\n<h1>Playbook</h1>\n",
+        "<input type=\"search\" placeholder=\"Filter\" oninput=\"for (const f of document.querySelectorAll('figure')) ",
+        "f.hidden = !f.dataset.search.includes(this.value.toLowerCase())\">\n<main>\n",
+    ));
+    for entry in entries {
+        let meta = &entry.meta;
+        let search = [
+            Some(&entry.play.name),
+            meta.formation.as_ref(),
+            meta.category.as_ref(),
+        ]
+        .into_iter()
+        .flatten()
+        .chain(&meta.tags)
+        .map(|text| text.to_lowercase())
+        .collect::<Vec<_>>()
+        .join(" ");
+        let svg = renderer
+            .clone()
+            .with_file(&entry.path)
+            .render(&entry.play.source)
+            .expect("Playbook was already checked");
+        html.push_str(&format!(
+            "<figure data-search=\"{}\">\n{}\n<figcaption><strong>{}</strong>",
+            escape_html(&search),
+            svg,
+            escape_html(&entry.play.name)
+        ));
+        let details: Vec<String> = [
+            meta.category.clone(),
+            meta.formation.clone(),
+            meta.call.as_ref().map(|call| format!("Call: {}", call)),
+            (!meta.tags.is_empty()).then(|| meta.tags.join(", ")),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        if !details.is_empty() {
+            html.push_str(&format!(
+                "<small>{}</small>",
+                escape_html(&details.join(" · "))
+            ));
+        }
+        html.push_str(&format!(
+            "<small>{}</small></figcaption>\n</figure>\n",
+            escape_html(&entry.path.display().to_string())
+        ));
+    }
+    html.push_str("</main>\n</body>\n</html>\n");
+    html
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries() -> Vec<Entry> {
+        let horns = r#"
+            meta = { name = "Horns Flare", formation = "Horns", tags = ["ATO", "horns"] }
+            players = { p1, p2, p4 }
+            state = { baller = p1, position = { p1 = (0, 60), p2 = (-30, 20), p4 = (-50, -10) } }
+            phase 1 = { screen = { p4 -> p2 }, cut = { p2 -> left_corner } }
+            phase 2 = { pass = { p1 -> p2 }, shot = { p2 } }
+        "#;
+        let iso = r#"
+            players = { p1 }
+            state = { baller = p1, position = { p1 = (0, 60) } }
+            action = { dribble = { p1 -> (30, 20) } }
+        "#;
+        vec![
+            Entry::new(Path::new("ato/horns.playbook"), horns.to_string()),
+            Entry::new(Path::new("iso.playbook"), iso.to_string()),
+        ]
+    }
+
+    #[test]
+    fn test_index() {
+        let entries = entries();
+        assert_eq!(
+            entries[0].actions,
+            [("cut", 1), ("screen", 1), ("pass", 1), ("shot", 1)]
+        );
+        assert_eq!(entries[1].play.name, "iso");
+
+        let json: Value =
+            serde_json::from_str(&list(&entries, Listing::Json, &Renderer::new())).unwrap();
+        assert_eq!(json[0]["name"], "Horns Flare");
+        assert_eq!(json[0]["phases"], 2);
+        assert_eq!(json[0]["players"], json!(["p1", "p2", "p4"]));
+        assert_eq!(json[1]["actions"], json!({ "dribble": 1 }));
+        assert_eq!(json[1]["formation"], Value::Null);
+
+        let html = list(&entries, Listing::Html, &Renderer::new());
+        assert_eq!(html.matches("<figure").count(), 2);
+        assert!(html.contains("data-search=\"horns flare horns ato horns\""));
+    }
+
+    #[test]
+    fn test_search() {
+        let entries = entries();
+        let found = |query: Query| -> Vec<String> {
+            entries
+                .iter()
+                .filter(|entry| query.matches(entry))
+                .map(|entry| entry.play.name.clone())
+                .collect()
+        };
+        let query = Query {
+            tags: vec!["HORNS".to_string()],
+            has: vec!["screen".to_string()],
+            ..Query::default()
+        };
+        assert_eq!(found(query), ["Horns Flare"]);
+        let query = Query {
+            has: vec!["screen".to_string(), "dribble".to_string()],
+            ..Query::default()
+        };
+        assert!(found(query).is_empty());
+        let query = Query {
+            text: Some("flare".to_string()),
+            ..Query::default()
+        };
+        assert_eq!(found(query), ["Horns Flare"]);
+        assert_eq!(found(Query::default()).len(), 2);
+    }
+}
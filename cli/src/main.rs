@@ -1,43 +1,654 @@
-use clap::Parser;
-use playbook_lang_core::Renderer;
+mod book;
+mod library;
+mod raster;
+
+use book::{Layout, Paper, Play};
+use clap::{Parser, Subcommand, ValueEnum};
+use library::{Entry, Listing, Query, ACTION_NAMES};
+use playbook_lang_core::court::{CourtSpec, CourtView, COURT_NAMES, VIEW_NAMES};
+use playbook_lang_core::diagnostic::Diagnostic;
+use playbook_lang_core::import::FileLoader;
+use playbook_lang_core::renderer::theme::THEME_NAMES;
+use playbook_lang_core::renderer::viewport::REGION_NAMES;
+use playbook_lang_core::renderer::Region;
+use playbook_lang_core::{diagnostic, formatter, Renderer, Theme};
+use raster::Rasterizer;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 #[derive(Parser)]
-#[command(author, version, about = "Convert playbook-lang files to SVG", long_about = None)]
+#[command(author, version, about = "Convert playbook-lang files to SVG, PNG or GIF", long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Input .playbook file
-    input: PathBuf,
+    #[arg(required = true)]
+    input: Option<PathBuf>,
 
-    /// Output .svg file
+    /// Output .svg, .png or .gif file
     #[arg(short, long)]
     output: Option<PathBuf>,
+
+    /// Output format (default: from the output extension, else svg)
+    #[arg(long, value_enum)]
+    format: Option<Format>,
+
+    /// Multiplies the pixel size of PNG and GIF output
+    #[arg(long)]
+    scale: Option<f32>,
+
+    /// Resolution of PNG and GIF output; 96 dpi is the SVG pixel size
+    #[arg(long, conflicts_with = "scale")]
+    dpi: Option<f32>,
+
+    /// Write one file per phase (<output>.1.svg, <output>.2.svg, ...)
+    #[arg(long)]
+    phases: bool,
+
+    /// Write an animation where players move and the ball travels:
+    /// an animated SVG or GIF, or numbered PNG frames (<output>.001.png, ...)
+    #[arg(long, conflicts_with = "phases")]
+    animate: bool,
+
+    /// Length of one loop of the animation, in seconds
+    #[arg(long, default_value_t = 4.0, requires = "animate", value_parser = parse_positive, allow_hyphen_values = true)]
+    duration: f64,
+
+    /// Frames per second of GIF and PNG animations
+    #[arg(long, default_value_t = 12.0, requires = "animate", value_parser = parse_positive, allow_hyphen_values = true)]
+    fps: f64,
+
+    #[command(flatten)]
+    render: RenderArgs,
+}
+
+/// How plays are drawn, for every command that draws them.
+#[derive(clap::Args)]
+struct RenderArgs {
+    /// Court for playbooks without a `court = ...` line
+    #[arg(long, value_parser = clap::builder::PossibleValuesParser::new(COURT_NAMES))]
+    court: Option<String>,
+
+    /// How much of the court to draw for playbooks without a `view = ...` line
+    #[arg(long, value_parser = clap::builder::PossibleValuesParser::new(VIEW_NAMES))]
+    view: Option<String>,
+
+    /// Built-in theme (classic, print, dark, team) or a .toml / .json theme file
+    #[arg(long)]
+    theme: Option<String>,
+
+    /// Pixel width of each phase (default 500)
+    #[arg(long, value_parser = parse_pixels, allow_hyphen_values = true)]
+    width: Option<u32>,
+
+    /// Pixel height of each phase (default: follows the picture)
+    #[arg(long, requires = "width", value_parser = parse_pixels, allow_hyphen_values = true)]
+    height: Option<u32>,
+
+    /// Room around the picture, in court drawing units
+    #[arg(long, value_parser = parse_non_negative, allow_hyphen_values = true)]
+    padding: Option<f64>,
+
+    /// Show only the players and their actions
+    #[arg(long)]
+    crop: bool,
+
+    /// Show only part of the court: left, right, paint, frontcourt, backcourt or x1,y1,x2,y2
+    #[arg(long, conflicts_with = "crop", value_parser = parse_region, allow_hyphen_values = true)]
+    zoom: Option<Region>,
+}
+
+impl RenderArgs {
+    fn renderer(&self) -> Renderer {
+        let mut renderer = Renderer::new();
+        if let Some(court) = self.court.as_deref().and_then(CourtSpec::from_name) {
+            renderer = renderer.with_court(court);
+        }
+        if let Some(view) = self.view.as_deref().and_then(CourtView::from_name) {
+            renderer = renderer.with_view(view);
+        }
+        match (self.width, self.height) {
+            (Some(width), Some(height)) => renderer = renderer.with_size(width, height),
+            (Some(width), None) => renderer = renderer.with_width(width),
+            _ => {}
+        }
+        if let Some(padding) = self.padding {
+            renderer = renderer.with_padding(padding);
+        }
+        if self.crop {
+            renderer = renderer.with_auto_crop();
+        }
+        if let Some(region) = self.zoom {
+            renderer = renderer.with_zoom(region);
+        }
+        if let Some(theme) = &self.theme {
+            match load_theme(theme) {
+                Ok(theme) => renderer = renderer.with_theme(theme),
+                Err(e) => {
+                    eprintln!("Theme Error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        renderer
+    }
+}
+
+fn parse_region(region: &str) -> Result<Region, String> {
+    Region::parse(region).ok_or_else(|| {
+        format!(
+            "expected {} or x1,y1,x2,y2 corners of a rectangle",
+            REGION_NAMES.join(", ")
+        )
+    })
+}
+
+fn parse_positive(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(number) if number > 0.0 && number.is_finite() => Ok(number),
+        Ok(_) => Err("must be greater than 0".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn parse_non_negative(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(number) if number >= 0.0 && number.is_finite() => Ok(number),
+        Ok(_) => Err("must be 0 or more".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn parse_pixels(value: &str) -> Result<u32, String> {
+    match value.parse::<i64>() {
+        Ok(number) if number > 0 => u32::try_from(number).map_err(|e| e.to_string()),
+        Ok(_) => Err("must be greater than 0".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Rewrite .playbook files in the canonical style
+    Fmt {
+        /// Files or directories (searched recursively for .playbook files)
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+
+        /// Don't write anything; exit with 1 if a file is not formatted
+        #[arg(long)]
+        check: bool,
+    },
+    /// Print plays into a PDF booklet, one play per page
+    Book {
+        /// .playbook files, directories (searched recursively) or manifests listing plays line by line
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+
+        /// Output .pdf file
+        #[arg(short, long, default_value = "playbook.pdf")]
+        output: PathBuf,
+
+        #[arg(long, value_enum, default_value_t = Paper::A4)]
+        paper: Paper,
+
+        /// How the phases of a play are laid out on its page
+        #[arg(long, value_enum, default_value_t = Layout::Strip)]
+        layout: Layout,
+
+        #[command(flatten)]
+        render: RenderArgs,
+    },
+    /// List the name, tags, players and actions of every play, as JSON or an HTML gallery
+    Index {
+        /// Files or directories (searched recursively for .playbook files)
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+
+        /// Output file (default: standard output)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        #[arg(long, value_enum, default_value_t = Listing::Json)]
+        format: Listing,
+
+        #[command(flatten)]
+        render: RenderArgs,
+    },
+    /// Find plays by tag, action, formation, category or text
+    Search {
+        /// Files or directories (searched recursively for .playbook files)
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+
+        /// Plays with this tag (repeat for several)
+        #[arg(long)]
+        tag: Vec<String>,
+
+        /// Plays using this kind of action (repeat for several)
+        #[arg(long, value_parser = clap::builder::PossibleValuesParser::new(ACTION_NAMES))]
+        has: Vec<String>,
+
+        #[arg(long)]
+        formation: Option<String>,
+
+        #[arg(long)]
+        category: Option<String>,
+
+        /// Plays whose name, call or notes contain this text
+        #[arg(long)]
+        text: Option<String>,
+
+        /// Output file (default: standard output)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        #[arg(long, value_enum, default_value_t = Listing::Text)]
+        format: Listing,
+
+        #[command(flatten)]
+        render: RenderArgs,
+    },
 }
 
 fn main() {
     let args = Args::parse();
 
-    let input_content = fs::read_to_string(&args.input).expect("Failed to read input file");
-    let renderer = Renderer::new();
-    let result = renderer.render(&input_content);
+    match args.command {
+        Some(Command::Fmt { paths, check }) => fmt(&paths, check),
+        Some(Command::Book {
+            paths,
+            output,
+            paper,
+            layout,
+            render,
+        }) => book(
+            &paths,
+            &output,
+            paper,
+            layout,
+            // The page title already shows the name
+            &render.renderer().without_caption(),
+        ),
+        Some(Command::Index {
+            paths,
+            output,
+            format,
+            render,
+        }) => library(
+            &paths,
+            &Query::default(),
+            format,
+            output.as_deref(),
+            // Figures have their own captions
+            &render.renderer().without_caption(),
+        ),
+        Some(Command::Search {
+            paths,
+            tag,
+            has,
+            formation,
+            category,
+            text,
+            output,
+            format,
+            render,
+        }) => {
+            let query = Query {
+                tags: tag,
+                has,
+                formation,
+                category,
+                text,
+            };
+            library(
+                &paths,
+                &query,
+                format,
+                output.as_deref(),
+                &render.renderer().without_caption(),
+            )
+        }
+        None => {
+            let mode = if args.animate {
+                Mode::Animated {
+                    duration: args.duration,
+                    fps: args.fps,
+                }
+            } else if args.phases {
+                Mode::Phases
+            } else {
+                Mode::Static
+            };
+            let renderer = args.render.renderer();
+            let output = Output {
+                path: args.output,
+                format: args.format,
+                scale: args.dpi.map_or(args.scale.unwrap_or(1.0), |dpi| dpi / 96.0),
+            };
+            convert(
+                args.input.expect("input is required"),
+                output,
+                mode,
+                &renderer,
+            )
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum Format {
+    Svg,
+    Png,
+    Gif,
+}
+
+impl Format {
+    fn of(path: &Path) -> Option<Format> {
+        match path.extension()?.to_str()? {
+            "svg" => Some(Format::Svg),
+            "png" => Some(Format::Png),
+            "gif" => Some(Format::Gif),
+            _ => None,
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Format::Svg => "svg",
+            Format::Png => "png",
+            Format::Gif => "gif",
+        }
+    }
+}
+
+/// Where and how the drawing is written.
+struct Output {
+    path: Option<PathBuf>,
+    format: Option<Format>,
+    /// PNG and GIF pixels per SVG pixel
+    scale: f32,
+}
+
+enum Mode {
+    Static,
+    Phases,
+    /// Loop duration in seconds
+    Animated {
+        duration: f64,
+        fps: f64,
+    },
+}
+
+fn convert(input: PathBuf, output: Output, mode: Mode, renderer: &Renderer) {
+    let input_content = fs::read_to_string(&input).expect("Failed to read input file");
+    let format = output
+        .format
+        .or_else(|| output.path.as_deref().and_then(Format::of))
+        .unwrap_or(Format::Svg);
+    let output_path = output.path.clone().unwrap_or_else(|| {
+        let mut path = input.clone();
+        path.set_extension(format.extension());
+        path
+    });
+
+    let rasterizer = (format != Format::Svg).then(Rasterizer::new);
+
+    let renderer = &renderer.clone().with_file(&input);
+    let (svg, diagnostics) = renderer.render_partial(&input_content);
+    if diagnostics.iter().any(|d| d.is_error()) {
+        eprintln!("Compile Error:");
+        report(&diagnostics, &input_content, &input);
+        std::process::exit(1);
+    }
+
+    if let Mode::Animated { duration, fps } = mode {
+        match &rasterizer {
+            None => {
+                let svg = renderer
+                    .render_animated(&input_content, duration)
+                    .expect("Playbook was already checked");
+                fs::write(&output_path, svg).expect("Failed to write output file");
+                println!("Successfully converted {:?} to {:?}", input, output_path);
+            }
+            Some(rasterizer) => {
+                let count = ((duration * fps).round() as usize).max(2);
+                let frames = renderer
+                    .render_frames(&input_content, count)
+                    .expect("Playbook was already checked");
+                if format == Format::Gif {
+                    // In hundredths of a second; 0 would make viewers pick their own speed
+                    let delay = (100.0 / fps).round().clamp(1.0, u16::MAX as f64) as u16;
+                    let gif = rasterizer
+                        .gif(&frames, output.scale, delay)
+                        .unwrap_or_else(|e| {
+                            eprintln!("GIF Error: {}", e);
+                            std::process::exit(1);
+                        });
+                    fs::write(&output_path, gif).expect("Failed to write output file");
+                    println!("Successfully converted {:?} to {:?}", input, output_path);
+                } else {
+                    // Numbered for ffmpeg: -i play.%03d.png
+                    let digits = count.to_string().len().max(3);
+                    for (i, svg) in frames.iter().enumerate() {
+                        let mut frame_path = output_path.clone();
+                        frame_path.set_extension(format!("{:0digits$}.png", i + 1));
+                        write(&frame_path, svg, Some(rasterizer), output.scale);
+                    }
+                    println!(
+                        "Successfully converted {:?} to {} frames {:?}, ...",
+                        input,
+                        count,
+                        output_path.with_extension(format!("{:0digits$}.png", 1))
+                    );
+                }
+            }
+        }
+        return;
+    }
 
-    match result {
-        Ok(svg) => {
-            let output_path = args.output.unwrap_or_else(|| {
-                let mut path = args.input.clone();
-                path.set_extension("svg");
-                path
-            });
+    if format == Format::Gif {
+        eprintln!("GIF output is an animation: add --animate");
+        std::process::exit(1);
+    }
+
+    if let Mode::Phases = mode {
+        let svgs = renderer
+            .render_phases(&input_content)
+            .expect("Playbook was already checked");
+        for (i, svg) in svgs.iter().enumerate() {
+            let mut phase_path = output_path.clone();
+            phase_path.set_extension(format!("{}.{}", i + 1, format.extension()));
+            write(&phase_path, svg, rasterizer.as_ref(), output.scale);
+            println!("Successfully converted {:?} to {:?}", input, phase_path);
+        }
+        return;
+    }
+
+    write(&output_path, &svg, rasterizer.as_ref(), output.scale);
+    println!("Successfully converted {:?} to {:?}", input, output_path);
+}
+
+/// Writes `svg` as is, or as a PNG at `scale` when there is a rasterizer.
+fn write(path: &Path, svg: &str, rasterizer: Option<&Rasterizer>, scale: f32) {
+    let content = match rasterizer {
+        None => svg.as_bytes().to_vec(),
+        Some(rasterizer) => rasterizer.png(svg, scale).unwrap_or_else(|e| {
+            eprintln!("PNG Error: {}", e);
+            std::process::exit(1);
+        }),
+    };
+    fs::write(path, content).expect("Failed to write output file");
+}
+
+/// A built-in theme by name, or a theme file.
+fn load_theme(theme: &str) -> Result<Theme, String> {
+    if let Some(theme) = Theme::from_name(theme) {
+        return Ok(theme);
+    }
+    let path = Path::new(theme);
+    if !path.exists() {
+        return Err(format!(
+            "'{}' is neither a theme ({}) nor a file",
+            theme,
+            THEME_NAMES.join(", ")
+        ));
+    }
+    let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    if path.extension().is_some_and(|ext| ext == "toml") {
+        let value: serde_json::Value = toml::from_str(&content).map_err(|e| e.to_string())?;
+        Theme::from_value(value)
+    } else {
+        Theme::from_json(&content)
+    }
+}
+
+fn fmt(paths: &[PathBuf], check: bool) {
+    let mut files = Vec::new();
+    for path in paths {
+        collect_playbooks(path, &mut files);
+    }
 
-            fs::write(&output_path, svg).expect("Failed to write output file");
-            println!(
-                "Successfully converted {:?} to {:?}",
-                args.input, output_path
-            );
+    let mut failed = false;
+    for file in &files {
+        let content = fs::read_to_string(file).expect("Failed to read input file");
+        let formatted = match formatter::format(&content) {
+            Ok(formatted) => formatted,
+            Err(diagnostics) => {
+                report(&diagnostics, &content, file);
+                failed = true;
+                continue;
+            }
+        };
+        if formatted == content {
+            continue;
+        }
+        if check {
+            println!("Not formatted: {}", file.display());
+            failed = true;
+        } else {
+            fs::write(file, formatted).expect("Failed to write output file");
+            println!("Formatted {}", file.display());
+        }
+    }
+
+    if failed {
+        std::process::exit(1);
+    }
+}
+
+fn book(paths: &[PathBuf], output: &Path, paper: Paper, layout: Layout, renderer: &Renderer) {
+    let mut files = Vec::new();
+    for path in paths {
+        if path.is_file() && path.extension().is_none_or(|ext| ext != "playbook") {
+            let manifest = fs::read_to_string(path).expect("Failed to read manifest");
+            let dir = path.parent().unwrap_or(Path::new(""));
+            for entry in book::parse_manifest(&manifest, dir) {
+                collect_playbooks(&entry, &mut files);
+            }
+        } else {
+            collect_playbooks(path, &mut files);
+        }
+    }
+
+    let mut plays = Vec::new();
+    let mut failed = false;
+    for file in &files {
+        let source = fs::read_to_string(file).expect("Failed to read input file");
+        let (_, diagnostics) = diagnostic::analyze_file(&source, file, &FileLoader);
+        if diagnostics.iter().any(|d| d.is_error()) {
+            report(&diagnostics, &source, file);
+            failed = true;
+            continue;
+        }
+        plays.push(Play::new(file, source));
+    }
+    if failed {
+        std::process::exit(1);
+    }
+
+    match book::booklet(&plays, renderer, paper, layout) {
+        Ok(pdf) => {
+            fs::write(output, pdf).expect("Failed to write output file");
+            println!("Printed {} plays to {:?}", plays.len(), output);
         }
         Err(e) => {
-            eprintln!("Compile Error:\n{}", e);
+            eprintln!("PDF Error: {}", e);
             std::process::exit(1);
         }
     }
 }
+
+/// Writes the plays under `paths` that match `query`. Files with errors are reported and left out.
+fn library(
+    paths: &[PathBuf],
+    query: &Query,
+    listing: Listing,
+    output: Option<&Path>,
+    renderer: &Renderer,
+) {
+    let mut files = Vec::new();
+    for path in paths {
+        collect_playbooks(path, &mut files);
+    }
+
+    let mut entries = Vec::new();
+    for file in &files {
+        let source = fs::read_to_string(file).expect("Failed to read input file");
+        let (_, diagnostics) = diagnostic::analyze_file(&source, file, &FileLoader);
+        if diagnostics.iter().any(|d| d.is_error()) {
+            eprintln!("Skipped {}:", file.display());
+            report(&diagnostics, &source, file);
+            continue;
+        }
+        let entry = Entry::new(file, source);
+        if query.matches(&entry) {
+            entries.push(entry);
+        }
+    }
+
+    let content = library::list(&entries, listing, renderer);
+    match output {
+        Some(output) => {
+            fs::write(output, content).expect("Failed to write output file");
+            println!("Listed {} plays in {:?}", entries.len(), output);
+        }
+        None => print!("{}", content),
+    }
+}
+
+/// Prints the diagnostics of `file`, each with the source of the file it points into.
+fn report(diagnostics: &[Diagnostic], source: &str, file: &Path) {
+    let file_name = file.display().to_string();
+    for diagnostic in diagnostics {
+        let rendered = match &diagnostic.file {
+            Some(imported) => {
+                let source = fs::read_to_string(imported).unwrap_or_default();
+                diagnostic.render(&source, &file_name)
+            }
+            None => diagnostic.render(source, &file_name),
+        };
+        eprintln!("{}", rendered);
+    }
+}
+
+fn collect_playbooks(path: &Path, files: &mut Vec<PathBuf>) {
+    if !path.is_dir() {
+        files.push(path.to_path_buf());
+        return;
+    }
+    let mut entries: Vec<PathBuf> = fs::read_dir(path)
+        .expect("Failed to read directory")
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .collect();
+    entries.sort();
+    for entry in entries {
+        if entry.is_dir() {
+            collect_playbooks(&entry, files);
+        } else if entry.extension().is_some_and(|ext| ext == "playbook") {
+            files.push(entry);
+        }
+    }
+}
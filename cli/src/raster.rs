@@ -0,0 +1,158 @@
+//! PNG and GIF output: the SVG from the renderer, rasterized with resvg.
+
+use resvg::{tiny_skia, usvg};
+
+/// Fonts tried, in order, for text whose font is not installed.
+const FALLBACK_FONTS: &[&str] = &[
+    "Arial",
+    "Helvetica",
+    "Liberation Sans",
+    "DejaVu Sans",
+    "Noto Sans",
+];
+
+/// Turns SVG into pixels, with the system fonts loaded once.
+pub struct Rasterizer {
+    options: usvg::Options<'static>,
+}
+
+impl Rasterizer {
+    pub fn new() -> Self {
+        let mut options = usvg::Options::default();
+        let fonts = options.fontdb_mut();
+        fonts.load_system_fonts();
+        // resvg falls back to the serif family when a font is missing; make that a sans like Arial
+        let fallback = FALLBACK_FONTS
+            .iter()
+            .map(|name| name.to_string())
+            .find(|name| {
+                fonts
+                    .faces()
+                    .any(|face| face.families.iter().any(|(f, _)| f == name))
+            })
+            .or_else(|| {
+                let face = fonts.faces().next()?;
+                face.families.first().map(|(family, _)| family.clone())
+            });
+        if let Some(family) = fallback {
+            fonts.set_serif_family(family);
+        }
+        Self { options }
+    }
+
+    /// Pixel size of `svg`, as given by its `width` and `height`.
+    pub fn size(&self, svg: &str) -> Result<(f32, f32), String> {
+        let tree = usvg::Tree::from_str(svg, &self.options).map_err(|e| e.to_string())?;
+        Ok((tree.size().width(), tree.size().height()))
+    }
+
+    /// Rasterizes `svg` at `scale` times its pixel size.
+    pub fn pixmap(&self, svg: &str, scale: f32) -> Result<tiny_skia::Pixmap, String> {
+        let tree = usvg::Tree::from_str(svg, &self.options).map_err(|e| e.to_string())?;
+        let size = tree
+            .size()
+            .to_int_size()
+            .scale_by(scale)
+            .ok_or("Image is too small")?;
+        let mut pixmap =
+            tiny_skia::Pixmap::new(size.width(), size.height()).ok_or("Image is too large")?;
+        resvg::render(
+            &tree,
+            tiny_skia::Transform::from_scale(scale, scale),
+            &mut pixmap.as_mut(),
+        );
+        Ok(pixmap)
+    }
+
+    pub fn png(&self, svg: &str, scale: f32) -> Result<Vec<u8>, String> {
+        self.pixmap(svg, scale)?
+            .encode_png()
+            .map_err(|e| e.to_string())
+    }
+
+    /// A looping GIF of `frames`, each shown `delay` hundredths of a second.
+    pub fn gif(&self, frames: &[String], scale: f32, delay: u16) -> Result<Vec<u8>, String> {
+        let pixmaps = frames
+            .iter()
+            .map(|svg| self.pixmap(svg, scale))
+            .collect::<Result<Vec<_>, _>>()?;
+        let Some(first) = pixmaps.first() else {
+            return Err("No frames to encode".to_string());
+        };
+        let (Ok(width), Ok(height)) = (u16::try_from(first.width()), u16::try_from(first.height()))
+        else {
+            return Err("GIF frames are limited to 65535 px".to_string());
+        };
+
+        let mut bytes = Vec::new();
+        let mut encoder =
+            gif::Encoder::new(&mut bytes, width, height, &[]).map_err(|e| e.to_string())?;
+        encoder
+            .set_repeat(gif::Repeat::Infinite)
+            .map_err(|e| e.to_string())?;
+        for pixmap in &pixmaps {
+            let mut rgba: Vec<u8> = pixmap
+                .pixels()
+                .iter()
+                .flat_map(|pixel| {
+                    let color = pixel.demultiply();
+                    [color.red(), color.green(), color.blue(), color.alpha()]
+                })
+                .collect();
+            let mut frame = gif::Frame::from_rgba_speed(width, height, &mut rgba, 10);
+            frame.delay = delay;
+            encoder.write_frame(&frame).map_err(|e| e.to_string())?;
+        }
+        drop(encoder);
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_png_size_follows_scale() {
+        let svg = playbook_lang_core::Renderer::new()
+            .render("players = { p1 }\nstate = { position = { p1 = (0, 0) } }")
+            .expect("Failed to render");
+        let png = Rasterizer::new()
+            .png(&svg, 2.0)
+            .expect("Failed to rasterize");
+        assert!(png.starts_with(b"\x89PNG"));
+        // IHDR: width and height, big-endian, right after the signature and chunk header
+        assert_eq!(u32::from_be_bytes(png[16..20].try_into().unwrap()), 1000);
+        assert_eq!(u32::from_be_bytes(png[20..24].try_into().unwrap()), 1000);
+    }
+
+    #[test]
+    fn test_gif_loops_every_frame() {
+        let frames = playbook_lang_core::Renderer::new()
+            .with_width(100)
+            .render_frames(
+                "players = { p1 }\nstate = { position = { p1 = (0, 0) } }\naction = { move = { p1 -> (40, 0) } }",
+                3,
+            )
+            .expect("Failed to render");
+        let gif = Rasterizer::new()
+            .gif(&frames, 1.0, 8)
+            .expect("Failed to encode");
+
+        let mut decoder = gif::DecodeOptions::new()
+            .read_info(gif.as_slice())
+            .expect("Invalid GIF");
+        assert_eq!((decoder.width(), decoder.height()), (100, 100));
+        let mut delays = Vec::new();
+        while let Some(frame) = decoder.read_next_frame().expect("Invalid frame") {
+            delays.push(frame.delay);
+        }
+        assert_eq!(delays, [8, 8, 8]);
+
+        let wide = r#"<svg width="70000" height="1" xmlns="http://www.w3.org/2000/svg"></svg>"#;
+        assert_eq!(
+            Rasterizer::new().gif(&[wide.to_string()], 1.0, 8),
+            Err("GIF frames are limited to 65535 px".to_string())
+        );
+    }
+}
@@ -1,16 +1,178 @@
+use crate::court::{CourtSpec, CourtView, Units};
+use crate::lexer::Span;
+use serde::Serialize;
 use std::collections::HashMap;
 
-#[derive(Debug, PartialEq, Clone)]
+/// Keys of the `meta` section.
+pub const META_FIELDS: &[&str] = &["name", "call", "category", "formation", "tags", "notes"];
+
+/// Steps of a `transform` block.
+pub const TRANSFORM_NAMES: &[&str] = &["mirror", "rotate", "translate"];
+
+#[derive(Debug, PartialEq, Clone, Default)]
 pub struct Playbook {
+    /// `meta = { name = "Horns Flare", ... }`
+    pub meta: Option<Meta>,
+    /// `import "formations/horns.playbook"`, in file order.
+    pub imports: Vec<Import>,
+    /// `use formation horns`: players, defense and state inherited from an imported file.
+    pub formation: Option<FormationUse>,
     pub players: Vec<String>,
+    pub player_spans: Vec<Span>,
+    /// Defenders from `defense = { x1, ... }`.
+    pub defense: Vec<String>,
+    pub defense_spans: Vec<Span>,
+    /// `court = fiba`
+    pub court: Option<CourtSpec>,
+    /// `units = feet`: overrides the court's usual units.
+    pub units: Option<Units>,
+    /// `view = full`
+    pub view: Option<CourtView>,
+    /// `spots = { name = (x, y) }`: custom court locations, taking precedence over built-in ones.
+    pub spots: HashMap<String, (f64, f64)>,
+    pub spot_spans: HashMap<String, Span>,
+    /// `transform = { mirror }`: moves the whole play, positions included.
+    pub transform: Vec<Transform>,
+    /// `template pick_and_roll(handler, screener) = { ... }`, in file order.
+    pub templates: Vec<Template>,
     pub state: State,
     pub action: Action,
+    pub phases: Vec<Phase>,
+    /// Comments not attached to a player, phase or action (file header, section notes).
+    pub comments: Vec<Comment>,
+}
+
+/// What the play is, for titles and for finding it in a playbook.
+#[derive(Debug, PartialEq, Clone, Default, Serialize)]
+pub struct Meta {
+    pub name: Option<String>,
+    /// The signal called from the bench.
+    pub call: Option<String>,
+    /// ATO, SLOB, BLOB, half-court set, ...
+    pub category: Option<String>,
+    pub formation: Option<String>,
+    pub tags: Vec<String>,
+    pub notes: Option<String>,
+}
+
+/// `import "formations/horns.playbook"`: a file whose spots this play can use,
+/// and whose players and state are a formation named after the file.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Import {
+    /// As written, relative to the importing file.
+    pub path: String,
+    /// The path string.
+    pub span: Span,
+}
+
+/// `use formation horns`
+#[derive(Debug, PartialEq, Clone)]
+pub struct FormationUse {
+    pub name: String,
+    pub span: Span,
+}
+
+impl Playbook {
+    /// Action blocks in play order.
+    /// A file without `phase` sections is a single phase built from `action`.
+    pub fn phase_actions(&self) -> Vec<&Action> {
+        if self.phases.is_empty() {
+            vec![&self.action]
+        } else {
+            self.phases.iter().map(|phase| &phase.action).collect()
+        }
+    }
+
+    /// The court the play is drawn on, in the units its coordinates are written in.
+    pub fn court(&self) -> CourtSpec {
+        let court = self.court.unwrap_or(CourtSpec::CLASSIC);
+        match self.units {
+            Some(units) => court.with_units(units),
+            None => court,
+        }
+    }
+
+    /// A custom spot, or else a built-in one, in drawing coordinates.
+    pub fn spot(&self, name: &str) -> Option<(f64, f64)> {
+        let court = self.court();
+        match self.spots.get(name) {
+            Some(point) => Some(court.to_diagram(*point)),
+            None => court.spot(name),
+        }
+    }
+
+    /// Every point written in the file: positions, then waypoints phase by phase.
+    pub fn points(&self) -> Vec<&Point> {
+        let mut points: Vec<&Point> = self.state.positions.values().collect();
+        let actions = std::iter::once(&self.action).chain(self.phases.iter().map(|p| &p.action));
+        for action in actions {
+            for m in &action.moves {
+                for waypoint in &m.path {
+                    points.push(&waypoint.point);
+                    points.extend(&waypoint.via);
+                }
+            }
+        }
+        points
+    }
+}
+
+/// A location on the court. Anything but plain coordinates is evaluated by the IR generator.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Point {
+    /// `(x, y)`
+    Coord(f64, f64),
+    /// `p3` or `left_wing`: where a player is when the phase starts, otherwise a spot.
+    Name(String, Span),
+    /// `p3 + (10, 0)`
+    Offset(Box<Point>, (f64, f64)),
+    /// `toward(basket, 15)`: 15 units from the previous point of the path towards `basket`.
+    /// The span is the `toward` keyword.
+    Toward(Box<Point>, f64, Span),
+    /// `polar(30, 45)`: 30 units from the hoop, 45 degrees to the right of straight out.
+    Polar(f64, f64),
+}
+
+impl Point {
+    /// This point and the points it is built from.
+    pub fn parts(&self) -> Vec<&Point> {
+        let mut parts = vec![self];
+        match self {
+            Point::Offset(base, _) | Point::Toward(base, _, _) => parts.extend(base.parts()),
+            Point::Coord(..) | Point::Name(..) | Point::Polar(..) => {}
+        }
+        parts
+    }
+
+    /// Players and spots this point refers to.
+    pub fn names(&self) -> Vec<(&String, Span)> {
+        self.parts()
+            .into_iter()
+            .filter_map(|part| match part {
+                Point::Name(name, span) => Some((name, *span)),
+                _ => None,
+            })
+            .collect()
+    }
 }
 
 #[derive(Debug, PartialEq, Clone, Default)]
 pub struct State {
     pub baller: Option<String>,
-    pub positions: HashMap<String, (f64, f64)>,
+    pub positions: HashMap<String, Point>,
+    pub baller_span: Option<Span>,
+    pub position_spans: HashMap<String, Span>,
+    pub position_comments: HashMap<String, Vec<Comment>>,
+    /// `guards = { x1 -> p1 }`: who each defender starts on.
+    pub guards: Vec<Matchup>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Matchup {
+    pub defender: String,
+    pub player: String,
+    pub span: Span,
+    pub player_span: Span,
 }
 
 #[derive(Debug, PartialEq, Clone, Default)]
@@ -18,12 +180,89 @@ pub struct Action {
     pub moves: Vec<MoveAction>,
     pub screens: Vec<ScreenAction>,
     pub passes: Vec<PassAction>,
+    pub shots: Vec<ShotAction>,
+    pub defense: Vec<DefenseAction>,
+    /// `transform = { rotate = 90 }`: moves the court locations of this phase only.
+    pub transform: Vec<Transform>,
+    /// `pick_and_roll(p1, p5)`: templates whose actions are added to this block.
+    /// Emptied once they are expanded.
+    pub calls: Vec<TemplateCall>,
+}
+
+/// A step of `transform = { mirror, rotate = 90, translate = (10, 0) }`, applied in order
+/// to court locations: coordinates, spots and `polar` points.
+/// Names of players stand for where the player is, so they are not moved again.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Transform {
+    /// Left and right swapped: Horns Left becomes Horns Right.
+    Mirror,
+    /// Degrees around the hoop, positive to the right like `polar` angles.
+    Rotate(f64),
+    Translate(f64, f64),
+}
+
+/// `template pick_and_roll(handler, screener) = { ... }`: actions written once
+/// for whichever players a phase calls them with.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Template {
+    pub name: String,
+    pub params: Vec<String>,
+    pub action: Action,
+    /// The name.
+    pub span: Span,
+}
+
+/// `pick_and_roll(p1, p5)`: the players (or spots) standing in for the template's parameters.
+#[derive(Debug, PartialEq, Clone)]
+pub struct TemplateCall {
+    pub name: String,
+    pub args: Vec<(String, Span)>,
+    /// The name.
+    pub span: Span,
 }
 
+#[derive(Debug, PartialEq, Clone)]
+pub struct Phase {
+    pub number: u32,
+    pub action: Action,
+    pub span: Span,
+    pub comments: Vec<Comment>,
+}
+
+/// `move`, `dribble` and `cut` entries, in source order.
 #[derive(Debug, PartialEq, Clone)]
 pub struct MoveAction {
+    pub kind: MoveKind,
     pub player: String,
-    pub target: (f64, f64),
+    /// `p2 -> (30, 0) -> (70, 20)`: every point after the start, the last one being the target.
+    pub path: Vec<Waypoint>,
+    /// `curve`: smooth the corners between waypoints.
+    pub curve: bool,
+    pub span: Span,
+    pub comments: Vec<Comment>,
+}
+
+impl MoveAction {
+    /// Where the movement ends.
+    pub fn target(&self) -> Option<&Point> {
+        self.path.last().map(|waypoint| &waypoint.point)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Waypoint {
+    pub point: Point,
+    /// `-> (70, 20) via (30, 0)`: bend the segment towards this control point.
+    pub via: Option<Point>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum MoveKind {
+    Move,
+    /// The ball handler moving with the ball.
+    Dribble,
+    /// An off-ball cut.
+    Cut,
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -31,13 +270,79 @@ pub struct ScreenAction {
     pub player: String,
     pub target: String,
     pub timing: Timing,
+    pub span: Span,
+    pub target_span: Span,
+    pub comments: Vec<Comment>,
 }
 
+/// `pass` and `handoff` entries, in the order the ball moves.
 #[derive(Debug, PartialEq, Clone)]
 pub struct PassAction {
+    pub kind: PassKind,
     pub from: String,
     pub to: String,
     pub timing: Timing,
+    pub span: Span,
+    pub to_span: Span,
+    pub comments: Vec<Comment>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum PassKind {
+    Pass,
+    Handoff,
+}
+
+/// `shot = { p2 }`: the holder shoots once the ball reaches them.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ShotAction {
+    pub player: String,
+    pub timing: Timing,
+    pub span: Span,
+    pub comments: Vec<Comment>,
+}
+
+/// `hedge = { x5 -> p1 }`: a defender reacting to `target`.
+/// For a switch the target is the other defender; they swap matchups.
+#[derive(Debug, PartialEq, Clone)]
+pub struct DefenseAction {
+    pub kind: DefenseKind,
+    pub defender: String,
+    pub target: String,
+    pub timing: Timing,
+    pub span: Span,
+    pub target_span: Span,
+    pub comments: Vec<Comment>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum DefenseKind {
+    Switch,
+    Hedge,
+    Help,
+    Trap,
+    Closeout,
+}
+
+impl DefenseKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            DefenseKind::Switch => "switch",
+            DefenseKind::Hedge => "hedge",
+            DefenseKind::Help => "help",
+            DefenseKind::Trap => "trap",
+            DefenseKind::Closeout => "closeout",
+        }
+    }
+}
+
+/// A `// line` or `/* block */` comment, without its delimiters.
+/// The parser attaches each one to the nearest node: the entry it trails
+/// on the same line, otherwise the next entry.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Comment {
+    pub text: String,
+    pub span: Span,
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -46,4 +351,25 @@ pub enum Timing {
     After,
     Middle,
     None, // Default if not specified
+    /// `p2:0.3`: a point in the movement, from 0.0 (start) to 1.0 (end)
+    Fraction(f64),
+    /// `p2:before p4.screen`
+    BeforeEvent(EventRef),
+    /// `p2:after p4.screen`
+    AfterEvent(EventRef),
+}
+
+/// Another player's action in the same phase, e.g. `p4.screen`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct EventRef {
+    pub player: String,
+    pub kind: EventKind,
+    pub span: Span,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum EventKind {
+    Move,
+    Screen,
+    Pass,
 }
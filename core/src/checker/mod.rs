@@ -0,0 +1,686 @@
+use crate::ast::{Action, DefenseKind, EventKind, EventRef, MoveKind, Playbook, Point, Timing};
+use crate::court;
+use crate::lexer::Span;
+use crate::parser::get_suggestion;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum CheckError {
+    UndeclaredPlayer(String, Span),
+    /// The second span is the first declaration.
+    DuplicatePlayer(String, Span, Span),
+    MissingPosition(String, Span),
+    /// The second span is the first move.
+    MovedTwice(String, Span, Span),
+    PassWithoutBall(String, Span),
+    SelfScreen(String, Span),
+    /// `after p4.screen` when p4 has no screen in that phase.
+    UnknownEvent(String, Span),
+    /// Timings that wait on each other, e.g. two passes each `after` the other.
+    TimingCycle(String, Span),
+    /// An offensive player where a defender is expected (`guards`, defensive actions).
+    ExpectedDefender(String, Span),
+    /// A defender where an offensive player is expected (screens, passes, the baller).
+    ExpectedOffense(String, Span),
+    /// A dribble by a player who never has the ball in that phase.
+    DribbleWithoutBall(String, Span),
+    ShotWithoutBall(String, Span),
+    /// A name in a point that is neither a player nor a spot, with the closest known name.
+    UnknownSpot(String, Span, Option<String>),
+    /// `toward` in a starting position, where there is no previous point to start from.
+    TowardWithoutStart(Span),
+    /// Starting positions that refer to each other, e.g. `p1 = p2 + (10, 0), p2 = p1`.
+    PositionCycle(String, Span),
+}
+
+impl CheckError {
+    pub fn span(&self) -> Span {
+        match self {
+            CheckError::UndeclaredPlayer(_, span)
+            | CheckError::DuplicatePlayer(_, span, _)
+            | CheckError::MissingPosition(_, span)
+            | CheckError::MovedTwice(_, span, _)
+            | CheckError::PassWithoutBall(_, span)
+            | CheckError::SelfScreen(_, span)
+            | CheckError::UnknownEvent(_, span)
+            | CheckError::TimingCycle(_, span)
+            | CheckError::ExpectedDefender(_, span)
+            | CheckError::ExpectedOffense(_, span)
+            | CheckError::DribbleWithoutBall(_, span)
+            | CheckError::ShotWithoutBall(_, span)
+            | CheckError::UnknownSpot(_, span, _)
+            | CheckError::TowardWithoutStart(span)
+            | CheckError::PositionCycle(_, span) => *span,
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            CheckError::UndeclaredPlayer(player, _) => {
+                format!("Player '{}' is not declared in players", player)
+            }
+            CheckError::DuplicatePlayer(player, _, _) => {
+                format!("Player '{}' is declared more than once", player)
+            }
+            CheckError::MissingPosition(player, _) => {
+                format!("Player '{}' has no starting position", player)
+            }
+            CheckError::MovedTwice(player, _, _) => {
+                format!("Player '{}' moves more than once in the same phase", player)
+            }
+            CheckError::PassWithoutBall(player, _) => {
+                format!("Player '{}' passes without having the ball", player)
+            }
+            CheckError::SelfScreen(player, _) => {
+                format!("Player '{}' cannot screen themselves", player)
+            }
+            CheckError::UnknownEvent(event, _) => {
+                format!("Event '{}' does not happen in this phase", event)
+            }
+            CheckError::TimingCycle(event, _) => {
+                format!("Timing through '{}' waits on itself", event)
+            }
+            CheckError::ExpectedDefender(player, _) => {
+                format!("Player '{}' is not a defender", player)
+            }
+            CheckError::ExpectedOffense(player, _) => {
+                format!("Player '{}' is a defender", player)
+            }
+            CheckError::DribbleWithoutBall(player, _) => {
+                format!("Player '{}' dribbles without having the ball", player)
+            }
+            CheckError::ShotWithoutBall(player, _) => {
+                format!("Player '{}' shoots without having the ball", player)
+            }
+            CheckError::UnknownSpot(spot, _, _) => format!("Unknown spot '{}'", spot),
+            CheckError::TowardWithoutStart(_) => {
+                "'toward' cannot be used in a starting position".to_string()
+            }
+            CheckError::PositionCycle(player, _) => {
+                format!("Position of '{}' depends on itself", player)
+            }
+        }
+    }
+}
+
+/// Semantic checks on a parsed `Playbook`, run before IR generation.
+pub struct Checker;
+
+impl Checker {
+    pub fn check(playbook: &Playbook) -> Vec<CheckError> {
+        let mut errors = Vec::new();
+
+        // 1. Declarations, offense then defense
+        let mut declared = HashMap::new();
+        let mut defenders = HashSet::new();
+        let offense = playbook.players.iter().enumerate().map(|(i, player)| {
+            let span = playbook.player_spans.get(i).copied().unwrap_or_default();
+            (player, span, false)
+        });
+        let defense = playbook.defense.iter().enumerate().map(|(i, player)| {
+            let span = playbook.defense_spans.get(i).copied().unwrap_or_default();
+            (player, span, true)
+        });
+        for (player, span, is_defender) in offense.chain(defense) {
+            if let Some(first) = declared.get(player.as_str()) {
+                errors.push(CheckError::DuplicatePlayer(player.clone(), span, *first));
+                continue;
+            }
+            declared.insert(player.as_str(), span);
+            if is_defender {
+                defenders.insert(player.as_str());
+            }
+            if !playbook.state.positions.contains_key(player) {
+                errors.push(CheckError::MissingPosition(player.clone(), span));
+            }
+        }
+
+        let check_declared = |player: &str, span: Span, errors: &mut Vec<CheckError>| {
+            if !declared.contains_key(player) {
+                errors.push(CheckError::UndeclaredPlayer(player.to_string(), span));
+            }
+        };
+        let check_team = |player: &str, span: Span, defense: bool, errors: &mut Vec<CheckError>| {
+            if !declared.contains_key(player) {
+                errors.push(CheckError::UndeclaredPlayer(player.to_string(), span));
+            } else if defense && !defenders.contains(player) {
+                errors.push(CheckError::ExpectedDefender(player.to_string(), span));
+            } else if !defense && defenders.contains(player) {
+                errors.push(CheckError::ExpectedOffense(player.to_string(), span));
+            }
+        };
+
+        // 2. State
+        let mut positioned: Vec<(&String, &Span)> = playbook.state.position_spans.iter().collect();
+        positioned.sort_by_key(|(_, span)| span.start);
+        for (player, span) in positioned {
+            check_declared(player, *span, &mut errors);
+        }
+        if let (Some(baller), Some(span)) = (&playbook.state.baller, playbook.state.baller_span) {
+            check_team(baller, span, false, &mut errors);
+        }
+        for matchup in &playbook.state.guards {
+            check_team(&matchup.defender, matchup.span, true, &mut errors);
+            check_team(&matchup.player, matchup.player_span, false, &mut errors);
+        }
+
+        // Names in points, in source order
+        let mut names: Vec<&str> = court::SPOT_NAMES.to_vec();
+        names.extend(playbook.spots.keys().map(String::as_str));
+        names.extend(
+            playbook
+                .players
+                .iter()
+                .chain(&playbook.defense)
+                .map(String::as_str),
+        );
+        let mut unknown: Vec<(&String, Span)> = playbook
+            .points()
+            .into_iter()
+            .flat_map(|point| point.names())
+            .filter(|(name, _)| {
+                !declared.contains_key(name.as_str()) && playbook.spot(name).is_none()
+            })
+            .collect();
+        unknown.sort_by_key(|(_, span)| span.start);
+        for (name, span) in unknown {
+            let suggestion = get_suggestion(name, &names);
+            errors.push(CheckError::UnknownSpot(name.clone(), span, suggestion));
+        }
+        let mut towards: Vec<Span> = playbook
+            .state
+            .positions
+            .values()
+            .flat_map(|point| point.parts())
+            .filter_map(|part| match part {
+                Point::Toward(_, _, span) => Some(*span),
+                _ => None,
+            })
+            .collect();
+        towards.sort_by_key(|span| span.start);
+        errors.extend(towards.into_iter().map(CheckError::TowardWithoutStart));
+        if let Some((player, span)) = position_cycle(playbook) {
+            errors.push(CheckError::PositionCycle(player, span));
+        }
+
+        // 3. Actions, phase by phase
+        let mut holder = playbook.state.baller.clone();
+        for action in playbook.phase_actions() {
+            // Whoever starts the phase with the ball or catches it during the phase
+            let ball_handlers: HashSet<&String> = holder
+                .iter()
+                .chain(action.passes.iter().map(|pass| &pass.to))
+                .collect();
+
+            let mut moved = HashMap::new();
+            for move_action in &action.moves {
+                if move_action.kind == MoveKind::Move {
+                    check_declared(&move_action.player, move_action.span, &mut errors);
+                } else {
+                    check_team(&move_action.player, move_action.span, false, &mut errors);
+                }
+                if move_action.kind == MoveKind::Dribble
+                    && !ball_handlers.contains(&move_action.player)
+                {
+                    errors.push(CheckError::DribbleWithoutBall(
+                        move_action.player.clone(),
+                        move_action.span,
+                    ));
+                }
+                match moved.get(move_action.player.as_str()) {
+                    Some(first) => errors.push(CheckError::MovedTwice(
+                        move_action.player.clone(),
+                        move_action.span,
+                        *first,
+                    )),
+                    None => {
+                        moved.insert(move_action.player.as_str(), move_action.span);
+                    }
+                }
+            }
+
+            for screen in &action.screens {
+                check_team(&screen.player, screen.span, false, &mut errors);
+                check_team(&screen.target, screen.target_span, false, &mut errors);
+                if screen.player == screen.target {
+                    errors.push(CheckError::SelfScreen(
+                        screen.player.clone(),
+                        screen.target_span,
+                    ));
+                }
+            }
+
+            for pass in &action.passes {
+                check_team(&pass.from, pass.span, false, &mut errors);
+                check_team(&pass.to, pass.to_span, false, &mut errors);
+                if holder.as_ref() != Some(&pass.from) {
+                    errors.push(CheckError::PassWithoutBall(pass.from.clone(), pass.span));
+                }
+                holder = Some(pass.to.clone());
+            }
+
+            // Nobody has the ball after a shot
+            for shot in &action.shots {
+                check_team(&shot.player, shot.span, false, &mut errors);
+                if holder.as_ref() != Some(&shot.player) {
+                    errors.push(CheckError::ShotWithoutBall(shot.player.clone(), shot.span));
+                }
+                holder = None;
+            }
+
+            // Defensive actions move the defender, a switch moves both defenders
+            for defense in &action.defense {
+                let switch = defense.kind == DefenseKind::Switch;
+                check_team(&defense.defender, defense.span, true, &mut errors);
+                check_team(&defense.target, defense.target_span, switch, &mut errors);
+                let mut movers = vec![(&defense.defender, defense.span)];
+                if switch {
+                    movers.push((&defense.target, defense.target_span));
+                }
+                for (player, span) in movers {
+                    match moved.get(player.as_str()) {
+                        Some(first) => {
+                            errors.push(CheckError::MovedTwice(player.clone(), span, *first))
+                        }
+                        None => {
+                            moved.insert(player.as_str(), span);
+                        }
+                    }
+                }
+            }
+
+            // Event timings
+            let timings = action
+                .screens
+                .iter()
+                .map(|screen| &screen.timing)
+                .chain(action.passes.iter().map(|pass| &pass.timing))
+                .chain(action.shots.iter().map(|shot| &shot.timing))
+                .chain(action.defense.iter().map(|defense| &defense.timing));
+            for timing in timings {
+                if let Some(event) = event_ref(timing)
+                    && find_event(action, event).is_none()
+                {
+                    errors.push(CheckError::UnknownEvent(event_name(event), event.span));
+                }
+            }
+            if let Some((name, span)) = timing_cycle(action) {
+                errors.push(CheckError::TimingCycle(name, span));
+            }
+        }
+
+        errors
+    }
+}
+
+/// A pass or screen, by index in its action.
+type Event = (EventKind, usize);
+
+fn event_ref(timing: &Timing) -> Option<&EventRef> {
+    match timing {
+        Timing::BeforeEvent(event) | Timing::AfterEvent(event) => Some(event),
+        _ => None,
+    }
+}
+
+fn event_name(event: &EventRef) -> String {
+    let kind = match event.kind {
+        EventKind::Move => "move",
+        EventKind::Screen => "screen",
+        EventKind::Pass => "pass",
+    };
+    format!("{}.{}", event.player, kind)
+}
+
+/// The first matching event, the same one the IR generator times against.
+/// Moves always start the phase, so they are found but never wait on anything.
+fn find_event(action: &Action, event: &EventRef) -> Option<Option<Event>> {
+    match event.kind {
+        EventKind::Move => action
+            .moves
+            .iter()
+            .any(|m| m.player == event.player)
+            .then_some(None),
+        EventKind::Screen => action
+            .screens
+            .iter()
+            .position(|s| s.player == event.player)
+            .map(|i| Some((EventKind::Screen, i))),
+        EventKind::Pass => action
+            .passes
+            .iter()
+            .position(|p| p.from == event.player)
+            .map(|i| Some((EventKind::Pass, i))),
+    }
+}
+
+/// Finds starting positions that refer back to themselves.
+/// Returns the player reference that closes the cycle.
+fn position_cycle(playbook: &Playbook) -> Option<(String, Span)> {
+    fn visit<'a>(
+        playbook: &'a Playbook,
+        player: &'a str,
+        finished: &mut HashMap<&'a str, bool>,
+    ) -> Option<(String, Span)> {
+        finished.insert(player, false);
+        let point = &playbook.state.positions[player];
+        for (name, span) in point.names() {
+            if !playbook.state.positions.contains_key(name) {
+                continue;
+            }
+            match finished.get(name.as_str()) {
+                // Back on the current path
+                Some(false) => return Some((name.clone(), span)),
+                Some(true) => {}
+                None => {
+                    if let Some(cycle) = visit(playbook, name, finished) {
+                        return Some(cycle);
+                    }
+                }
+            }
+        }
+        finished.insert(player, true);
+        None
+    }
+
+    let mut players: Vec<(&String, &Span)> = playbook.state.position_spans.iter().collect();
+    players.sort_by_key(|(_, span)| span.start);
+    let mut finished = HashMap::new();
+    for (player, _) in players {
+        if !finished.contains_key(player.as_str())
+            && playbook.state.positions.contains_key(player)
+            && let Some(cycle) = visit(playbook, player, &mut finished)
+        {
+            return Some(cycle);
+        }
+    }
+    None
+}
+
+/// Finds timings that wait on themselves. A pass also waits for the previous pass.
+/// Returns the event reference that closes the cycle.
+fn timing_cycle(action: &Action) -> Option<(String, Span)> {
+    fn dependencies(action: &Action, (kind, i): Event) -> Vec<(Event, Option<&EventRef>)> {
+        let timing = match kind {
+            EventKind::Screen => &action.screens[i].timing,
+            EventKind::Pass => &action.passes[i].timing,
+            EventKind::Move => return Vec::new(),
+        };
+        let mut dependencies = Vec::new();
+        if let Some(event) = event_ref(timing)
+            && let Some(Some(target)) = find_event(action, event)
+        {
+            dependencies.push((target, Some(event)));
+        }
+        if kind == EventKind::Pass && i > 0 {
+            dependencies.push(((EventKind::Pass, i - 1), None));
+        }
+        dependencies
+    }
+
+    fn visit<'a>(
+        action: &'a Action,
+        event: Event,
+        finished: &mut HashMap<Event, bool>,
+        path: &mut Vec<Option<&'a EventRef>>,
+    ) -> Option<(String, Span)> {
+        finished.insert(event, false);
+        for (dependency, reference) in dependencies(action, event) {
+            path.push(reference);
+            match finished.get(&dependency) {
+                // Back on the current path: the last reference is part of the cycle
+                Some(false) => {
+                    let event = path.iter().rev().flatten().next()?;
+                    return Some((event_name(event), event.span));
+                }
+                Some(true) => {}
+                None => {
+                    if let Some(cycle) = visit(action, dependency, finished, path) {
+                        return Some(cycle);
+                    }
+                }
+            }
+            path.pop();
+        }
+        finished.insert(event, true);
+        None
+    }
+
+    let mut finished = HashMap::new();
+    let events = (0..action.screens.len())
+        .map(|i| (EventKind::Screen, i))
+        .chain((0..action.passes.len()).map(|i| (EventKind::Pass, i)));
+    for event in events {
+        if !finished.contains_key(&event)
+            && let Some(cycle) = visit(action, event, &mut finished, &mut Vec::new())
+        {
+            return Some(cycle);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn check(input: &str) -> Vec<CheckError> {
+        let mut lexer = Lexer::new(input);
+        let mut parser = Parser::new(lexer.tokenize());
+        Checker::check(&parser.parse().unwrap())
+    }
+
+    #[test]
+    fn test_valid_playbook() {
+        let errors = check(
+            r#"
+            players = { p1, p2 }
+            state = { baller = p1, position = { p1 = (0, 60), p2 = (90, -80) } }
+            phase 1 = { move = { p2 -> (70, 20) }, pass = { p1 -> p2 } }
+            phase 2 = { pass = { p2 -> p1 } }
+            "#,
+        );
+        assert_eq!(errors, vec![]);
+    }
+
+    #[test]
+    fn test_undeclared_player() {
+        let errors = check(
+            r#"players = { p1 }
+state = { baller = p1, position = { p1 = (0, 0) } }
+action = { move = { p9 -> (10, 10) } }"#,
+        );
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(&errors[0], CheckError::UndeclaredPlayer(p, _) if p == "p9"));
+        assert_eq!(errors[0].span().line, 3);
+        assert_eq!(errors[0].span().column, 21);
+    }
+
+    #[test]
+    fn test_declaration_errors() {
+        let errors = check("players = { p1, p1, p2 } state = { position = { p1 = (0, 0) } }");
+        assert_eq!(
+            errors,
+            vec![
+                CheckError::DuplicatePlayer(
+                    "p1".to_string(),
+                    Span {
+                        start: 16,
+                        end: 18,
+                        line: 1,
+                        column: 17
+                    },
+                    Span {
+                        start: 12,
+                        end: 14,
+                        line: 1,
+                        column: 13
+                    }
+                ),
+                CheckError::MissingPosition(
+                    "p2".to_string(),
+                    Span {
+                        start: 20,
+                        end: 22,
+                        line: 1,
+                        column: 21
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_action_errors() {
+        let errors = check(
+            r#"
+            players = { p1, p2 }
+            state = { baller = p1, position = { p1 = (0, 60), p2 = (90, -80) } }
+            action = {
+                move = { p2 -> (70, 20), p2 -> (0, 0) },
+                screen = { p1 -> p1 },
+                pass = { p2 -> p1 },
+            }
+            "#,
+        );
+        assert!(matches!(&errors[0], CheckError::MovedTwice(p, _, _) if p == "p2"));
+        assert!(matches!(&errors[1], CheckError::SelfScreen(p, _) if p == "p1"));
+        assert!(matches!(&errors[2], CheckError::PassWithoutBall(p, _) if p == "p2"));
+        assert_eq!(errors.len(), 3);
+    }
+
+    #[test]
+    fn test_event_timings() {
+        let errors = check(
+            r#"
+            players = { p1, p2, p4 }
+            state = { baller = p1, position = { p1 = (0, 60), p2 = (90, -80), p4 = (50, -10) } }
+            action = {
+                screen = { p4 -> p2:after p2.move },
+                pass = { p1 -> p2:after p4.screen },
+            }
+            "#,
+        );
+        assert!(matches!(&errors[0], CheckError::UnknownEvent(e, _) if e == "p2.move"));
+        assert_eq!(errors.len(), 1);
+
+        let errors = check(
+            r#"
+            players = { p1, p2, p4 }
+            state = { baller = p1, position = { p1 = (0, 60), p2 = (90, -80), p4 = (50, -10) } }
+            action = {
+                screen = { p4 -> p2:after p2.pass },
+                pass = { p1 -> p2:after p4.screen, p2 -> p1 },
+            }
+            "#,
+        );
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(&errors[0], CheckError::TimingCycle(..)));
+        assert_eq!(errors[0].span().line, 6);
+    }
+
+    #[test]
+    fn test_defense() {
+        let errors = check(
+            r#"
+            players = { p1, p5 }
+            defense = { x1, x5 }
+            state = {
+                baller = p1,
+                position = { p1 = (0, 60), p5 = (0, 20), x1 = (0, 45), x5 = (0, 5) },
+                guards = { x1 -> p1, p5 -> x5 },
+            }
+            action = {
+                move = { x5 -> (10, 10) },
+                hedge = { x5 -> p1 },
+                switch = { x1 -> p5 },
+                pass = { p1 -> x1 },
+            }
+            "#,
+        );
+        let names: Vec<(&str, &str)> = errors
+            .iter()
+            .map(|e| match e {
+                CheckError::ExpectedDefender(p, _) => ("defender", p.as_str()),
+                CheckError::ExpectedOffense(p, _) => ("offense", p.as_str()),
+                CheckError::MovedTwice(p, _, _) => ("moved", p.as_str()),
+                _ => ("other", ""),
+            })
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                ("defender", "p5"),
+                ("offense", "x5"),
+                ("offense", "x1"),
+                ("moved", "x5"),
+                ("defender", "p5"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ball_possession() {
+        let errors = check(
+            r#"
+            players = { p1, p2, p3 }
+            state = { baller = p1, position = { p1 = (0, 60), p2 = (90, -80), p3 = (-90, -80) } }
+            phase 1 = {
+                dribble = { p1 -> (0, 40), p3 -> (-60, -60) },
+                handoff = { p1 -> p2 },
+                shot = { p2 },
+            }
+            phase 2 = { pass = { p2 -> p1 } }
+            "#,
+        );
+        assert_eq!(errors.len(), 2);
+        assert!(matches!(&errors[0], CheckError::DribbleWithoutBall(p, _) if p == "p3"));
+        assert!(matches!(&errors[1], CheckError::PassWithoutBall(p, _) if p == "p2"));
+    }
+
+    #[test]
+    fn test_unknown_spot() {
+        let errors = check(
+            r#"
+            players = { p1, p2 }
+            spots = { deep = (0, 80) }
+            state = { position = { p1 = left_wnig, p2 = deep } }
+            action = { move = { p2 -> top -> (0, 0) via dep } }
+            "#,
+        );
+        assert_eq!(
+            errors,
+            vec![
+                CheckError::UnknownSpot(
+                    "left_wnig".to_string(),
+                    errors[0].span(),
+                    Some("left_wing".to_string())
+                ),
+                CheckError::UnknownSpot(
+                    "dep".to_string(),
+                    errors[1].span(),
+                    Some("deep".to_string())
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_relative_points() {
+        let errors = check(
+            r#"
+            players = { p1, p2, p3 }
+            state = { position = { p1 = p3 + (10, 0), p2 = toward(top, 5), p3 = p1 } }
+            action = { move = { p2 -> toward(basket, 10) + (0, 5), p3 -> p4 } }
+            "#,
+        );
+        assert_eq!(errors.len(), 3);
+        assert!(
+            matches!(&errors[0], CheckError::UnknownSpot(name, _, Some(s)) if name == "p4" && s == "p1")
+        );
+        assert!(matches!(&errors[1], CheckError::TowardWithoutStart(_)));
+        assert!(matches!(&errors[2], CheckError::PositionCycle(name, _) if name == "p1"));
+    }
+}
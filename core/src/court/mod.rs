@@ -0,0 +1,368 @@
+//! Court geometry. Every court is drawn 200 units wide with its baseline at `y = -90`;
+//! a `CourtSpec` says where the lines go and how playbook coordinates map onto the drawing.
+
+/// Where the baseline is drawn.
+pub const BASELINE: f64 = -90.0;
+/// Drawn width of every court, sideline to sideline.
+pub const DIAGRAM_WIDTH: f64 = 200.0;
+
+const FT: f64 = 0.3048;
+
+/// Names accepted by `court = ...`.
+pub const COURT_NAMES: &[&str] = &["classic", "fiba", "nba", "ncaa", "high_school", "fiba_3x3"];
+
+/// Names accepted by `view = ...`.
+pub const VIEW_NAMES: &[&str] = &["half", "three_quarter", "full"];
+
+/// Built-in spot names, left and right as seen by the offense facing the basket.
+pub const SPOT_NAMES: &[&str] = &[
+    "top",
+    "left_slot",
+    "right_slot",
+    "left_wing",
+    "right_wing",
+    "left_corner",
+    "right_corner",
+    "left_elbow",
+    "right_elbow",
+    "nail",
+    "left_block",
+    "right_block",
+    "left_short_corner",
+    "right_short_corner",
+    "dunker_left",
+    "dunker_right",
+    "basket",
+    "half_court",
+];
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Units {
+    Meters,
+    Feet,
+}
+
+impl Units {
+    pub fn from_name(name: &str) -> Option<Units> {
+        match name {
+            "meters" => Some(Units::Meters),
+            "feet" => Some(Units::Feet),
+            _ => None,
+        }
+    }
+}
+
+/// How much of the court is drawn, starting from the baseline under the basket the offense attacks.
+/// Coordinates carry on past the half-court line into the backcourt.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum CourtView {
+    #[default]
+    Half,
+    /// The half court plus half of the backcourt, for presses and transition.
+    ThreeQuarter,
+    /// Both baskets.
+    Full,
+}
+
+impl CourtView {
+    pub fn from_name(name: &str) -> Option<CourtView> {
+        match name {
+            "half" => Some(CourtView::Half),
+            "three_quarter" => Some(CourtView::ThreeQuarter),
+            "full" => Some(CourtView::Full),
+            _ => None,
+        }
+    }
+
+    /// Drawn length of `court` in this view, from the baseline.
+    pub fn drawn_length(&self, court: &CourtSpec) -> f64 {
+        let halves = match self {
+            CourtView::Half => 1.0,
+            CourtView::ThreeQuarter => 1.5,
+            CourtView::Full => 2.0,
+        };
+        court.drawn_half_length() * halves
+    }
+}
+
+/// Dimensions of a court, in meters for real rulesets.
+/// Distances along the court are measured from the baseline.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct CourtSpec {
+    pub name: &'static str,
+    /// Units of the playbook coordinates, whose origin is the center of the hoop.
+    /// `None` for the classic diagram, where measurements and coordinates share their own unit
+    /// and `(0, 0)` is the middle of the half court.
+    pub units: Option<Units>,
+    pub width: f64,
+    /// Baseline to the half-court line.
+    pub half_length: f64,
+    /// Baseline to the center of the hoop.
+    pub hoop: f64,
+    pub hoop_radius: f64,
+    pub backboard: f64,
+    pub backboard_width: f64,
+    pub lane_width: f64,
+    pub free_throw_line: f64,
+    pub free_throw_circle: f64,
+    pub three_point_radius: f64,
+    /// Baseline to the center of the 3-point arc.
+    pub three_point_center: f64,
+    /// Middle of the court to the straight corner-three lines.
+    pub corner_three: f64,
+    pub restricted_area: Option<f64>,
+    pub center_circle: Option<f64>,
+    /// Baseline to `y = 0` of the playbook coordinates.
+    pub origin: f64,
+}
+
+impl CourtSpec {
+    /// The original diagram in its own units, with the hoop at `(0, -84)`.
+    pub const CLASSIC: CourtSpec = CourtSpec {
+        name: "classic",
+        units: None,
+        width: 200.0,
+        half_length: 180.0,
+        hoop: 6.0,
+        hoop_radius: 5.0,
+        backboard: 2.0,
+        backboard_width: 24.0,
+        lane_width: 40.0,
+        free_throw_line: 65.0,
+        free_throw_circle: 20.0,
+        three_point_radius: 80.0,
+        three_point_center: 55.0,
+        corner_three: 80.0,
+        restricted_area: None,
+        center_circle: Some(20.0),
+        origin: 90.0,
+    };
+
+    pub const FIBA: CourtSpec = CourtSpec {
+        name: "fiba",
+        units: Some(Units::Meters),
+        width: 15.0,
+        half_length: 14.0,
+        hoop: 1.575,
+        hoop_radius: 0.225,
+        backboard: 1.2,
+        backboard_width: 1.8,
+        lane_width: 4.9,
+        free_throw_line: 5.8,
+        free_throw_circle: 1.8,
+        three_point_radius: 6.75,
+        three_point_center: 1.575,
+        corner_three: 6.6,
+        restricted_area: Some(1.25),
+        center_circle: Some(1.8),
+        origin: 1.575,
+    };
+
+    pub const NBA: CourtSpec = CourtSpec {
+        name: "nba",
+        units: Some(Units::Feet),
+        width: 50.0 * FT,
+        half_length: 47.0 * FT,
+        hoop: 5.25 * FT,
+        hoop_radius: 0.75 * FT,
+        backboard: 4.0 * FT,
+        backboard_width: 6.0 * FT,
+        lane_width: 16.0 * FT,
+        free_throw_line: 19.0 * FT,
+        free_throw_circle: 6.0 * FT,
+        three_point_radius: 23.75 * FT,
+        three_point_center: 5.25 * FT,
+        corner_three: 22.0 * FT,
+        restricted_area: Some(4.0 * FT),
+        center_circle: Some(6.0 * FT),
+        origin: 5.25 * FT,
+    };
+
+    /// Men's and women's college, with the 6.75 m arc.
+    pub const NCAA: CourtSpec = CourtSpec {
+        name: "ncaa",
+        lane_width: 12.0 * FT,
+        three_point_radius: 6.75,
+        corner_three: 6.6,
+        ..CourtSpec::NBA
+    };
+
+    /// NFHS rules.
+    pub const HIGH_SCHOOL: CourtSpec = CourtSpec {
+        name: "high_school",
+        half_length: 42.0 * FT,
+        lane_width: 12.0 * FT,
+        three_point_radius: 19.75 * FT,
+        corner_three: 19.75 * FT,
+        restricted_area: None,
+        ..CourtSpec::NBA
+    };
+
+    /// A single 15 x 11 m half court.
+    pub const FIBA_3X3: CourtSpec = CourtSpec {
+        name: "fiba_3x3",
+        half_length: 11.0,
+        center_circle: None,
+        ..CourtSpec::FIBA
+    };
+
+    pub fn from_name(name: &str) -> Option<CourtSpec> {
+        match name {
+            "classic" => Some(CourtSpec::CLASSIC),
+            "fiba" => Some(CourtSpec::FIBA),
+            "nba" => Some(CourtSpec::NBA),
+            "ncaa" => Some(CourtSpec::NCAA),
+            "high_school" => Some(CourtSpec::HIGH_SCHOOL),
+            "fiba_3x3" => Some(CourtSpec::FIBA_3X3),
+            _ => None,
+        }
+    }
+
+    /// The same court with coordinates in other units. The classic diagram keeps its own.
+    pub fn with_units(mut self, units: Units) -> Self {
+        if self.units.is_some() {
+            self.units = Some(units);
+        }
+        self
+    }
+
+    /// Drawing units per measurement unit.
+    pub fn scale(&self) -> f64 {
+        DIAGRAM_WIDTH / self.width
+    }
+
+    /// Drawing position of a distance from the baseline.
+    pub fn y(&self, from_baseline: f64) -> f64 {
+        BASELINE + from_baseline * self.scale()
+    }
+
+    /// A length in playbook units, in drawing units.
+    pub fn length(&self, length: f64) -> f64 {
+        match self.units {
+            None => length,
+            Some(units) => round(length * meters(units) * self.scale()),
+        }
+    }
+
+    /// Playbook coordinates to drawing coordinates.
+    pub fn to_diagram(&self, (x, y): (f64, f64)) -> (f64, f64) {
+        match self.units {
+            None => (x, y),
+            Some(units) => (
+                round(x * meters(units) * self.scale()),
+                round(self.y(self.origin + y * meters(units))),
+            ),
+        }
+    }
+
+    /// Center of the hoop in drawing coordinates.
+    pub fn hoop_center(&self) -> (f64, f64) {
+        (0.0, self.y(self.hoop))
+    }
+
+    /// Drawn length of the half court, baseline to half-court line.
+    pub fn drawn_half_length(&self) -> f64 {
+        self.half_length * self.scale()
+    }
+
+    /// A built-in spot in drawing coordinates. Perimeter spots sit just behind the 3-point line.
+    pub fn spot(&self, name: &str) -> Option<(f64, f64)> {
+        let s = self.scale();
+        let lane = self.lane_width / 2.0 * s;
+        let corner = self.corner_three * s;
+        let arc_center = self.y(self.three_point_center);
+        let behind = self.three_point_radius * s + 8.0;
+        let around = |degrees: f64| {
+            let angle = f64::to_radians(degrees);
+            (behind * angle.sin(), arc_center + behind * angle.cos())
+        };
+        let (x, y) = match name {
+            "top" => around(0.0),
+            "left_slot" => around(-22.5),
+            "right_slot" => around(22.5),
+            "left_wing" => around(-45.0),
+            "right_wing" => around(45.0),
+            "left_corner" => (-(corner + 8.0), BASELINE + 12.0),
+            "right_corner" => (corner + 8.0, BASELINE + 12.0),
+            "left_elbow" => (-lane, self.y(self.free_throw_line)),
+            "right_elbow" => (lane, self.y(self.free_throw_line)),
+            "nail" => (0.0, self.y(self.free_throw_line)),
+            "left_block" => (-(lane + 4.0), BASELINE + 22.0),
+            "right_block" => (lane + 4.0, BASELINE + 22.0),
+            "left_short_corner" => (-((lane + corner) / 2.0 + 5.0), BASELINE + 12.0),
+            "right_short_corner" => ((lane + corner) / 2.0 + 5.0, BASELINE + 12.0),
+            "dunker_left" => (-(lane + 12.0), self.y(self.hoop)),
+            "dunker_right" => (lane + 12.0, self.y(self.hoop)),
+            "basket" => self.hoop_center(),
+            "half_court" => (0.0, self.y(self.half_length)),
+            _ => return None,
+        };
+        Some((x.round(), y.round()))
+    }
+}
+
+fn meters(units: Units) -> f64 {
+    match units {
+        Units::Meters => 1.0,
+        Units::Feet => FT,
+    }
+}
+
+fn round(value: f64) -> f64 {
+    (value * 100.0).round() / 100.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPECS: &[CourtSpec] = &[
+        CourtSpec::CLASSIC,
+        CourtSpec::FIBA,
+        CourtSpec::NBA,
+        CourtSpec::NCAA,
+        CourtSpec::HIGH_SCHOOL,
+        CourtSpec::FIBA_3X3,
+    ];
+
+    #[test]
+    fn test_perimeter_spots_are_behind_the_line() {
+        for spec in SPECS {
+            let s = spec.scale();
+            let arc_center = (0.0, spec.y(spec.three_point_center));
+            for name in ["top", "left_slot", "right_slot", "left_wing", "right_wing"] {
+                let (x, y) = spec.spot(name).unwrap();
+                let distance = ((x - arc_center.0).powi(2) + (y - arc_center.1).powi(2)).sqrt();
+                assert!(
+                    distance > spec.three_point_radius * s,
+                    "{}: {}",
+                    spec.name,
+                    name
+                );
+            }
+            for name in ["left_corner", "right_corner"] {
+                assert!(spec.spot(name).unwrap().0.abs() > spec.corner_three * s);
+            }
+            assert_eq!(spec.spot("logo"), None);
+        }
+        assert_eq!(CourtSpec::CLASSIC.spot("left_elbow"), Some((-20.0, -25.0)));
+        assert_eq!(CourtSpec::CLASSIC.spot("right_wing"), Some((62.0, 27.0)));
+        assert_eq!(CourtSpec::CLASSIC.spot("basket"), Some((0.0, -84.0)));
+        assert_eq!(CourtSpec::CLASSIC.spot("half_court"), Some((0.0, 90.0)));
+    }
+
+    #[test]
+    fn test_coordinates() {
+        // The classic diagram draws coordinates as written
+        assert_eq!(CourtSpec::CLASSIC.to_diagram((0.1, -20.5)), (0.1, -20.5));
+
+        // Real courts measure from the hoop: the top of the FIBA arc, 6.75 m out
+        let fiba = CourtSpec::FIBA;
+        assert_eq!(fiba.to_diagram((0.0, 0.0)), fiba.hoop_center());
+        assert_eq!(fiba.to_diagram((0.0, 6.75)), (0.0, 21.0));
+        assert_eq!(fiba.to_diagram((7.5, 0.0)).0, 100.0);
+        let feet = fiba.with_units(Units::Feet);
+        assert_eq!(feet.length(25.0), fiba.length(7.62));
+        assert_eq!(CourtSpec::NBA.to_diagram((25.0, 0.0)).0, 100.0);
+    }
+}
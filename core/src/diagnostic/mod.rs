@@ -0,0 +1,392 @@
+use crate::ast::Playbook;
+use crate::checker::{CheckError, Checker};
+use crate::import::{Loader, Resolver};
+use crate::lexer::Span;
+use crate::parser::ParseError;
+use serde::Serialize;
+use std::fmt;
+use std::path::Path;
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+/// A compiler message pointing into the source.
+/// Serializes to JSON so editors can underline `primary.span`.
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct Diagnostic {
+    pub code: &'static str,
+    pub severity: Severity,
+    pub message: String,
+    pub primary: Label,
+    pub secondary: Vec<Label>,
+    pub help: Option<String>,
+    pub suggestion: Option<String>,
+    /// The imported file the spans point into; `None` for the file being analyzed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn error(code: &'static str, message: impl Into<String>, span: Span) -> Self {
+        Self {
+            code,
+            severity: Severity::Error,
+            message: message.into(),
+            primary: Label {
+                span,
+                message: String::new(),
+            },
+            secondary: Vec::new(),
+            help: None,
+            suggestion: None,
+            file: None,
+        }
+    }
+
+    pub fn with_label(mut self, message: impl Into<String>) -> Self {
+        self.primary.message = message.into();
+        self
+    }
+
+    pub fn with_secondary(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.secondary.push(Label {
+            span,
+            message: message.into(),
+        });
+        self
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+
+    pub fn with_suggestion(mut self, suggestion: impl Into<String>) -> Self {
+        self.suggestion = Some(suggestion.into());
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    /// Pretty-prints the diagnostic with the offending source line and a caret underline.
+    /// For a diagnostic in an imported file, `source` is that file's content.
+    ///
+    /// ```text
+    /// error[E0001]: Expected RBrace
+    ///  --> input.playbook:1:13
+    ///   |
+    /// 1 | players = {
+    ///   |             ^ found EOF
+    /// ```
+    pub fn render(&self, source: &str, file_name: &str) -> String {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        let mut out = format!("{}[{}]: {}\n", severity, self.code, self.message);
+
+        let span = self.primary.span;
+        let max_line = self
+            .secondary
+            .iter()
+            .map(|label| label.span.line)
+            .chain(std::iter::once(span.line))
+            .max()
+            .unwrap_or(1);
+        let gutter = " ".repeat(max_line.to_string().len());
+
+        out.push_str(&format!(
+            "{}--> {}:{}:{}\n",
+            gutter,
+            self.file.as_deref().unwrap_or(file_name),
+            span.line,
+            span.column
+        ));
+        out.push_str(&format!("{} |\n", gutter));
+        out.push_str(&render_snippet(source, &self.primary, '^', &gutter));
+        for label in &self.secondary {
+            out.push_str(&render_snippet(source, label, '-', &gutter));
+        }
+
+        if let Some(help) = &self.help {
+            out.push_str(&format!("{} = help: {}\n", gutter, help));
+        }
+        if let Some(suggestion) = &self.suggestion {
+            out.push_str(&format!(
+                "{} = suggestion: did you mean '{}'?\n",
+                gutter, suggestion
+            ));
+        }
+        out
+    }
+}
+
+fn render_snippet(source: &str, label: &Label, marker: char, gutter: &str) -> String {
+    let span = label.span;
+    let Some(line) = source.lines().nth(span.line.saturating_sub(1)) else {
+        return String::new();
+    };
+    // Columns are counted in chars by the lexer
+    let line_len = line.chars().count();
+    let start = span.column.saturating_sub(1).min(line_len);
+    let width = source
+        .get(span.start..span.end)
+        .map(|text| text.chars().take_while(|c| *c != '\n').count())
+        .unwrap_or(0)
+        .max(1);
+
+    let mut out = format!("{:>width$} | {}\n", span.line, line, width = gutter.len());
+    let mut underline = format!(
+        "{} | {}{}",
+        gutter,
+        " ".repeat(start),
+        marker.to_string().repeat(width)
+    );
+    if !label.message.is_empty() {
+        underline.push(' ');
+        underline.push_str(&label.message);
+    }
+    out.push_str(&underline);
+    out.push('\n');
+    out
+}
+
+/// One-line form, e.g. `Error at line 3, column 5: Expected Identifier (found Comma)`.
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "Error",
+            Severity::Warning => "Warning",
+        };
+        let span = self.primary.span;
+        write!(f, "{}", severity)?;
+        if let Some(file) = &self.file {
+            write!(f, " in {}", file)?;
+        }
+        if span.line == 0 {
+            write!(f, ": {}", self.message)?;
+        } else {
+            write!(
+                f,
+                " at line {}, column {}: {}",
+                span.line, span.column, self.message
+            )?;
+        }
+        if let Some(suggestion) = &self.suggestion {
+            write!(f, ". Did you mean '{}'?", suggestion)?;
+        }
+        if !self.primary.message.is_empty() {
+            write!(f, " ({})", self.primary.message)?;
+        }
+        Ok(())
+    }
+}
+
+impl From<&ParseError> for Diagnostic {
+    fn from(error: &ParseError) -> Self {
+        match error {
+            ParseError::UnexpectedToken(token, msg) => {
+                let found = format!("found {:?}", token.kind);
+                // Typo hints are produced as "<message>. Did you mean '<keyword>'?"
+                let diagnostic = match msg
+                    .strip_suffix("'?")
+                    .and_then(|m| m.split_once(". Did you mean '"))
+                {
+                    Some((message, suggestion)) => {
+                        Diagnostic::error("E0001", message, token.span).with_suggestion(suggestion)
+                    }
+                    None => Diagnostic::error("E0001", msg.clone(), token.span),
+                };
+                diagnostic.with_label(found)
+            }
+            ParseError::UnexpectedEOF => {
+                Diagnostic::error("E0002", "Unexpected End of File", Span::default())
+            }
+            ParseError::InvalidSyntax(msg) => {
+                Diagnostic::error("E0003", msg.clone(), Span::default())
+            }
+        }
+    }
+}
+
+impl From<&CheckError> for Diagnostic {
+    fn from(error: &CheckError) -> Self {
+        let code = match error {
+            CheckError::UndeclaredPlayer(..) => "E0101",
+            CheckError::DuplicatePlayer(..) => "E0102",
+            CheckError::MissingPosition(..) => "E0103",
+            CheckError::MovedTwice(..) => "E0104",
+            CheckError::PassWithoutBall(..) => "E0105",
+            CheckError::SelfScreen(..) => "E0106",
+            CheckError::UnknownEvent(..) => "E0107",
+            CheckError::TimingCycle(..) => "E0108",
+            CheckError::ExpectedDefender(..) => "E0109",
+            CheckError::ExpectedOffense(..) => "E0110",
+            CheckError::DribbleWithoutBall(..) => "E0111",
+            CheckError::ShotWithoutBall(..) => "E0112",
+            CheckError::UnknownSpot(..) => "E0113",
+            CheckError::TowardWithoutStart(..) => "E0114",
+            CheckError::PositionCycle(..) => "E0115",
+        };
+        let diagnostic = Diagnostic::error(code, error.message(), error.span());
+        match error {
+            CheckError::UndeclaredPlayer(player, _) => {
+                diagnostic.with_help(format!("add '{}' to the players section", player))
+            }
+            CheckError::DuplicatePlayer(_, _, first) => {
+                diagnostic.with_secondary(*first, "first declared here")
+            }
+            CheckError::MissingPosition(player, _) => {
+                diagnostic.with_help(format!("add '{} = (x, y)' to state.position", player))
+            }
+            CheckError::MovedTwice(_, _, first) => {
+                diagnostic.with_secondary(*first, "first move here")
+            }
+            CheckError::PassWithoutBall(..) => {
+                diagnostic.with_help("only the baller or the last receiver can pass")
+            }
+            CheckError::DribbleWithoutBall(..) => {
+                diagnostic.with_help("use 'cut' for a player moving without the ball")
+            }
+            CheckError::ShotWithoutBall(..) => {
+                diagnostic.with_help("only the baller or the last receiver can shoot")
+            }
+            CheckError::SelfScreen(..) => diagnostic,
+            CheckError::UnknownEvent(..) => {
+                diagnostic.with_help("refer to a move, screen or pass of the same phase")
+            }
+            CheckError::TimingCycle(..) => {
+                diagnostic.with_help("passes also wait for the previous pass to be caught")
+            }
+            CheckError::ExpectedDefender(player, _) => {
+                diagnostic.with_help(format!("declare '{}' in the defense section", player))
+            }
+            CheckError::UnknownSpot(_, _, Some(suggestion)) => {
+                diagnostic.with_suggestion(suggestion)
+            }
+            CheckError::UnknownSpot(..) => diagnostic.with_help(
+                "use a player, define the spot in a 'spots' section, or use coordinates",
+            ),
+            CheckError::TowardWithoutStart(..) => diagnostic
+                .with_help("use 'toward' in a movement, where it starts from the previous point"),
+            CheckError::PositionCycle(..) => {
+                diagnostic.with_help("a position can refer to another player, but not in a circle")
+            }
+            CheckError::ExpectedOffense(..) => diagnostic.with_help(
+                "only players from the players section can screen, pass or hold the ball",
+            ),
+        }
+    }
+}
+
+/// Lexes, parses (with recovery) and checks `input`.
+/// Returns the best-effort playbook together with every diagnostic found.
+/// `input` is not a file, so it cannot import others.
+pub fn analyze(input: &str) -> (Playbook, Vec<Diagnostic>) {
+    Resolver::new(None).analyze(input, Path::new(""))
+}
+
+/// Like `analyze`, for the file at `path`: imports are read with `loader`,
+/// relative to the file, and applied before checking.
+pub fn analyze_file(input: &str, path: &Path, loader: &dyn Loader) -> (Playbook, Vec<Diagnostic>) {
+    Resolver::new(Some(loader)).analyze(input, path)
+}
+
+/// Checks a playbook whose imports are already applied.
+pub(crate) fn check(playbook: &Playbook) -> Vec<Diagnostic> {
+    Checker::check(playbook)
+        .iter()
+        .map(Diagnostic::from)
+        .collect()
+}
+
+/// Serializes diagnostics as a JSON array.
+pub fn to_json(diagnostics: &[Diagnostic]) -> String {
+    serde_json::to_string(diagnostics).unwrap_or_else(|_| "[]".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_diagnostics(input: &str) -> Vec<Diagnostic> {
+        analyze(input).1
+    }
+
+    #[test]
+    fn test_render_snippet() {
+        let input = "players = { p1 }\nstate = { baller = 1 }";
+        let diagnostics = parse_diagnostics(input);
+        assert_eq!(
+            diagnostics[0].render(input, "input.playbook"),
+            "error[E0001]: Expected Identifier
+ --> input.playbook:2:20
+  |
+2 | state = { baller = 1 }
+  |                    ^ found Number(1.0)
+"
+        );
+    }
+
+    #[test]
+    fn test_eof_span() {
+        let input = "players = { p1";
+        let diagnostics = parse_diagnostics(input);
+        assert_eq!(diagnostics[0].primary.span.line, 1);
+        assert_eq!(diagnostics[0].primary.span.column, 15);
+        assert!(
+            diagnostics[0]
+                .render(input, "a.playbook")
+                .ends_with("1 | players = { p1\n  |               ^ found EOF\n")
+        );
+    }
+
+    #[test]
+    fn test_suggestion_and_display() {
+        let diagnostics = parse_diagnostics("aciton = { }");
+        assert_eq!(diagnostics[0].message, "Expected section start");
+        assert_eq!(diagnostics[0].suggestion, Some("action".to_string()));
+        assert_eq!(
+            diagnostics[0].to_string(),
+            "Error at line 1, column 1: Expected section start. Did you mean 'action'? (found Identifier(\"aciton\"))"
+        );
+    }
+
+    #[test]
+    fn test_secondary_and_json() {
+        let error = CheckError::DuplicatePlayer(
+            "p1".to_string(),
+            Span {
+                start: 16,
+                end: 18,
+                line: 1,
+                column: 17,
+            },
+            Span {
+                start: 12,
+                end: 14,
+                line: 1,
+                column: 13,
+            },
+        );
+        let diagnostic = Diagnostic::from(&error);
+        let rendered = diagnostic.render("players = { p1, p1 }", "a.playbook");
+        assert!(rendered.contains("  |                 ^^\n"));
+        assert!(rendered.contains("  |             -- first declared here\n"));
+
+        let json = to_json(&[diagnostic]);
+        assert!(json.starts_with(r#"[{"code":"E0102","severity":"error","message":"Player 'p1' is declared more than once","primary":{"span":{"start":16,"end":18,"line":1,"column":17},"message":""}"#));
+    }
+}
@@ -0,0 +1,624 @@
+use crate::diagnostic::Diagnostic;
+use crate::lexer::{Lexer, Token, TokenKind};
+use crate::parser::Parser;
+
+const INDENT: &str = "  ";
+
+/// Pretty-prints `input` in the canonical style, keeping every comment.
+///
+/// The file must be syntactically valid; semantic errors are ignored so
+/// half-written plays can still be formatted.
+pub fn format(input: &str) -> Result<String, Vec<Diagnostic>> {
+    let tokens = Lexer::new(input).tokenize();
+    let (_, errors) = Parser::new(tokens.clone()).parse_with_recovery();
+    if !errors.is_empty() {
+        return Err(errors.iter().map(Diagnostic::from).collect());
+    }
+
+    let mut builder = TreeBuilder {
+        source: input,
+        tokens,
+        pos: 0,
+        last_line: 0,
+    };
+    let (items, dangling) = builder.items();
+
+    let mut out = String::new();
+    for (i, item) in items.iter().enumerate() {
+        // Sections are set apart, `import` and `use` lines are kept together
+        if i > 0 && !(is_import(&items[i - 1]) && is_import(item)) {
+            out.push('\n');
+        }
+        print_item(&mut out, item, 0);
+        out.push('\n');
+    }
+    if !dangling.is_empty() {
+        if !items.is_empty() {
+            out.push('\n');
+        }
+        for comment in &dangling {
+            out.push_str(comment);
+            out.push('\n');
+        }
+    }
+    Ok(out)
+}
+
+/// One entry of a block (or a top-level section) together with its comments.
+struct Item {
+    leading: Vec<String>,
+    parts: Vec<Part>,
+    trailing: Option<String>,
+    blank_before: bool,
+}
+
+enum Part {
+    Token(Token, String),
+    Block(Block),
+}
+
+struct Block {
+    /// Comment on the same line as the `{`.
+    open_comment: Option<String>,
+    items: Vec<Item>,
+    /// Comments after the last entry.
+    dangling: Vec<String>,
+}
+
+impl Block {
+    /// Blocks of bare names (`{ p1, p2 }`) stay on one line.
+    fn is_flat(&self) -> bool {
+        self.open_comment.is_none()
+            && self.dangling.is_empty()
+            && self.items.iter().all(|item| {
+                item.leading.is_empty()
+                    && item.trailing.is_none()
+                    && matches!(item.parts.as_slice(), [Part::Token(..)])
+            })
+    }
+}
+
+/// Groups the token stream into blocks and entries.
+/// Commas are optional in the grammar, so entries are also split where
+/// two operands meet without an operator in between (`p1 -> (0, 0) p2 -> ...`).
+struct TreeBuilder<'a> {
+    source: &'a str,
+    tokens: Vec<Token>,
+    pos: usize,
+    /// Line on which the last consumed token ends.
+    last_line: usize,
+}
+
+impl TreeBuilder<'_> {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos.min(self.tokens.len() - 1)]
+    }
+
+    fn advance(&mut self) -> Token {
+        let token = self.peek().clone();
+        if self.pos < self.tokens.len() {
+            self.pos += 1;
+        }
+        let text = &self.source[token.span.start..token.span.end];
+        self.last_line = token.span.line + text.matches('\n').count();
+        token
+    }
+
+    fn text(&self, token: &Token) -> String {
+        self.source[token.span.start..token.span.end]
+            .trim_end()
+            .to_string()
+    }
+
+    fn comment_on_last_line(&mut self) -> Option<String> {
+        let token = self.peek();
+        if is_comment(&token.kind) && token.span.line == self.last_line {
+            let token = self.advance();
+            Some(self.text(&token))
+        } else {
+            None
+        }
+    }
+
+    /// Reads entries up to the closing `}` (not consumed) or EOF.
+    fn items(&mut self) -> (Vec<Item>, Vec<String>) {
+        let mut items = Vec::new();
+        loop {
+            let blank_before = !items.is_empty() && self.peek().span.line > self.last_line + 1;
+            let mut leading = Vec::new();
+            while is_comment(&self.peek().kind) {
+                let token = self.advance();
+                leading.push(self.text(&token));
+            }
+            if matches!(self.peek().kind, TokenKind::RBrace | TokenKind::EOF) {
+                return (items, leading);
+            }
+
+            let parts = self.parts(&mut leading);
+            if self.peek().kind == TokenKind::Comma {
+                self.advance();
+            }
+            let trailing = self.comment_on_last_line();
+            // `p1 // note` followed by the comma on the next line
+            if self.peek().kind == TokenKind::Comma {
+                self.advance();
+            }
+            items.push(Item {
+                leading,
+                parts,
+                trailing,
+                blank_before,
+            });
+        }
+    }
+
+    fn parts(&mut self, leading: &mut Vec<String>) -> Vec<Part> {
+        let mut parts: Vec<Part> = Vec::new();
+        let mut depth = 0usize;
+        loop {
+            let token = self.peek().clone();
+            match token.kind {
+                TokenKind::Comment(_) | TokenKind::BlockComment(_) => {
+                    let next = self.tokens[self.pos..]
+                        .iter()
+                        .find(|token| !is_comment(&token.kind))
+                        .unwrap_or(&token);
+                    if depth == 0 && ends_item(&parts, &next.kind) {
+                        // Belongs to whatever follows this entry
+                        return parts;
+                    }
+                    // A comment inside an entry is moved above it
+                    self.advance();
+                    leading.push(self.text(&token));
+                }
+                TokenKind::Comma if depth == 0 => return parts,
+                TokenKind::RBrace | TokenKind::EOF => return parts,
+                TokenKind::LBrace => {
+                    self.advance();
+                    let open_comment = self.comment_on_last_line();
+                    let (items, dangling) = self.items();
+                    if self.peek().kind == TokenKind::RBrace {
+                        self.advance();
+                    }
+                    parts.push(Part::Block(Block {
+                        open_comment,
+                        items,
+                        dangling,
+                    }));
+                    // Every block closes its entry
+                    return parts;
+                }
+                _ => {
+                    if depth == 0 && ends_item(&parts, &token.kind) {
+                        return parts;
+                    }
+                    match token.kind {
+                        TokenKind::LParenthesis | TokenKind::LBracket => depth += 1,
+                        TokenKind::RParenthesis | TokenKind::RBracket => {
+                            depth = depth.saturating_sub(1)
+                        }
+                        // `["ATO",]`
+                        TokenKind::Comma
+                            if self.tokens.get(self.pos + 1).map(|t| &t.kind)
+                                == Some(&TokenKind::RBracket) =>
+                        {
+                            self.advance();
+                            continue;
+                        }
+                        _ => {}
+                    }
+                    self.advance();
+                    let text = self.text(&token);
+                    parts.push(Part::Token(token, text));
+                }
+            }
+        }
+    }
+}
+
+fn is_import(item: &Item) -> bool {
+    matches!(
+        item.parts.first(),
+        Some(Part::Token(token, _)) if matches!(token.kind, TokenKind::Import | TokenKind::Use)
+    )
+}
+
+fn is_comment(kind: &TokenKind) -> bool {
+    matches!(kind, TokenKind::Comment(_) | TokenKind::BlockComment(_))
+}
+
+/// Whether `next` can no longer belong to the entry made of `parts`.
+fn ends_item(parts: &[Part], next: &TokenKind) -> bool {
+    match next {
+        TokenKind::Comma | TokenKind::RBrace | TokenKind::EOF => true,
+        // `:after p4.screen`
+        TokenKind::Identifier(_)
+            if matches!(
+                parts.last(),
+                Some(Part::Token(prev, _)) if matches!(prev.kind, TokenKind::Before | TokenKind::After)
+            ) =>
+        {
+            false
+        }
+        // `use formation horns`
+        TokenKind::Identifier(_) if matches!(parts, [Part::Token(first, _), Part::Token(..)] if first.kind == TokenKind::Use) => {
+            false
+        }
+        _ => matches!(
+            parts.last(),
+            Some(Part::Token(prev, _)) if ends_operand(&prev.kind) && starts_operand(next)
+        ),
+    }
+}
+
+fn ends_operand(kind: &TokenKind) -> bool {
+    !matches!(
+        kind,
+        TokenKind::Equals
+            | TokenKind::Arrow
+            | TokenKind::Colon
+            | TokenKind::Dot
+            | TokenKind::Via
+            | TokenKind::Plus
+            | TokenKind::Toward
+            | TokenKind::Polar
+            | TokenKind::LParenthesis
+            | TokenKind::LBracket
+            | TokenKind::Comma
+            | TokenKind::Phase
+            | TokenKind::Import
+            | TokenKind::Use
+            | TokenKind::Template
+    )
+}
+
+fn starts_operand(kind: &TokenKind) -> bool {
+    !matches!(
+        kind,
+        TokenKind::Equals
+            | TokenKind::Arrow
+            | TokenKind::Colon
+            | TokenKind::Dot
+            | TokenKind::Via
+            | TokenKind::Curve
+            | TokenKind::Plus
+            | TokenKind::LParenthesis
+            | TokenKind::RParenthesis
+            | TokenKind::RBracket
+            | TokenKind::Comma
+            | TokenKind::Number(_)
+    )
+}
+
+fn needs_space(prev: &TokenKind, next: &TokenKind) -> bool {
+    !matches!(
+        (prev, next),
+        (
+            _,
+            TokenKind::Comma
+                | TokenKind::RParenthesis
+                | TokenKind::RBracket
+                | TokenKind::Colon
+                | TokenKind::Dot
+        ) | (
+            TokenKind::LParenthesis | TokenKind::LBracket | TokenKind::Colon | TokenKind::Dot,
+            _
+        ) | (
+            TokenKind::Identifier(_) | TokenKind::Toward | TokenKind::Polar,
+            TokenKind::LParenthesis
+        )
+    )
+}
+
+fn push_indent(out: &mut String, level: usize) {
+    out.push_str(&INDENT.repeat(level));
+}
+
+/// Prints an entry without its trailing comma or newline.
+fn print_item(out: &mut String, item: &Item, level: usize) {
+    for comment in &item.leading {
+        push_indent(out, level);
+        out.push_str(comment);
+        out.push('\n');
+    }
+    push_indent(out, level);
+
+    let mut prev: Option<&TokenKind> = None;
+    for part in &item.parts {
+        match part {
+            Part::Token(token, text) => {
+                if prev.is_some_and(|prev| needs_space(prev, &token.kind)) {
+                    out.push(' ');
+                }
+                out.push_str(text);
+                prev = Some(&token.kind);
+            }
+            Part::Block(block) => {
+                if prev.is_some() {
+                    out.push(' ');
+                }
+                print_block(out, block, level);
+            }
+        }
+    }
+
+    // Top-level sections end here; block entries get their comma first
+    if level == 0
+        && let Some(comment) = &item.trailing
+    {
+        out.push(' ');
+        out.push_str(comment);
+    }
+}
+
+fn print_block(out: &mut String, block: &Block, level: usize) {
+    if block.items.is_empty() && block.is_flat() {
+        out.push_str("{}");
+        return;
+    }
+    if block.is_flat() {
+        let names: Vec<&str> = block
+            .items
+            .iter()
+            .filter_map(|item| match item.parts.as_slice() {
+                [Part::Token(_, text)] => Some(text.as_str()),
+                _ => None,
+            })
+            .collect();
+        out.push_str(&format!("{{ {} }}", names.join(", ")));
+        return;
+    }
+
+    out.push('{');
+    if let Some(comment) = &block.open_comment {
+        out.push(' ');
+        out.push_str(comment);
+    }
+    out.push('\n');
+    for (i, item) in block.items.iter().enumerate() {
+        if i > 0 && item.blank_before {
+            out.push('\n');
+        }
+        print_item(out, item, level + 1);
+        out.push(',');
+        if let Some(comment) = &item.trailing {
+            out.push(' ');
+            out.push_str(comment);
+        }
+        out.push('\n');
+    }
+    for comment in &block.dangling {
+        push_indent(out, level + 1);
+        out.push_str(comment);
+        out.push('\n');
+    }
+    push_indent(out, level);
+    out.push('}');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_canonical() {
+        let input = "players = {p1,p2}
+state = { baller = p1, position = { p1 = ( 0,60 ), p2=(90, -80.5) } }
+action = {
+    move = { p2->(70, 20) }
+    pass = { p1 -> p2 : after, },
+}";
+        assert_eq!(
+            format(input).unwrap(),
+            "players = { p1, p2 }
+
+state = {
+  baller = p1,
+  position = {
+    p1 = (0, 60),
+    p2 = (90, -80.5),
+  },
+}
+
+action = {
+  move = {
+    p2 -> (70, 20),
+  },
+  pass = {
+    p1 -> p2:after,
+  },
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_missing_commas() {
+        let input = "action = { move = { p1 -> (0, 0) p2 -> (1, 1) } screen = { p3 -> p1:middle p4 -> p2 } }";
+        assert_eq!(
+            format(input).unwrap(),
+            "action = {
+  move = {
+    p1 -> (0, 0),
+    p2 -> (1, 1),
+  },
+  screen = {
+    p3 -> p1:middle,
+    p4 -> p2,
+  },
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_event_timings() {
+        let input = "action = { pass = { p1 -> p2 : after p4 . screen p2 -> p3:0.5 } }";
+        assert_eq!(
+            format(input).unwrap(),
+            "action = {
+  pass = {
+    p1 -> p2:after p4.screen,
+    p2 -> p3:0.5,
+  },
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_waypoints() {
+        let input = "action = { cut = { p2->(30,0)->(70,20)via(60,-10) curve p3 -> (0, 0) } }";
+        assert_eq!(
+            format(input).unwrap(),
+            "action = {
+  cut = {
+    p2 -> (30, 0) -> (70, 20) via (60, -10) curve,
+    p3 -> (0, 0),
+  },
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_relative_points() {
+        let input =
+            "action = { cut = { p2->p3+(10,0) p3 -> toward ( basket,15 )->polar(30, -45) } }";
+        assert_eq!(
+            format(input).unwrap(),
+            "action = {
+  cut = {
+    p2 -> p3 + (10, 0),
+    p3 -> toward(basket, 15) -> polar(30, -45),
+  },
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_meta() {
+        let input = r#"meta = { name="Horns \"Flare\"" tags = [ "ATO" ,"horns", ] }"#;
+        assert_eq!(
+            format(input).unwrap(),
+            r#"meta = {
+  name = "Horns \"Flare\"",
+  tags = ["ATO", "horns"],
+}
+"#
+        );
+    }
+
+    #[test]
+    fn test_imports() {
+        let input = "import   \"formations/horns.playbook\"\nuse formation\nhorns players = { p6 }";
+        assert_eq!(
+            format(input).unwrap(),
+            "import \"formations/horns.playbook\"\nuse formation horns\n\nplayers = { p6 }\n"
+        );
+    }
+
+    #[test]
+    fn test_templates() {
+        let input = "template  pick_and_roll( handler,screener ) = { shot = { handler } }\nphase 1 = { transform = { mirror } pick_and_roll(p1, p5) give(p1,p2) }";
+        let expected = "template pick_and_roll(handler, screener) = {
+  shot = { handler },
+}
+
+phase 1 = {
+  transform = { mirror },
+  pick_and_roll(p1, p5),
+  give(p1, p2),
+}
+";
+        assert_eq!(format(input).unwrap(), expected);
+        assert_eq!(format(expected).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_comments_are_kept() {
+        let input = "// Horns set
+players = { p1, p2 } // two players
+
+action = { // the only action
+  move = {
+    // cut first
+    p2 -> (70, 20), // to the wing
+
+    p1 -> (0, 0)
+    // end of moves
+  }
+}
+// end";
+        let expected = "// Horns set
+players = { p1, p2 } // two players
+
+action = { // the only action
+  move = {
+    // cut first
+    p2 -> (70, 20), // to the wing
+
+    p1 -> (0, 0),
+    // end of moves
+  },
+}
+
+// end
+";
+        assert_eq!(format(input).unwrap(), expected);
+        assert_eq!(format(expected).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_block_comments() {
+        let input = "players = { p1 /* guard */, p2 }\n/* multi\n   line */\nstate = {}";
+        assert_eq!(
+            format(input).unwrap(),
+            "players = {\n  p1, /* guard */\n  p2,\n}\n\n/* multi\n   line */\nstate = {}\n"
+        );
+    }
+
+    #[test]
+    fn test_comments_inside_entries_keep_their_node() {
+        let input = "players = { p1, p2 }
+state = { baller = p1, position = { p1 = /* top */ (0, 60), p2 = (90, -80) } }
+phase /* entry */ 1 = { move = // p2 first
+  { p2 -> /* wide */ (70, 20) }, pass = { p1 -> p2:after /* late */ p2.move } }
+phase 2 = { cut = { p1 -> (0, // deep
+  0) } }";
+        let formatted = format(input).unwrap();
+        let renderer = crate::Renderer::new();
+        assert_eq!(
+            renderer.render(&formatted).unwrap(),
+            renderer.render(input).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_fixtures_are_formatted() {
+        for input in [
+            include_str!("../../../fixtures/input.playbook"),
+            include_str!("../../../fixtures/phases.playbook"),
+            include_str!("../../../fixtures/screen.playbook"),
+            include_str!("../../../fixtures/defense.playbook"),
+            include_str!("../../../fixtures/paths.playbook"),
+            include_str!("../../../fixtures/spots.playbook"),
+            include_str!("../../../fixtures/relative.playbook"),
+            include_str!("../../../fixtures/fiba.playbook"),
+            include_str!("../../../fixtures/press.playbook"),
+            include_str!("../../../fixtures/meta.playbook"),
+            include_str!("../../../fixtures/import.playbook"),
+            include_str!("../../../fixtures/template.playbook"),
+            include_str!("../../../fixtures/formations/horns.playbook"),
+        ] {
+            assert_eq!(format(input).unwrap(), input);
+        }
+    }
+
+    #[test]
+    fn test_syntax_error() {
+        let errors = format("players = { p1").unwrap_err();
+        assert_eq!(errors[0].code, "E0001");
+    }
+}
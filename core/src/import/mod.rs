@@ -0,0 +1,373 @@
+//! `import "file.playbook"` and `use formation name`: plays built on shared formations and spots.
+//!
+//! Imported files are analyzed on their own first, so their errors point into them.
+//! A formation is an imported file's players, defense and starting state;
+//! the importing play keeps whatever it declares itself.
+
+use crate::ast::{FormationUse, Import, Matchup, Playbook};
+use crate::diagnostic::{self, Diagnostic};
+use crate::lexer::{Lexer, Span};
+use crate::parser::{Parser, get_suggestion};
+use crate::template;
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+
+/// Reads the files playbooks import.
+pub trait Loader {
+    fn load(&self, path: &Path) -> Result<String, String>;
+}
+
+/// Reads imports from disk.
+pub struct FileLoader;
+
+impl Loader for FileLoader {
+    fn load(&self, path: &Path) -> Result<String, String> {
+        std::fs::read_to_string(path).map_err(|e| e.to_string())
+    }
+}
+
+/// Files kept in memory, by path.
+impl Loader for HashMap<PathBuf, String> {
+    fn load(&self, path: &Path) -> Result<String, String> {
+        self.get(path)
+            .cloned()
+            .ok_or_else(|| "No such file".to_string())
+    }
+}
+
+/// Analyzes a file after the files it imports, depth first.
+pub(crate) struct Resolver<'a> {
+    /// `None` for input that is not a file and cannot import.
+    loader: Option<&'a dyn Loader>,
+    /// Files being analyzed, each imported by the one before.
+    stack: Vec<PathBuf>,
+}
+
+impl<'a> Resolver<'a> {
+    pub fn new(loader: Option<&'a dyn Loader>) -> Self {
+        Self {
+            loader,
+            stack: Vec::new(),
+        }
+    }
+
+    pub fn analyze(&mut self, input: &str, path: &Path) -> (Playbook, Vec<Diagnostic>) {
+        let mut parser = Parser::new(Lexer::new(input).tokenize());
+        let (mut playbook, parse_errors) = parser.parse_with_recovery();
+        let mut diagnostics: Vec<Diagnostic> = parse_errors.iter().map(Diagnostic::from).collect();
+
+        let path = normalize(path);
+        self.stack.push(path.clone());
+        diagnostics.extend(self.resolve(&mut playbook, &path));
+        self.stack.pop();
+
+        // Semantic errors on a partial playbook would mostly be noise from the syntax errors
+        if diagnostics.is_empty() {
+            diagnostics.extend(template::expand(&mut playbook));
+        }
+        if diagnostics.is_empty() {
+            diagnostics.extend(diagnostic::check(&playbook));
+        }
+        (playbook, diagnostics)
+    }
+
+    /// Takes the spots and templates of every import, then the players and state of the formation used.
+    fn resolve(&mut self, playbook: &mut Playbook, path: &Path) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        let mut formations: Vec<(String, Playbook)> = Vec::new();
+        let mut failed: Vec<String> = Vec::new();
+        for import in &playbook.imports.clone() {
+            let name = formation_name(import);
+            match self.import(import, path) {
+                Ok(imported) => {
+                    for (spot, point) in &imported.spots {
+                        if !playbook.spots.contains_key(spot) {
+                            playbook.spots.insert(spot.clone(), *point);
+                            playbook.spot_spans.insert(spot.clone(), import.span);
+                        }
+                    }
+                    for template in &imported.templates {
+                        if !playbook
+                            .templates
+                            .iter()
+                            .any(|own| own.name == template.name)
+                        {
+                            playbook.templates.push(template.clone());
+                        }
+                    }
+                    formations.push((name, imported));
+                }
+                Err(errors) => {
+                    diagnostics.extend(errors);
+                    failed.push(name);
+                }
+            }
+        }
+
+        if let Some(formation) = playbook.formation.clone() {
+            match formations.iter().find(|(name, _)| *name == formation.name) {
+                Some((_, imported)) => inherit(playbook, imported, formation.span),
+                // Already reported at the import
+                None if failed.contains(&formation.name) => {}
+                None => diagnostics.push(unknown_formation(&formation, &formations)),
+            }
+        }
+        diagnostics
+    }
+
+    /// The imported file, analyzed, or the errors that keep it from being used.
+    fn import(&mut self, import: &Import, from: &Path) -> Result<Playbook, Vec<Diagnostic>> {
+        let Some(loader) = self.loader else {
+            return Err(vec![
+                Diagnostic::error(
+                    "E0201",
+                    format!("Cannot import '{}' here", import.path),
+                    import.span,
+                )
+                .with_help(
+                    "imports are read next to the playbook file, and this one is not a file",
+                ),
+            ]);
+        };
+        let path = normalize(&from.parent().unwrap_or(Path::new("")).join(&import.path));
+        if let Some(start) = self.stack.iter().position(|file| *file == path) {
+            let cycle: Vec<String> = self.stack[start..]
+                .iter()
+                .chain(std::iter::once(&path))
+                .map(|file| file.display().to_string())
+                .collect();
+            return Err(vec![Diagnostic::error(
+                "E0202",
+                format!("Import cycle: {}", cycle.join(" -> ")),
+                import.span,
+            )]);
+        }
+        let source = loader.load(&path).map_err(|e| {
+            vec![Diagnostic::error(
+                "E0201",
+                format!("Cannot read '{}': {}", import.path, e),
+                import.span,
+            )]
+        })?;
+
+        let (imported, mut diagnostics) = self.analyze(&source, &path);
+        diagnostics.retain(Diagnostic::is_error);
+        if diagnostics.is_empty() {
+            return Ok(imported);
+        }
+        // Errors of files imported further down already name their file
+        for diagnostic in &mut diagnostics {
+            diagnostic
+                .file
+                .get_or_insert_with(|| path.display().to_string());
+        }
+        let error = Diagnostic::error(
+            "E0203",
+            format!("'{}' has errors", import.path),
+            import.span,
+        )
+        .with_label(match diagnostics.len() {
+            1 => format!("1 error in {}", path.display()),
+            count => format!("{} errors in {}", count, path.display()),
+        });
+        diagnostics.insert(0, error);
+        Err(diagnostics)
+    }
+}
+
+/// `formations/horns.playbook` is the formation `horns`.
+fn formation_name(import: &Import) -> String {
+    Path::new(&import.path)
+        .file_stem()
+        .map_or_else(String::new, |stem| stem.to_string_lossy().into_owned())
+}
+
+/// Adds what the play doesn't declare itself from `formation`, pointing at `span`.
+fn inherit(playbook: &mut Playbook, formation: &Playbook, span: Span) {
+    let merge = |own: &mut Vec<String>, own_spans: &mut Vec<Span>, inherited: &[String]| {
+        let missing: Vec<String> = inherited
+            .iter()
+            .filter(|name| !own.contains(name))
+            .cloned()
+            .collect();
+        own_spans.splice(0..0, std::iter::repeat_n(span, missing.len()));
+        own.splice(0..0, missing);
+    };
+    merge(
+        &mut playbook.players,
+        &mut playbook.player_spans,
+        &formation.players,
+    );
+    merge(
+        &mut playbook.defense,
+        &mut playbook.defense_spans,
+        &formation.defense,
+    );
+
+    let state = &mut playbook.state;
+    if state.baller.is_none() {
+        state.baller = formation.state.baller.clone();
+        state.baller_span = formation.state.baller.as_ref().map(|_| span);
+    }
+    for (player, point) in &formation.state.positions {
+        if !state.positions.contains_key(player) {
+            state.positions.insert(player.clone(), point.clone());
+            state.position_spans.insert(player.clone(), span);
+        }
+    }
+    let guards: Vec<Matchup> = formation
+        .state
+        .guards
+        .iter()
+        .filter(|matchup| {
+            !state
+                .guards
+                .iter()
+                .any(|own| own.defender == matchup.defender)
+        })
+        .map(|matchup| Matchup {
+            span,
+            player_span: span,
+            ..matchup.clone()
+        })
+        .collect();
+    state.guards.splice(0..0, guards);
+
+    // Positions are written for the formation's court
+    playbook.court = playbook.court.or(formation.court);
+    playbook.units = playbook.units.or(formation.units);
+    playbook.view = playbook.view.or(formation.view);
+}
+
+fn unknown_formation(formation: &FormationUse, formations: &[(String, Playbook)]) -> Diagnostic {
+    let names: Vec<&str> = formations.iter().map(|(name, _)| name.as_str()).collect();
+    let diagnostic = Diagnostic::error(
+        "E0204",
+        format!("Unknown formation '{}'", formation.name),
+        formation.span,
+    );
+    match get_suggestion(&formation.name, &names) {
+        Some(suggestion) => diagnostic.with_suggestion(suggestion),
+        None => diagnostic.with_help(format!(
+            "import the file it is in, e.g. import \"formations/{}.playbook\"",
+            formation.name
+        )),
+    }
+}
+
+/// `plays/formations/../horns.playbook` as `plays/horns.playbook`, without touching the disk.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if normalized.file_name().is_some() => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn files(entries: &[(&str, &str)]) -> HashMap<PathBuf, String> {
+        entries
+            .iter()
+            .map(|(path, source)| (PathBuf::from(path), source.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_use_formation() {
+        let loader = files(&[
+            (
+                "plays/formations/horns.playbook",
+                r#"
+                import "../spots.playbook"
+                players = { p1, p2, p3, p4, p5 }
+                state = {
+                  baller = p1,
+                  position = { p1 = top, p2 = (-90, -80), p3 = (90, -80), p4 = (-25, 30), p5 = (25, 30) },
+                }
+                "#,
+            ),
+            ("plays/spots.playbook", "spots = { top = (0, 60) }"),
+        ]);
+        let input = r#"
+            import "formations/horns.playbook"
+            use formation horns
+            players = { p5 }
+            state = { baller = p2, position = { p5 = (40, 20) } }
+            action = { pass = { p2 -> p5 } }
+        "#;
+        let (playbook, diagnostics) =
+            diagnostic::analyze_file(input, Path::new("plays/flare.playbook"), &loader);
+        assert_eq!(diagnostics, []);
+        assert_eq!(playbook.players, ["p1", "p2", "p3", "p4", "p5"]);
+        assert_eq!(playbook.state.baller.as_deref(), Some("p2"));
+        assert_eq!(
+            playbook.state.positions["p5"],
+            crate::ast::Point::Coord(40.0, 20.0)
+        );
+        assert_eq!(playbook.state.positions.len(), 5);
+        assert!(playbook.spots.contains_key("top"));
+        // Inherited entries point at `use formation`
+        assert_eq!(playbook.player_spans[0].line, 3);
+
+        // Without a file there is nothing to import from
+        let (_, diagnostics) = diagnostic::analyze(input);
+        assert_eq!(diagnostics[0].code, "E0201");
+    }
+
+    #[test]
+    fn test_import_errors() {
+        let loader = files(&[
+            ("a.playbook", "import \"b.playbook\"\nplayers = { p1 }"),
+            ("b.playbook", "import \"a.playbook\"\nplayers = { p1 }"),
+            (
+                "broken.playbook",
+                "players = { p1 }\nstate = { baller = p9, position = { p1 = (0, 0) } }",
+            ),
+            (
+                "horns.playbook",
+                "players = { p1 }\nstate = { position = { p1 = (0, 0) } }",
+            ),
+        ]);
+        let (_, diagnostics) =
+            diagnostic::analyze_file("import \"a.playbook\"", Path::new("main.playbook"), &loader);
+        let cycle = diagnostics.iter().find(|d| d.code == "E0202").unwrap();
+        assert_eq!(
+            cycle.message,
+            "Import cycle: a.playbook -> b.playbook -> a.playbook"
+        );
+        assert_eq!(cycle.file.as_deref(), Some("b.playbook"));
+
+        // Errors in the imported file point into it; the import itself is flagged too
+        let input = "import \"broken.playbook\"\nuse formation broken";
+        let (_, diagnostics) = diagnostic::analyze_file(input, Path::new("main.playbook"), &loader);
+        let codes: Vec<&str> = diagnostics.iter().map(|d| d.code).collect();
+        assert_eq!(codes, ["E0203", "E0101"]);
+        assert_eq!(diagnostics[0].file, None);
+        assert_eq!(diagnostics[1].file.as_deref(), Some("broken.playbook"));
+        assert_eq!(diagnostics[1].primary.span.line, 2);
+        assert!(
+            diagnostics[1]
+                .to_string()
+                .starts_with("Error in broken.playbook at line 2")
+        );
+
+        let input = "import \"horns.playbook\"\nimport \"missing.playbook\"\nuse formation horn";
+        let (_, diagnostics) = diagnostic::analyze_file(input, Path::new("c.playbook"), &loader);
+        let unknown = diagnostics.iter().find(|d| d.code == "E0204").unwrap();
+        assert_eq!(unknown.suggestion.as_deref(), Some("horns"));
+        let missing = diagnostics
+            .iter()
+            .find(|d| d.message.starts_with("Cannot read 'missing.playbook'"))
+            .unwrap();
+        assert_eq!(missing.primary.span.line, 2);
+    }
+}
@@ -1,126 +1,643 @@
-use crate::ast::{Playbook, Timing};
+use crate::ast::{
+    Action, Comment, EventKind, EventRef, MoveKind, PassKind, Playbook, Point, Timing, Transform,
+};
 use crate::ir::*;
+use crate::template;
+use std::collections::{HashMap, HashSet};
+
+/// How far short of their target a defender stops.
+const DEFENSE_GAP: f64 = 12.0;
+
+/// A waypoint in coordinates, with its `via` control point.
+type ResolvedWaypoint = ((f64, f64), Option<(f64, f64)>);
 
 pub struct IRGenerator;
 
 impl IRGenerator {
-    pub fn generate(playbook: Playbook) -> Scene {
+    /// Builds one `Scene` per phase, expanding any template calls left in the playbook.
+    /// Each phase starts from the positions, ball holder and matchups the previous phase ended with.
+    pub fn generate(mut playbook: Playbook) -> Vec<Scene> {
+        // Already reported when the playbook was analyzed
+        let _ = template::expand(&mut playbook);
+        let mut positions = initial_positions(&playbook);
+        let mut baller = playbook.state.baller.clone();
+        let mut guards: HashMap<String, String> = playbook
+            .state
+            .guards
+            .iter()
+            .map(|matchup| (matchup.defender.clone(), matchup.player.clone()))
+            .collect();
+        let mut scenes = Vec::new();
+
+        let numbers: Vec<u32> = match playbook.phases.as_slice() {
+            [] => vec![1],
+            phases => phases.iter().map(|phase| phase.number).collect(),
+        };
+        for (action, number) in playbook.phase_actions().into_iter().zip(numbers) {
+            let mut scene = Self::generate_phase(&playbook, &positions, &baller, &guards, action);
+            scene.number = number;
+
+            for entity in &scene.entities {
+                positions.insert(entity.id.clone(), entity.end_pos);
+            }
+            guards = scene
+                .entities
+                .iter()
+                .filter_map(|entity| Some((entity.id.clone(), entity.guarding.clone()?)))
+                .collect();
+            // The last receiver holds the ball going into the next phase, unless they shot it
+            if !action.shots.is_empty() {
+                baller = None;
+            } else if let Some(pass) = action.passes.last() {
+                baller = Some(pass.to.clone());
+            }
+            scenes.push(scene);
+        }
+
+        scenes
+    }
+
+    fn generate_phase(
+        playbook: &Playbook,
+        start_positions: &HashMap<String, (f64, f64)>,
+        baller: &Option<String>,
+        guards: &HashMap<String, String>,
+        action: &Action,
+    ) -> Scene {
         let mut entities = Vec::new();
         let mut interactions = Vec::new();
 
         // 1. Resolve positions
         // Default end_pos to start_pos if no move is specified
-        let start_positions = playbook.state.positions.clone();
         let mut end_positions = start_positions.clone();
 
-        for move_action in &playbook.action.moves {
-            end_positions.insert(move_action.player.clone(), move_action.target);
+        let court = playbook.court();
+        let transform =
+            Affine::new(&court, &action.transform).then(Affine::new(&court, &playbook.transform));
+        let evaluate = |point: &Point, from: (f64, f64)| {
+            evaluate(playbook, start_positions, &transform, point, from)
+        };
+
+        let mut move_lines = Vec::new();
+        for move_action in &action.moves {
+            let from = *start_positions
+                .get(&move_action.player)
+                .unwrap_or(&(0.0, 0.0));
+            // Each waypoint is evaluated from the one before it
+            let mut previous = from;
+            let waypoints: Vec<ResolvedWaypoint> = move_action
+                .path
+                .iter()
+                .map(|waypoint| {
+                    let via = waypoint.via.as_ref().map(|via| evaluate(via, previous));
+                    previous = evaluate(&waypoint.point, previous);
+                    (previous, via)
+                })
+                .collect();
+            end_positions.insert(move_action.player.clone(), previous);
+            let line = MoveLine {
+                player_id: move_action.player.clone(),
+                from,
+                to: previous,
+                path: path_segments(from, &waypoints, move_action.curve),
+                start: 0.0,
+                end: MOVE_BEATS,
+                notes: notes(&move_action.comments),
+            };
+            move_lines.push((move_action.kind, line));
         }
 
+        // Players follow their path over the movement beat
+        let position_at = |player: &str, t: f64| match move_lines
+            .iter()
+            .find(|(_, line)| line.player_id == player)
+        {
+            Some((_, line)) => line.point_at((t / MOVE_BEATS).clamp(0.0, 1.0)),
+            None => *start_positions.get(player).unwrap_or(&(0.0, 0.0)),
+        };
+        let mut schedule = Schedule::new(action);
+
+        // Defenders head for their target and stop just short of it.
+        // On a switch each defender picks up the other's man.
+        let mut guarding = guards.clone();
+        let mut defense_lines = Vec::new();
+        for defense in &action.defense {
+            let (start, end) = schedule.defense(&defense.timing);
+            let mut reactions = Vec::new();
+            if defense.kind == DefenseKind::Switch {
+                let first = guarding.remove(&defense.defender);
+                let second = guarding.remove(&defense.target);
+                for (defender, man, partner) in [
+                    (&defense.defender, second, &defense.target),
+                    (&defense.target, first, &defense.defender),
+                ] {
+                    let from = *start_positions.get(defender).unwrap_or(&(0.0, 0.0));
+                    match man {
+                        Some(man) => {
+                            let to = approach(from, position_at(&man, start));
+                            guarding.insert(defender.clone(), man.clone());
+                            reactions.push((defender, man, from, to));
+                        }
+                        // Without matchups they trade places
+                        None => {
+                            let to = *start_positions.get(partner).unwrap_or(&(0.0, 0.0));
+                            reactions.push((defender, partner.clone(), from, to));
+                        }
+                    }
+                }
+            } else {
+                let from = *start_positions
+                    .get(&defense.defender)
+                    .unwrap_or(&(0.0, 0.0));
+                let to = approach(from, position_at(&defense.target, start));
+                reactions.push((&defense.defender, defense.target.clone(), from, to));
+            }
+
+            for (defender, target_id, from, to) in reactions {
+                defense_lines.push(DefenseLine {
+                    defender_id: defender.clone(),
+                    target_id,
+                    kind: defense.kind,
+                    from,
+                    to,
+                    start,
+                    end,
+                    notes: notes(&defense.comments),
+                });
+            }
+        }
         // 2. Create Entities
-        for player_id in playbook.players {
-            let start_pos = *start_positions.get(&player_id).unwrap_or(&(0.0, 0.0));
-            let end_pos = *end_positions.get(&player_id).unwrap_or(&start_pos);
-            let is_baller = playbook.state.baller.as_ref() == Some(&player_id);
+        let roster = playbook
+            .players
+            .iter()
+            .map(|id| (id, Team::Offense))
+            .chain(playbook.defense.iter().map(|id| (id, Team::Defense)));
+        for (player_id, team) in roster {
+            let start_pos = *start_positions.get(player_id).unwrap_or(&(0.0, 0.0));
+            let end_pos = defense_lines
+                .iter()
+                .find(|line| &line.defender_id == player_id)
+                .map(|line| line.to)
+                .or_else(|| end_positions.get(player_id).copied())
+                .unwrap_or(start_pos);
+            let is_baller = baller.as_ref() == Some(player_id);
+            let label = match team {
+                Team::Offense => player_id.replace("p", ""), // p1 -> 1
+                Team::Defense => player_id.replace("x", ""), // x1 -> 1
+            };
 
             entities.push(Entity {
                 id: player_id.clone(),
-                label: player_id.replace("p", ""), // p1 -> 1
+                label,
                 start_pos,
                 end_pos,
                 is_baller,
+                team,
+                guarding: guarding.get(player_id).cloned(),
+                notes: playbook
+                    .state
+                    .position_comments
+                    .get(player_id)
+                    .map(|comments| notes(comments))
+                    .unwrap_or_default(),
             });
         }
 
         // 3. Create Interactions
+
         // Moves
-        for move_action in playbook.action.moves {
-            let from = *start_positions
-                .get(&move_action.player)
-                .unwrap_or(&(0.0, 0.0));
-            interactions.push(Interaction::Move(MoveLine {
-                player_id: move_action.player,
-                from,
-                to: move_action.target,
-            }));
+        for (kind, line) in &move_lines {
+            let line = line.clone();
+            interactions.push(match kind {
+                MoveKind::Move => Interaction::Move(line),
+                MoveKind::Dribble => Interaction::Dribble(line),
+                MoveKind::Cut => Interaction::Cut(line),
+            });
         }
 
-        // Passes
-        for pass in playbook.action.passes {
-            let from = *end_positions.get(&pass.from).unwrap_or(&(0.0, 0.0)); // Ball moves after or during action
-            let to = match pass.timing {
-                Timing::Before => *start_positions.get(&pass.to).unwrap_or(&(0.0, 0.0)),
-                Timing::Middle => {
-                    let start = *start_positions.get(&pass.to).unwrap_or(&(0.0, 0.0));
-                    let end = *end_positions.get(&pass.to).unwrap_or(&(0.0, 0.0));
-                    ((start.0 + end.0) / 2.0, (start.1 + end.1) / 2.0)
-                }
-                Timing::After | Timing::None => *end_positions.get(&pass.to).unwrap_or(&(0.0, 0.0)),
+        // Passes: from the passer at the throw to the receiver at the catch
+        for (i, pass) in action.passes.iter().enumerate() {
+            let (start, end) = schedule.pass(i);
+            let line = PassLine {
+                passer_id: pass.from.clone(),
+                receiver_id: pass.to.clone(),
+                from: position_at(&pass.from, start),
+                to: position_at(&pass.to, end),
+                start,
+                end,
+                notes: notes(&pass.comments),
             };
-            interactions.push(Interaction::Pass(PassLine { from, to }));
+            interactions.push(match pass.kind {
+                PassKind::Pass => Interaction::Pass(line),
+                PassKind::Handoff => Interaction::Handoff(line),
+            });
         }
 
         // Screens
-        for screen in playbook.action.screens {
-            let from = *start_positions.get(&screen.player).unwrap_or(&(0.0, 0.0));
-            let to = match screen.timing {
-                Timing::Before => *start_positions.get(&screen.target).unwrap_or(&(0.0, 0.0)),
-                Timing::Middle => {
-                    let start = *start_positions.get(&screen.target).unwrap_or(&(0.0, 0.0));
-                    let end = *end_positions.get(&screen.target).unwrap_or(&(0.0, 0.0));
-                    ((start.0 + end.0) / 2.0, (start.1 + end.1) / 2.0)
-                }
-                Timing::After | Timing::None => {
-                    *end_positions.get(&screen.target).unwrap_or(&(0.0, 0.0))
-                }
-            };
+        for (i, screen) in action.screens.iter().enumerate() {
+            let (start, end) = schedule.screen(i);
             interactions.push(Interaction::Screen(ScreenLine {
-                screener_id: screen.player,
-                from,
-                to,
+                screener_id: screen.player.clone(),
+                from: *start_positions.get(&screen.player).unwrap_or(&(0.0, 0.0)),
+                to: position_at(&screen.target, start),
+                start,
+                end,
+                notes: notes(&screen.comments),
             }));
         }
 
+        // Shots: once the ball has arrived
+        for (i, shot) in action.shots.iter().enumerate() {
+            let (start, end) = schedule.shot(i);
+            interactions.push(Interaction::Shot(ShotLine {
+                shooter_id: shot.player.clone(),
+                from: position_at(&shot.player, start),
+                to: playbook.court().hoop_center(),
+                start,
+                end,
+                notes: notes(&shot.comments),
+            }));
+        }
+
+        // Defense
+        interactions.extend(defense_lines.into_iter().map(Interaction::Defense));
+
+        let duration = interactions
+            .iter()
+            .map(Interaction::end)
+            .fold(MOVE_BEATS, f64::max);
+
         Scene {
+            number: 1,
             entities,
             interactions,
+            duration,
+            court: playbook.court(),
+            view: playbook.view.unwrap_or_default(),
+            meta: playbook.meta.clone().unwrap_or_default(),
+        }
+    }
+}
+
+/// Resolves when each pass and screen of a phase happens.
+/// Passes are thrown in order: one can't leave before the previous one is caught.
+/// Timings may point at other events (`after p4.screen`); a cycle falls back to
+/// the end of the movement (the checker reports it).
+struct Schedule<'a> {
+    action: &'a Action,
+    passes: Vec<Option<(f64, f64)>>,
+    screens: Vec<Option<(f64, f64)>>,
+    resolving: HashSet<(EventKind, usize)>,
+}
+
+impl<'a> Schedule<'a> {
+    fn new(action: &'a Action) -> Self {
+        Self {
+            action,
+            passes: vec![None; action.passes.len()],
+            screens: vec![None; action.screens.len()],
+            resolving: HashSet::new(),
+        }
+    }
+
+    fn pass(&mut self, i: usize) -> (f64, f64) {
+        if let Some(times) = self.passes[i] {
+            return times;
+        }
+        if !self.resolving.insert((EventKind::Pass, i)) {
+            return (MOVE_BEATS, MOVE_BEATS + PASS_BEATS);
+        }
+        let throw = self.time(&self.action.passes[i].timing);
+        let earliest = if i > 0 { self.pass(i - 1).1 } else { 0.0 };
+        let start = throw.max(earliest);
+        self.resolving.remove(&(EventKind::Pass, i));
+
+        let times = (start, start + PASS_BEATS);
+        self.passes[i] = Some(times);
+        times
+    }
+
+    fn screen(&mut self, i: usize) -> (f64, f64) {
+        if let Some(times) = self.screens[i] {
+            return times;
+        }
+        if !self.resolving.insert((EventKind::Screen, i)) {
+            return (MOVE_BEATS, MOVE_BEATS + SCREEN_BEATS);
+        }
+        let start = self.time(&self.action.screens[i].timing);
+        self.resolving.remove(&(EventKind::Screen, i));
+
+        let times = (start, start + SCREEN_BEATS);
+        self.screens[i] = Some(times);
+        times
+    }
+
+    /// A shot waits for the last pass to be caught.
+    fn shot(&mut self, i: usize) -> (f64, f64) {
+        let release = self.time(&self.action.shots[i].timing);
+        let earliest = match self.action.passes.len() {
+            0 => 0.0,
+            n => self.pass(n - 1).1,
+        };
+        let start = release.max(earliest);
+        (start, start + SHOT_BEATS)
+    }
+
+    fn defense(&mut self, timing: &Timing) -> (f64, f64) {
+        let start = self.time(timing);
+        (start, start + DEFENSE_BEATS)
+    }
+
+    fn time(&mut self, timing: &Timing) -> f64 {
+        match timing {
+            Timing::Before => 0.0,
+            Timing::Middle => MOVE_BEATS / 2.0,
+            Timing::After | Timing::None => MOVE_BEATS,
+            Timing::Fraction(fraction) => fraction * MOVE_BEATS,
+            Timing::BeforeEvent(event) => self.event(event).map_or(MOVE_BEATS, |(start, _)| start),
+            Timing::AfterEvent(event) => self.event(event).map_or(MOVE_BEATS, |(_, end)| end),
+        }
+    }
+
+    /// Start and end of the first matching event in this phase.
+    fn event(&mut self, event: &EventRef) -> Option<(f64, f64)> {
+        let action = self.action;
+        match event.kind {
+            EventKind::Move => action
+                .moves
+                .iter()
+                .any(|m| m.player == event.player)
+                .then_some((0.0, MOVE_BEATS)),
+            EventKind::Screen => {
+                let i = action
+                    .screens
+                    .iter()
+                    .position(|s| s.player == event.player)?;
+                Some(self.screen(i))
+            }
+            EventKind::Pass => {
+                let i = action.passes.iter().position(|p| p.from == event.player)?;
+                Some(self.pass(i))
+            }
+        }
+    }
+}
+
+/// Where everyone starts, moved by the play's `transform`.
+/// A position can refer to other players' positions, so those are placed first.
+fn initial_positions(playbook: &Playbook) -> HashMap<String, (f64, f64)> {
+    let transform = Affine::new(&playbook.court(), &playbook.transform);
+    let mut placed = HashMap::new();
+    let mut players: Vec<&String> = playbook.state.positions.keys().collect();
+    players.sort();
+    for player in players {
+        place(playbook, &transform, player, &mut placed, &mut Vec::new());
+    }
+    placed
+}
+
+fn place(
+    playbook: &Playbook,
+    transform: &Affine,
+    player: &str,
+    placed: &mut HashMap<String, (f64, f64)>,
+    visiting: &mut Vec<String>,
+) {
+    let Some(point) = playbook.state.positions.get(player) else {
+        return;
+    };
+    // Cycles are reported by the checker
+    if placed.contains_key(player) || visiting.iter().any(|p| p == player) {
+        return;
+    }
+    visiting.push(player.to_string());
+    for (name, _) in point.names() {
+        place(playbook, transform, name, placed, visiting);
+    }
+    visiting.pop();
+    let position = evaluate(playbook, placed, transform, point, (0.0, 0.0));
+    placed.insert(player.to_string(), position);
+}
+
+/// The coordinates of `point` at the start of a phase, `from` being the previous point of the path.
+/// Player names stand for where they are in `positions`; court locations are moved by `transform`.
+fn evaluate(
+    playbook: &Playbook,
+    positions: &HashMap<String, (f64, f64)>,
+    transform: &Affine,
+    point: &Point,
+    from: (f64, f64),
+) -> (f64, f64) {
+    match point {
+        Point::Coord(x, y) => transform.point(playbook.court().to_diagram((*x, *y))),
+        // Unknown names are reported by the checker
+        Point::Name(name, _) => positions
+            .get(name)
+            .copied()
+            .or_else(|| playbook.spot(name).map(|spot| transform.point(spot)))
+            .unwrap_or((0.0, 0.0)),
+        Point::Offset(base, (dx, dy)) => {
+            let court = playbook.court();
+            let (x, y) = evaluate(playbook, positions, transform, base, from);
+            let (dx, dy) = transform.vector((court.length(*dx), court.length(*dy)));
+            (x + dx, y + dy)
+        }
+        Point::Toward(target, length, _) => {
+            let target = evaluate(playbook, positions, transform, target, from);
+            let length = playbook.court().length(*length);
+            let len = distance(from, target);
+            // Stop at the target rather than run through it
+            if len <= length {
+                target
+            } else {
+                lerp(from, target, length / len)
+            }
+        }
+        Point::Polar(distance, angle) => {
+            let court = playbook.court();
+            let (x, y) = court.hoop_center();
+            let (distance, angle) = (court.length(*distance), angle.to_radians());
+            transform.point((x + distance * angle.sin(), y + distance * angle.cos()))
+        }
+    }
+}
+
+/// A `transform` in drawing coordinates: `(x, y)` becomes `matrix * (x, y) + offset`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+struct Affine {
+    /// Row by row.
+    matrix: [f64; 4],
+    offset: (f64, f64),
+}
+
+impl Affine {
+    const IDENTITY: Affine = Affine {
+        matrix: [1.0, 0.0, 0.0, 1.0],
+        offset: (0.0, 0.0),
+    };
+
+    /// `steps` one after the other. Mirroring is about the center line,
+    /// rotation about the hoop, and translation is in the court's units.
+    fn new(court: &CourtSpec, steps: &[Transform]) -> Self {
+        steps.iter().fold(Self::IDENTITY, |affine, step| {
+            let step = match *step {
+                Transform::Mirror => Affine {
+                    matrix: [-1.0, 0.0, 0.0, 1.0],
+                    offset: (0.0, 0.0),
+                },
+                Transform::Rotate(angle) => {
+                    let (sin, cos) = angle.to_radians().sin_cos();
+                    let (x, y) = court.hoop_center();
+                    // Straight out turns to the right, as with `polar`
+                    let rotation = Affine {
+                        matrix: [cos, sin, -sin, cos],
+                        offset: (0.0, 0.0),
+                    };
+                    let to_hoop = Affine {
+                        offset: (-x, -y),
+                        ..Self::IDENTITY
+                    };
+                    let back = Affine {
+                        offset: (x, y),
+                        ..Self::IDENTITY
+                    };
+                    to_hoop.then(rotation).then(back)
+                }
+                Transform::Translate(dx, dy) => Affine {
+                    offset: (court.length(dx), court.length(dy)),
+                    ..Self::IDENTITY
+                },
+            };
+            affine.then(step)
+        })
+    }
+
+    /// This transform followed by `next`.
+    fn then(self, next: Affine) -> Affine {
+        let [a, b, c, d] = next.matrix;
+        let [e, f, g, h] = self.matrix;
+        Affine {
+            matrix: [a * e + b * g, a * f + b * h, c * e + d * g, c * f + d * h],
+            offset: next.point(self.offset),
         }
     }
+
+    fn point(&self, point: (f64, f64)) -> (f64, f64) {
+        let (x, y) = self.vector(point);
+        (x + self.offset.0, y + self.offset.1)
+    }
+
+    /// A direction or distance, which translation leaves alone.
+    fn vector(&self, (x, y): (f64, f64)) -> (f64, f64) {
+        let [a, b, c, d] = self.matrix;
+        (a * x + b * y, c * x + d * y)
+    }
+}
+
+/// One segment per waypoint: straight, bent towards its `via` point, or smoothed
+/// through the neighbouring waypoints (Catmull-Rom) when the move is a `curve`.
+fn path_segments(
+    from: (f64, f64),
+    waypoints: &[ResolvedWaypoint],
+    curve: bool,
+) -> Vec<PathSegment> {
+    let mut points = vec![from];
+    points.extend(waypoints.iter().map(|(point, _)| *point));
+    let smooth = curve && waypoints.len() > 1;
+
+    waypoints
+        .iter()
+        .enumerate()
+        .map(|(i, (_, via))| {
+            let (p1, p2) = (points[i], points[i + 1]);
+            if let Some(control) = via {
+                PathSegment::Quad {
+                    control: *control,
+                    to: p2,
+                }
+            } else if smooth {
+                let p0 = points[i.saturating_sub(1)];
+                let p3 = *points.get(i + 2).unwrap_or(&p2);
+                PathSegment::Cubic {
+                    c1: (p1.0 + (p2.0 - p0.0) / 6.0, p1.1 + (p2.1 - p0.1) / 6.0),
+                    c2: (p2.0 - (p3.0 - p1.0) / 6.0, p2.1 - (p3.1 - p1.1) / 6.0),
+                    to: p2,
+                }
+            } else {
+                PathSegment::Line(p2)
+            }
+        })
+        .collect()
+}
+
+/// The point `DEFENSE_GAP` short of `target`, coming from `from`.
+fn approach(from: (f64, f64), target: (f64, f64)) -> (f64, f64) {
+    let (dx, dy) = (from.0 - target.0, from.1 - target.1);
+    let len = (dx * dx + dy * dy).sqrt();
+    if len <= DEFENSE_GAP {
+        return from;
+    }
+    (
+        target.0 + dx / len * DEFENSE_GAP,
+        target.1 + dy / len * DEFENSE_GAP,
+    )
+}
+
+fn notes(comments: &[Comment]) -> Vec<String> {
+    comments
+        .iter()
+        .map(|comment| comment.text.clone())
+        .collect()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::ast::*;
-    use std::collections::HashMap;
+    use crate::lexer::Span;
 
     #[test]
     fn test_ir_generation() {
         let mut positions = HashMap::new();
-        positions.insert("p1".to_string(), (0.0, 0.0));
-        positions.insert("p2".to_string(), (10.0, 10.0));
+        positions.insert("p1".to_string(), Point::Coord(0.0, 0.0));
+        positions.insert("p2".to_string(), Point::Coord(10.0, 10.0));
 
         let playbook = Playbook {
             players: vec!["p1".to_string(), "p2".to_string()],
             state: State {
                 baller: Some("p1".to_string()),
                 positions,
+                ..Default::default()
             },
             action: Action {
                 moves: vec![MoveAction {
+                    kind: MoveKind::Move,
                     player: "p2".to_string(),
-                    target: (20.0, 20.0),
+                    path: vec![Waypoint {
+                        point: Point::Coord(20.0, 20.0),
+                        via: None,
+                    }],
+                    curve: false,
+                    span: Span::default(),
+                    comments: Vec::new(),
                 }],
                 passes: vec![PassAction {
+                    kind: PassKind::Pass,
                     from: "p1".to_string(),
                     to: "p2".to_string(),
                     timing: Timing::After,
+                    span: Span::default(),
+                    to_span: Span::default(),
+                    comments: Vec::new(),
                 }],
                 ..Default::default()
             },
+            ..Default::default()
         };
 
-        let scene = IRGenerator::generate(playbook);
+        let scenes = IRGenerator::generate(playbook);
+        assert_eq!(scenes.len(), 1);
+        let scene = &scenes[0];
 
         assert_eq!(scene.entities.len(), 2);
         let p2_entity = scene.entities.iter().find(|e| e.id == "p2").unwrap();
@@ -134,4 +651,329 @@ mod tests {
             panic!("Expected Pass interaction");
         }
     }
+
+    #[test]
+    fn test_phases_chain_end_state() {
+        let mut positions = HashMap::new();
+        positions.insert("p1".to_string(), Point::Coord(0.0, 60.0));
+        positions.insert("p2".to_string(), Point::Coord(90.0, -80.0));
+
+        let playbook = Playbook {
+            players: vec!["p1".to_string(), "p2".to_string()],
+            state: State {
+                baller: Some("p1".to_string()),
+                positions,
+                ..Default::default()
+            },
+            phases: vec![
+                Phase {
+                    number: 1,
+                    action: Action {
+                        moves: vec![MoveAction {
+                            kind: MoveKind::Move,
+                            player: "p2".to_string(),
+                            path: vec![Waypoint {
+                                point: Point::Coord(70.0, 20.0),
+                                via: None,
+                            }],
+                            curve: false,
+                            span: Span::default(),
+                            comments: Vec::new(),
+                        }],
+                        passes: vec![PassAction {
+                            kind: PassKind::Pass,
+                            from: "p1".to_string(),
+                            to: "p2".to_string(),
+                            timing: Timing::After,
+                            span: Span::default(),
+                            to_span: Span::default(),
+                            comments: Vec::new(),
+                        }],
+                        ..Default::default()
+                    },
+                    span: Span::default(),
+                    comments: Vec::new(),
+                },
+                Phase {
+                    number: 2,
+                    action: Action {
+                        moves: vec![MoveAction {
+                            kind: MoveKind::Move,
+                            player: "p1".to_string(),
+                            path: vec![Waypoint {
+                                point: Point::Coord(0.0, 0.0),
+                                via: None,
+                            }],
+                            curve: false,
+                            span: Span::default(),
+                            comments: Vec::new(),
+                        }],
+                        ..Default::default()
+                    },
+                    span: Span::default(),
+                    comments: Vec::new(),
+                },
+            ],
+            ..Default::default()
+        };
+
+        let scenes = IRGenerator::generate(playbook);
+        assert_eq!(scenes.len(), 2);
+
+        let p2 = scenes[1].entities.iter().find(|e| e.id == "p2").unwrap();
+        assert_eq!(p2.start_pos, (70.0, 20.0));
+        assert_eq!(p2.end_pos, (70.0, 20.0));
+        assert!(p2.is_baller);
+
+        let p1 = scenes[1].entities.iter().find(|e| e.id == "p1").unwrap();
+        assert_eq!(p1.start_pos, (0.0, 60.0));
+        assert_eq!(p1.end_pos, (0.0, 0.0));
+        assert!(!p1.is_baller);
+    }
+
+    #[test]
+    fn test_timeline() {
+        let input = r#"
+            players = { p1, p2, p4 }
+            state = { baller = p1, position = { p1 = (0, 60), p2 = (90, -80), p4 = (50, -10) } }
+            action = {
+                move = { p2 -> (70, 20) },
+                screen = { p4 -> p2:0.5 },
+                pass = { p1 -> p2:after p4.screen, p2 -> p1 },
+            }
+        "#;
+        let tokens = crate::lexer::Lexer::new(input).tokenize();
+        let playbook = crate::parser::Parser::new(tokens).parse().unwrap();
+        let scene = &IRGenerator::generate(playbook)[0];
+
+        assert_eq!(scene.position_at("p2", 0.5), Some((80.0, -30.0)));
+        let screen = scene
+            .interactions
+            .iter()
+            .find_map(|interaction| match interaction {
+                Interaction::Screen(screen) => Some(screen),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!((screen.start, screen.to), (0.5, (80.0, -30.0)));
+
+        // Thrown when the screen is released, caught where p2 finished
+        let passes: Vec<&PassLine> = scene.passes().collect();
+        assert_eq!((passes[0].start, passes[0].end), (0.75, 1.0));
+        assert_eq!(passes[0].to, (70.0, 20.0));
+        // The second pass waits for the first to be caught
+        assert_eq!((passes[1].start, passes[1].end), (1.0, 1.25));
+        assert_eq!(scene.duration, 1.25);
+        assert_eq!(scene.ball_at(1.125), Some((35.0, 40.0)));
+    }
+
+    #[test]
+    fn test_switch_swaps_matchups() {
+        let input = r#"
+            players = { p1, p5 }
+            defense = { x1, x5 }
+            state = {
+                baller = p1,
+                position = { p1 = (0, 60), p5 = (0, 0), x1 = (0, 40), x5 = (20, 0) },
+                guards = { x1 -> p1, x5 -> p5 },
+            }
+            phase 1 = { switch = { x1 -> x5:before } }
+            phase 2 = { help = { x5 -> p5 } }
+        "#;
+        let tokens = crate::lexer::Lexer::new(input).tokenize();
+        let playbook = crate::parser::Parser::new(tokens).parse().unwrap();
+        let scenes = IRGenerator::generate(playbook);
+
+        let x1 = scenes[0].entities.iter().find(|e| e.id == "x1").unwrap();
+        assert_eq!(x1.team, Team::Defense);
+        assert_eq!(x1.label, "1");
+        assert_eq!(x1.guarding.as_deref(), Some("p5"));
+        assert_eq!(x1.end_pos, (0.0, 12.0));
+        let x5 = scenes[0].entities.iter().find(|e| e.id == "x5").unwrap();
+        assert_eq!(x5.guarding.as_deref(), Some("p1"));
+        assert_eq!(scenes[0].duration, MOVE_BEATS);
+        assert_eq!(scenes[0].position_at("x1", 0.25), Some((0.0, 26.0)));
+
+        // Matchups carry over to the next phase
+        let x5 = scenes[1].entities.iter().find(|e| e.id == "x5").unwrap();
+        assert_eq!(x5.guarding.as_deref(), Some("p1"));
+        assert_eq!(scenes[1].duration, MOVE_BEATS + DEFENSE_BEATS);
+    }
+
+    #[test]
+    fn test_ball_actions() {
+        let input = r#"
+            players = { p1, p2 }
+            state = { baller = p1, position = { p1 = (0, 60), p2 = (40, 40) } }
+            phase 1 = {
+                dribble = { p1 -> (20, 40) },
+                cut = { p2 -> (30, 50) },
+                handoff = { p1 -> p2:after },
+                shot = { p2 },
+            }
+            phase 2 = { cut = { p1 -> (0, 0) } }
+        "#;
+        let tokens = crate::lexer::Lexer::new(input).tokenize();
+        let playbook = crate::parser::Parser::new(tokens).parse().unwrap();
+        let scenes = IRGenerator::generate(playbook);
+
+        let kinds: Vec<&str> = scenes[0]
+            .interactions
+            .iter()
+            .map(|interaction| match interaction {
+                Interaction::Dribble(_) => "dribble",
+                Interaction::Cut(_) => "cut",
+                Interaction::Handoff(_) => "handoff",
+                Interaction::Shot(_) => "shot",
+                _ => "other",
+            })
+            .collect();
+        assert_eq!(kinds, vec!["dribble", "cut", "handoff", "shot"]);
+
+        // The shot goes up once the handoff is done
+        let shot = scenes[0].shot().unwrap();
+        assert_eq!((shot.start, shot.end), (1.25, 1.75));
+        assert_eq!(
+            (shot.from, shot.to),
+            ((30.0, 50.0), CourtSpec::CLASSIC.hoop_center())
+        );
+        assert_eq!(scenes[0].ball_at(1.125), Some((25.0, 45.0)));
+        assert_eq!(
+            scenes[0].ball_at(2.0),
+            Some(CourtSpec::CLASSIC.hoop_center())
+        );
+
+        // Nobody has the ball after the shot
+        assert!(scenes[1].entities.iter().all(|e| !e.is_baller));
+        assert_eq!(scenes[1].ball_at(0.0), None);
+    }
+
+    #[test]
+    fn test_paths() {
+        let input = r#"
+            players = { p1, p2, p3 }
+            state = { position = { p1 = (0, 0), p2 = (0, 0), p3 = (0, 0) } }
+            action = {
+                move = { p1 -> (40, 0) -> (40, 40) },
+                cut = { p2 -> (40, 0) via (20, 20) },
+                move = { p3 -> (40, 0) -> (40, 40) curve },
+            }
+        "#;
+        let tokens = crate::lexer::Lexer::new(input).tokenize();
+        let playbook = crate::parser::Parser::new(tokens).parse().unwrap();
+        let scene = &IRGenerator::generate(playbook)[0];
+        let path = |id: &str| {
+            scene
+                .interactions
+                .iter()
+                .find_map(|interaction| match interaction {
+                    Interaction::Move(m) | Interaction::Cut(m) if m.player_id == id => {
+                        Some(m.path.clone())
+                    }
+                    _ => None,
+                })
+                .unwrap()
+        };
+
+        assert_eq!(
+            path("p1"),
+            vec![
+                PathSegment::Line((40.0, 0.0)),
+                PathSegment::Line((40.0, 40.0))
+            ]
+        );
+        assert_eq!(
+            path("p2"),
+            vec![PathSegment::Quad {
+                control: (20.0, 20.0),
+                to: (40.0, 0.0)
+            }]
+        );
+        assert!(matches!(
+            path("p3").as_slice(),
+            [PathSegment::Cubic { .. }, PathSegment::Cubic { .. }]
+        ));
+
+        // Players walk the path at an even pace and end on the last waypoint
+        assert_eq!(scene.position_at("p1", 0.5), Some((40.0, 0.0)));
+        assert_eq!(scene.position_at("p1", 0.75), Some((40.0, 20.0)));
+        let p2 = scene.entities.iter().find(|e| e.id == "p2").unwrap();
+        assert_eq!(p2.end_pos, (40.0, 0.0));
+    }
+
+    #[test]
+    fn test_relative_points() {
+        let input = r#"
+            players = { p1, p2, p3 }
+            spots = { deep = (0, 80) }
+            state = { position = { p1 = p3 + (10, 0), p2 = left_wing + (0, -5), p3 = deep } }
+            phase 1 = {
+                move = { p1 -> toward(basket, 20) },
+                cut = { p2 -> polar(30, 90) -> toward(p3, 10) },
+            }
+            phase 2 = { move = { p3 -> p1 + (0, 10) } }
+        "#;
+        let tokens = crate::lexer::Lexer::new(input).tokenize();
+        let playbook = crate::parser::Parser::new(tokens).parse().unwrap();
+        let scenes = IRGenerator::generate(playbook);
+        let entity = |scene: usize, id: &str| {
+            let entity = scenes[scene].entities.iter().find(|e| e.id == id).unwrap();
+            (entity.start_pos, entity.end_pos)
+        };
+
+        assert_eq!(entity(0, "p1").0, (10.0, 80.0));
+        assert_eq!(entity(0, "p2").0, (-62.0, 22.0));
+        // 20 units from (10, 80) towards the hoop at (0, -84)
+        let (x, y) = entity(0, "p1").1;
+        assert!(((x - 10.0).powi(2) + (y - 80.0).powi(2)).sqrt() - 20.0 < 1e-9);
+        assert!(x < 10.0 && y < 80.0);
+        // Polar points are around the hoop; `toward` starts from the previous waypoint
+        let cut = scenes[0]
+            .interactions
+            .iter()
+            .find_map(|interaction| match interaction {
+                Interaction::Cut(m) => Some(m),
+                _ => None,
+            })
+            .unwrap();
+        let corner = cut.path[0].point((0.0, 0.0), 1.0);
+        assert!((corner.0 - 30.0).abs() < 1e-9 && (corner.1 + 84.0).abs() < 1e-9);
+        let expected = lerp(corner, (0.0, 80.0), 10.0 / distance(corner, (0.0, 80.0)));
+        assert_eq!(cut.to, expected);
+        // Players stand for where they start the phase
+        assert_eq!(
+            entity(1, "p3").1,
+            (entity(1, "p1").0.0, entity(1, "p1").0.1 + 10.0)
+        );
+    }
+
+    #[test]
+    fn test_transforms() {
+        let input = r#"
+            players = { p1, p2 }
+            transform = { mirror }
+            state = { position = { p1 = (20, 60), p2 = left_wing } }
+            phase 1 = { move = { p1 -> p2 + (10, 0) } }
+            phase 2 = {
+                transform = { rotate = 90, translate = (0, 10) },
+                move = { p2 -> polar(30, 0) },
+            }
+        "#;
+        let tokens = crate::lexer::Lexer::new(input).tokenize();
+        let playbook = crate::parser::Parser::new(tokens).parse().unwrap();
+        let wing = playbook.spot("right_wing").unwrap();
+        let scenes = IRGenerator::generate(playbook);
+        let entity = |scene: usize, id: &str| {
+            let entity = scenes[scene].entities.iter().find(|e| e.id == id).unwrap();
+            (entity.start_pos, entity.end_pos)
+        };
+
+        // Mirrored: the left wing is the right wing, and offsets point the other way
+        assert_eq!(entity(0, "p1").0, (-20.0, 60.0));
+        assert_eq!(entity(0, "p2").0, wing);
+        assert_eq!(entity(0, "p1").1, (wing.0 - 10.0, wing.1));
+        // Phase 2 turns straight out to the right, then the play mirrors it to the left
+        let (x, y) = entity(1, "p2").1;
+        assert!((x + 30.0).abs() < 1e-9 && (y + 74.0).abs() < 1e-9);
+    }
 }
@@ -1,10 +1,111 @@
 pub mod generator;
+pub use crate::ast::{DefenseKind, Meta};
+pub use crate::court::{CourtSpec, CourtView};
 pub use generator::IRGenerator;
 
+// Time in a scene is measured in beats from the start of the phase.
+/// Moves run over the first beat.
+pub const MOVE_BEATS: f64 = 1.0;
+/// Time a pass is in the air.
+pub const PASS_BEATS: f64 = 0.25;
+/// Time a screen is held once set.
+pub const SCREEN_BEATS: f64 = 0.25;
+/// Time a defender takes to hedge, help, trap, close out or switch.
+pub const DEFENSE_BEATS: f64 = 0.5;
+/// Time the ball is in the air on a shot.
+pub const SHOT_BEATS: f64 = 0.5;
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct Scene {
+    /// The phase number as written; 1 for a play without phases.
+    pub number: u32,
     pub entities: Vec<Entity>,
     pub interactions: Vec<Interaction>,
+    /// Length of the phase in beats: the movement plus any pass or screen ending later.
+    pub duration: f64,
+    /// The court the phase is drawn on.
+    pub court: CourtSpec,
+    pub view: CourtView,
+    /// Name and notes of the whole play.
+    pub meta: Meta,
+}
+
+impl Scene {
+    /// Where `player_id` is `t` beats into the phase.
+    pub fn position_at(&self, player_id: &str, t: f64) -> Option<(f64, f64)> {
+        let entity = self.entities.iter().find(|e| e.id == player_id)?;
+        let progress = |start: f64, end: f64| {
+            if end > start {
+                ((t - start) / (end - start)).clamp(0.0, 1.0)
+            } else if t < start {
+                0.0
+            } else {
+                1.0
+            }
+        };
+        let position = self
+            .interactions
+            .iter()
+            .find_map(|interaction| match interaction {
+                Interaction::Move(m) | Interaction::Dribble(m) | Interaction::Cut(m)
+                    if m.player_id == player_id =>
+                {
+                    Some(m.point_at(progress(m.start, m.end)))
+                }
+                Interaction::Defense(d) if d.defender_id == player_id => {
+                    Some(lerp(d.from, d.to, progress(d.start, d.end)))
+                }
+                _ => None,
+            });
+        Some(position.unwrap_or(entity.start_pos))
+    }
+
+    /// Where the ball is `t` beats into the phase: with its holder, in the air,
+    /// or at the hoop after a shot. `None` if nobody has the ball.
+    pub fn ball_at(&self, t: f64) -> Option<(f64, f64)> {
+        let mut holder = &self.entities.iter().find(|e| e.is_baller)?.id;
+        for pass in self.passes() {
+            if t < pass.start {
+                break;
+            }
+            if t < pass.end {
+                let progress = (t - pass.start) / (pass.end - pass.start);
+                return Some(lerp(pass.from, pass.to, progress));
+            }
+            holder = &pass.receiver_id;
+        }
+        if let Some(shot) = self.shot()
+            && t >= shot.start
+        {
+            let progress = ((t - shot.start) / (shot.end - shot.start)).clamp(0.0, 1.0);
+            return Some(lerp(shot.from, shot.to, progress));
+        }
+        self.position_at(holder, t)
+    }
+
+    /// Passes and handoffs in the order they are thrown.
+    pub fn passes(&self) -> impl Iterator<Item = &PassLine> {
+        self.interactions
+            .iter()
+            .filter_map(|interaction| match interaction {
+                Interaction::Pass(pass) | Interaction::Handoff(pass) => Some(pass),
+                _ => None,
+            })
+    }
+
+    /// The shot ending the possession, if any.
+    pub fn shot(&self) -> Option<&ShotLine> {
+        self.interactions
+            .iter()
+            .find_map(|interaction| match interaction {
+                Interaction::Shot(shot) => Some(shot),
+                _ => None,
+            })
+    }
+}
+
+pub fn lerp(a: (f64, f64), b: (f64, f64), t: f64) -> (f64, f64) {
+    (a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t)
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -14,13 +115,53 @@ pub struct Entity {
     pub start_pos: (f64, f64),
     pub end_pos: (f64, f64),
     pub is_baller: bool,
+    pub team: Team,
+    /// The offensive player a defender is guarding at the end of the phase.
+    pub guarding: Option<String>,
+    /// Comments from the source, shown as tooltips.
+    pub notes: Vec<String>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Team {
+    Offense,
+    Defense,
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Interaction {
     Move(MoveLine),
+    Dribble(MoveLine),
+    Cut(MoveLine),
     Pass(PassLine),
+    Handoff(PassLine),
     Screen(ScreenLine),
+    Shot(ShotLine),
+    Defense(DefenseLine),
+}
+
+impl Interaction {
+    /// Beat at which the interaction is over.
+    pub fn end(&self) -> f64 {
+        match self {
+            Interaction::Move(m) | Interaction::Dribble(m) | Interaction::Cut(m) => m.end,
+            Interaction::Pass(p) | Interaction::Handoff(p) => p.end,
+            Interaction::Screen(s) => s.end,
+            Interaction::Shot(s) => s.end,
+            Interaction::Defense(d) => d.end,
+        }
+    }
+
+    /// Points the drawing of the interaction goes through.
+    pub fn points(&self) -> Vec<(f64, f64)> {
+        match self {
+            Interaction::Move(m) | Interaction::Dribble(m) | Interaction::Cut(m) => m.points(),
+            Interaction::Pass(p) | Interaction::Handoff(p) => vec![p.from, p.to],
+            Interaction::Screen(s) => vec![s.from, s.to],
+            Interaction::Shot(s) => vec![s.from, s.to],
+            Interaction::Defense(d) => vec![d.from, d.to],
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -28,17 +169,159 @@ pub struct MoveLine {
     pub player_id: String,
     pub from: (f64, f64),
     pub to: (f64, f64),
+    /// The way from `from` to `to`, one segment per waypoint.
+    pub path: Vec<PathSegment>,
+    pub start: f64,
+    pub end: f64,
+    pub notes: Vec<String>,
+}
+
+/// Points per curved segment when a path is flattened.
+const CURVE_SAMPLES: usize = 16;
+
+impl MoveLine {
+    /// A plain `<line>`: one straight segment.
+    pub fn is_straight(&self) -> bool {
+        matches!(self.path.as_slice(), [PathSegment::Line(_)])
+    }
+
+    /// The path flattened into a polyline, starting at `from`.
+    pub fn points(&self) -> Vec<(f64, f64)> {
+        let mut points = vec![self.from];
+        for segment in &self.path {
+            let start = *points.last().unwrap_or(&self.from);
+            match segment {
+                PathSegment::Line(to) => points.push(*to),
+                _ => points.extend(
+                    (1..=CURVE_SAMPLES)
+                        .map(|i| segment.point(start, i as f64 / CURVE_SAMPLES as f64)),
+                ),
+            }
+        }
+        points
+    }
+
+    pub fn length(&self) -> f64 {
+        self.points()
+            .windows(2)
+            .map(|pair| distance(pair[0], pair[1]))
+            .sum()
+    }
+
+    /// The point `distance` along the path and the direction of travel there.
+    pub fn walk(&self, distance_along: f64) -> ((f64, f64), (f64, f64)) {
+        let points = self.points();
+        let mut left = distance_along.max(0.0);
+        let mut direction = (0.0, 1.0);
+        for pair in points.windows(2) {
+            let len = distance(pair[0], pair[1]);
+            if len < 1e-9 {
+                continue;
+            }
+            direction = ((pair[1].0 - pair[0].0) / len, (pair[1].1 - pair[0].1) / len);
+            if left <= len {
+                return (lerp(pair[0], pair[1], left / len), direction);
+            }
+            left -= len;
+        }
+        (*points.last().unwrap_or(&self.from), direction)
+    }
+
+    /// The point `progress` (0.0 to 1.0) of the way along the path, by distance.
+    pub fn point_at(&self, progress: f64) -> (f64, f64) {
+        self.walk(progress * self.length()).0
+    }
+}
+
+/// A piece of a movement path, starting where the previous one ended.
+#[derive(Debug, PartialEq, Clone)]
+pub enum PathSegment {
+    Line((f64, f64)),
+    /// `via`: bends towards `control` without passing through it.
+    Quad {
+        control: (f64, f64),
+        to: (f64, f64),
+    },
+    /// `curve`: a smooth corner through the waypoints.
+    Cubic {
+        c1: (f64, f64),
+        c2: (f64, f64),
+        to: (f64, f64),
+    },
+}
+
+impl PathSegment {
+    /// The point at `t` (0.0 to 1.0) of a segment starting at `from`.
+    pub fn point(&self, from: (f64, f64), t: f64) -> (f64, f64) {
+        match self {
+            PathSegment::Line(to) => lerp(from, *to, t),
+            PathSegment::Quad { control, to } => {
+                lerp(lerp(from, *control, t), lerp(*control, *to, t), t)
+            }
+            PathSegment::Cubic { c1, c2, to } => {
+                let a = lerp(from, *c1, t);
+                let b = lerp(*c1, *c2, t);
+                let c = lerp(*c2, *to, t);
+                lerp(lerp(a, b, t), lerp(b, c, t), t)
+            }
+        }
+    }
+}
+
+fn distance(a: (f64, f64), b: (f64, f64)) -> f64 {
+    ((b.0 - a.0).powi(2) + (b.1 - a.1).powi(2)).sqrt()
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct PassLine {
+    pub passer_id: String,
+    pub receiver_id: String,
+    /// Passer at the throw.
     pub from: (f64, f64),
+    /// Receiver at the catch.
     pub to: (f64, f64),
+    /// Beat the ball is thrown.
+    pub start: f64,
+    /// Beat the ball is caught.
+    pub end: f64,
+    pub notes: Vec<String>,
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct ScreenLine {
     pub screener_id: String,
     pub from: (f64, f64),
+    /// Where the screened player is when the screen is set.
+    pub to: (f64, f64),
+    /// Beat the screen is set.
+    pub start: f64,
+    /// Beat the screen is released.
+    pub end: f64,
+    pub notes: Vec<String>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ShotLine {
+    pub shooter_id: String,
+    /// Shooter at the release.
+    pub from: (f64, f64),
+    /// The hoop.
+    pub to: (f64, f64),
+    pub start: f64,
+    pub end: f64,
+    pub notes: Vec<String>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct DefenseLine {
+    pub defender_id: String,
+    /// The player reacted to: for a switch, the new man.
+    pub target_id: String,
+    pub kind: DefenseKind,
+    pub from: (f64, f64),
+    /// Where the defender stops, just short of the target.
     pub to: (f64, f64),
+    pub start: f64,
+    pub end: f64,
+    pub notes: Vec<String>,
 }
@@ -1,4 +1,6 @@
-#[derive(Debug, PartialEq, Clone, Copy)]
+use serde::Serialize;
+
+#[derive(Debug, PartialEq, Clone, Copy, Default, Serialize)]
 pub struct Span {
     pub start: usize,
     pub end: usize,
@@ -23,13 +25,40 @@ pub enum TokenKind {
     Move,
     Screen,
     Pass,
+    Dribble,
+    Cut,
+    Handoff,
+    Shot,
+    Curve,
+    Via,
     Before,
     After,
     Middle,
+    Phase,
+    Defense,
+    Guards,
+    Switch,
+    Hedge,
+    Help,
+    Trap,
+    Closeout,
+    Spots,
+    Toward,
+    Polar,
+    Court,
+    Units,
+    View,
+    Meta,
+    Import,
+    Use,
+    Template,
+    Transform,
 
     // Identifiers & Values
     Identifier(String),
     Number(f64), // Coordinates can be numbers
+    /// `"Horns Flare"`, with escapes resolved.
+    String(String),
 
     // Symbols
     Equals,       // =
@@ -37,12 +66,17 @@ pub enum TokenKind {
     RBrace,       // }
     LParenthesis, // (
     RParenthesis, // )
+    LBracket,     // [
+    RBracket,     // ]
     Comma,        // ,
     Arrow,        // ->
     Colon,        // :
+    Dot,          // .
+    Plus,         // +
 
     // Special
     Comment(String),
+    BlockComment(String),
     EOF,
 }
 
@@ -132,6 +166,53 @@ impl<'a> Lexer<'a> {
         self.input[start..self.pos].trim().to_string()
     }
 
+    /// Reads `/* ... */`. Returns `None` (consuming only `/*`) if it is never closed.
+    fn read_block_comment(&mut self) -> Option<String> {
+        let end = self.input[self.pos + 2..].find("*/")?;
+        let content = self.input[self.pos + 2..self.pos + 2 + end]
+            .trim()
+            .to_string();
+        while !self.starts_with("*/") {
+            self.advance();
+        }
+        self.advance();
+        self.advance();
+        Some(content)
+    }
+
+    /// Reads `"..."`, where `\"`, `\\` and `\n` are escapes.
+    /// Returns `None` (consuming only the `"`) if it is never closed.
+    fn read_string(&mut self) -> Option<String> {
+        let mut content = String::new();
+        let mut chars = self.input[self.pos + 1..].chars();
+        let mut len = 1;
+        loop {
+            let c = chars.next()?;
+            len += c.len_utf8();
+            match c {
+                '"' => break,
+                '\\' => {
+                    let escaped = chars.next()?;
+                    len += escaped.len_utf8();
+                    match escaped {
+                        'n' => content.push('\n'),
+                        '"' | '\\' => content.push(escaped),
+                        _ => {
+                            content.push('\\');
+                            content.push(escaped);
+                        }
+                    }
+                }
+                _ => content.push(c),
+            }
+        }
+        let end = self.pos + len;
+        while self.pos < end {
+            self.advance();
+        }
+        Some(content)
+    }
+
     pub fn next_token(&mut self) -> Token {
         self.skip_whitespace();
 
@@ -175,14 +256,37 @@ impl<'a> Lexer<'a> {
                 self.advance();
                 TokenKind::RParenthesis
             }
+            '[' => {
+                self.advance();
+                TokenKind::LBracket
+            }
+            ']' => {
+                self.advance();
+                TokenKind::RBracket
+            }
             ',' => {
                 self.advance();
                 TokenKind::Comma
             }
+            '"' => match self.read_string() {
+                Some(content) => TokenKind::String(content),
+                None => {
+                    self.advance();
+                    TokenKind::Identifier("\"".to_string())
+                }
+            },
             ':' => {
                 self.advance();
                 TokenKind::Colon
             }
+            '.' => {
+                self.advance();
+                TokenKind::Dot
+            }
+            '+' => {
+                self.advance();
+                TokenKind::Plus
+            }
             '-' => {
                 if self.starts_with("->") {
                     self.advance();
@@ -207,6 +311,15 @@ impl<'a> Lexer<'a> {
             '/' => {
                 if self.starts_with("//") {
                     TokenKind::Comment(self.read_comment())
+                } else if self.starts_with("/*") {
+                    match self.read_block_comment() {
+                        Some(content) => TokenKind::BlockComment(content),
+                        None => {
+                            self.advance();
+                            self.advance();
+                            TokenKind::Identifier("/*".to_string())
+                        }
+                    }
                 } else {
                     self.advance();
                     TokenKind::Identifier("/".to_string())
@@ -224,9 +337,34 @@ impl<'a> Lexer<'a> {
                     "move" => TokenKind::Move,
                     "screen" => TokenKind::Screen,
                     "pass" => TokenKind::Pass,
+                    "dribble" => TokenKind::Dribble,
+                    "cut" => TokenKind::Cut,
+                    "handoff" => TokenKind::Handoff,
+                    "shot" => TokenKind::Shot,
+                    "curve" => TokenKind::Curve,
+                    "via" => TokenKind::Via,
                     "before" => TokenKind::Before,
                     "after" => TokenKind::After,
                     "middle" => TokenKind::Middle,
+                    "phase" => TokenKind::Phase,
+                    "defense" => TokenKind::Defense,
+                    "guards" => TokenKind::Guards,
+                    "switch" => TokenKind::Switch,
+                    "hedge" => TokenKind::Hedge,
+                    "help" => TokenKind::Help,
+                    "trap" => TokenKind::Trap,
+                    "closeout" => TokenKind::Closeout,
+                    "spots" => TokenKind::Spots,
+                    "toward" => TokenKind::Toward,
+                    "polar" => TokenKind::Polar,
+                    "court" => TokenKind::Court,
+                    "units" => TokenKind::Units,
+                    "view" => TokenKind::View,
+                    "meta" => TokenKind::Meta,
+                    "import" => TokenKind::Import,
+                    "use" => TokenKind::Use,
+                    "template" => TokenKind::Template,
+                    "transform" => TokenKind::Transform,
                     _ => TokenKind::Identifier(ident),
                 }
             }
@@ -322,6 +460,85 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_block_comments() {
+        let input = "players /* first\n second */ state /* open";
+        let mut lexer = Lexer::new(input);
+        let tokens = lexer.tokenize();
+        assert_eq!(
+            tokens[1].kind,
+            TokenKind::BlockComment("first\n second".to_string())
+        );
+        assert_eq!(tokens[2].kind, TokenKind::State);
+        assert_eq!(tokens[2].span.line, 2);
+        // Unterminated: only `/*` is consumed so the parser can point at it
+        assert_eq!(tokens[3].kind, TokenKind::Identifier("/*".to_string()));
+        assert_eq!(tokens[4].kind, TokenKind::Identifier("open".to_string()));
+    }
+
+    #[test]
+    fn test_event_references() {
+        let input = "p4.screen";
+        let mut lexer = Lexer::new(input);
+        let tokens = lexer.tokenize();
+        let kinds: Vec<TokenKind> = tokens.into_iter().map(|t| t.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                TokenKind::Identifier("p4".to_string()),
+                TokenKind::Dot,
+                TokenKind::Screen,
+                TokenKind::EOF
+            ]
+        );
+    }
+
+    #[test]
+    fn test_defense_keywords() {
+        let input = "defense switch";
+        let mut lexer = Lexer::new(input);
+        let tokens = lexer.tokenize();
+        let kinds: Vec<TokenKind> = tokens.into_iter().map(|t| t.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![TokenKind::Defense, TokenKind::Switch, TokenKind::EOF]
+        );
+    }
+
+    #[test]
+    fn test_relative_points() {
+        let input = "+ toward";
+        let mut lexer = Lexer::new(input);
+        let tokens = lexer.tokenize();
+        let kinds: Vec<TokenKind> = tokens.into_iter().map(|t| t.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![TokenKind::Plus, TokenKind::Toward, TokenKind::EOF]
+        );
+    }
+
+    #[test]
+    fn test_strings() {
+        let input = r#"meta [ "Horns \"Flare\"" ] "a\\b\nc" "open"#;
+        let mut lexer = Lexer::new(input);
+        let tokens = lexer.tokenize();
+        let kinds: Vec<TokenKind> = tokens.iter().map(|t| t.kind.clone()).collect();
+        assert_eq!(
+            kinds[..5],
+            [
+                TokenKind::Meta,
+                TokenKind::LBracket,
+                TokenKind::String("Horns \"Flare\"".to_string()),
+                TokenKind::RBracket,
+                TokenKind::String("a\\b\nc".to_string()),
+            ]
+        );
+        assert_eq!(tokens[2].span.end, 24);
+        // Unterminated: only `"` is consumed so the parser can point at it
+        assert_eq!(kinds[5], TokenKind::Identifier("\"".to_string()));
+        assert_eq!(kinds[6], TokenKind::Identifier("open".to_string()));
+    }
+
     #[test]
     fn test_span() {
         let input = "players";
@@ -1,15 +1,160 @@
+use serde::Deserialize;
 use wasm_bindgen::prelude::*;
 
 pub mod ast;
+pub mod checker;
+pub mod court;
+pub mod diagnostic;
+pub mod formatter;
+pub mod import;
 pub mod ir;
 pub mod lexer;
 pub mod parser;
 pub mod renderer;
+pub mod template;
 
-pub use renderer::Renderer;
+use renderer::Region;
+pub use renderer::{Renderer, Theme};
 
+/// The SVG of the play, or every error in `input`, one per line. Nothing is drawn
+/// while there are errors; `render_playbook_partial` draws what parsed alongside them.
 #[wasm_bindgen]
 pub fn render_playbook(input: &str) -> Result<String, JsValue> {
     let renderer = Renderer::new();
     renderer.render(input).map_err(|e| JsValue::from_str(&e))
 }
+
+#[wasm_bindgen]
+pub fn render_playbook_phases(input: &str) -> Result<Vec<String>, JsValue> {
+    let renderer = Renderer::new();
+    renderer
+        .render_phases(input)
+        .map_err(|e| JsValue::from_str(&e))
+}
+
+/// Animated SVG of the whole play, looping every `duration` seconds.
+#[wasm_bindgen]
+pub fn render_playbook_animated(input: &str, duration: f64) -> Result<String, JsValue> {
+    let renderer = Renderer::new();
+    renderer
+        .render_animated(input, duration)
+        .map_err(|e| JsValue::from_str(&e))
+}
+
+/// Settings of `render_playbook_with`, every one optional.
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct RenderOptions {
+    width: Option<u32>,
+    height: Option<u32>,
+    padding: Option<f64>,
+    crop: bool,
+    /// Region name or `x1,y1,x2,y2`
+    zoom: Option<String>,
+    court: Option<String>,
+    view: Option<String>,
+    /// Built-in theme name, or a theme object like a JSON theme file
+    theme: Option<serde_json::Value>,
+}
+
+impl RenderOptions {
+    fn renderer(self) -> Result<Renderer, String> {
+        let mut renderer = Renderer::new();
+        if self.width == Some(0) || self.height == Some(0) {
+            return Err("'width' and 'height' must be greater than 0".to_string());
+        }
+        match (self.width, self.height) {
+            (Some(width), Some(height)) => renderer = renderer.with_size(width, height),
+            (Some(width), None) => renderer = renderer.with_width(width),
+            (None, Some(_)) => return Err("'height' needs a 'width'".to_string()),
+            (None, None) => {}
+        }
+        if let Some(padding) = self.padding {
+            if !(padding >= 0.0 && padding.is_finite()) {
+                return Err("'padding' must be 0 or more".to_string());
+            }
+            renderer = renderer.with_padding(padding);
+        }
+        if self.crop {
+            renderer = renderer.with_auto_crop();
+        }
+        if let Some(zoom) = self.zoom {
+            let region = Region::parse(&zoom).ok_or(format!("Unknown zoom region '{}'", zoom))?;
+            renderer = renderer.with_zoom(region);
+        }
+        if let Some(name) = self.court {
+            let court =
+                court::CourtSpec::from_name(&name).ok_or(format!("Unknown court '{}'", name))?;
+            renderer = renderer.with_court(court);
+        }
+        if let Some(name) = self.view {
+            let view =
+                court::CourtView::from_name(&name).ok_or(format!("Unknown view '{}'", name))?;
+            renderer = renderer.with_view(view);
+        }
+        let theme = match self.theme {
+            None => None,
+            Some(serde_json::Value::String(name)) => {
+                Some(Theme::from_name(&name).ok_or(format!("Unknown theme '{}'", name))?)
+            }
+            Some(theme) => Some(Theme::from_value(theme)?),
+        };
+        if let Some(theme) = theme {
+            renderer = renderer.with_theme(theme);
+        }
+        Ok(renderer)
+    }
+}
+
+/// Like `render_playbook`, with a JSON object of settings:
+/// `{ "width": 800, "padding": 10, "crop": true, "zoom": "right", "court": "fiba", "view": "full", "theme": "dark" }`.
+#[wasm_bindgen]
+pub fn render_playbook_with(input: &str, options: &str) -> Result<String, JsValue> {
+    let options: RenderOptions =
+        serde_json::from_str(options).map_err(|e| JsValue::from_str(&e.to_string()))?;
+    let renderer = options.renderer().map_err(|e| JsValue::from_str(&e))?;
+    renderer.render(input).map_err(|e| JsValue::from_str(&e))
+}
+
+#[wasm_bindgen(getter_with_clone)]
+pub struct RenderResult {
+    pub svg: String,
+    pub errors: Vec<String>,
+    /// JSON array of `Diagnostic`s
+    pub diagnostics: String,
+}
+
+/// Draws whatever parsed correctly and reports every error, for live editing.
+#[wasm_bindgen]
+pub fn render_playbook_partial(input: &str) -> RenderResult {
+    let renderer = Renderer::new();
+    let (svg, diagnostics) = renderer.render_partial(input);
+    RenderResult {
+        svg,
+        errors: diagnostics.iter().map(|d| d.to_string()).collect(),
+        diagnostics: diagnostic::to_json(&diagnostics),
+    }
+}
+
+/// Returns a JSON array of `Diagnostic`s so editors can underline problems.
+#[wasm_bindgen]
+pub fn diagnose_playbook(input: &str) -> String {
+    let (_, diagnostics) = diagnostic::analyze(input);
+    diagnostic::to_json(&diagnostics)
+}
+
+/// Returns the `meta` section as JSON, or `null` if the playbook has none.
+#[wasm_bindgen]
+pub fn playbook_meta(input: &str) -> String {
+    let (playbook, _) = diagnostic::analyze(input);
+    serde_json::to_string(&playbook.meta).unwrap_or_else(|_| "null".to_string())
+}
+
+/// Formats a playbook in the canonical style, keeping comments.
+#[wasm_bindgen]
+pub fn format_playbook(input: &str) -> Result<String, JsValue> {
+    formatter::format(input).map_err(|diagnostics| {
+        let messages: Vec<String> = diagnostics.iter().map(|d| d.to_string()).collect();
+        JsValue::from_str(&messages.join("\n"))
+    })
+}
@@ -1,4 +1,5 @@
 use crate::ast::*;
+use crate::court::{COURT_NAMES, CourtSpec, CourtView, Units, VIEW_NAMES};
 use crate::lexer::{Span, Token, TokenKind};
 
 #[derive(Debug)]
@@ -10,7 +11,35 @@ pub enum ParseError {
 
 pub struct Parser {
     tokens: Vec<Token>,
+    comments: Vec<Comment>,
     pos: usize,
+    errors: Vec<ParseError>,
+    /// `players` / `defense` / `state` / `action` keywords, for attaching section comments.
+    section_spans: Vec<Span>,
+    /// Source ranges of every entry (top-level sections included) and of every `{ ... }` block,
+    /// for telling comments inside an entry from comments between entries.
+    entry_extents: Vec<(usize, usize)>,
+    block_extents: Vec<(usize, usize)>,
+}
+
+/// A node comments can be attached to.
+enum Anchor {
+    Playbook,
+    Position(String),
+    Phase(usize),
+    Move(Scope, usize),
+    Screen(Scope, usize),
+    Pass(Scope, usize),
+    Shot(Scope, usize),
+    Defense(Scope, usize),
+}
+
+/// The action block a node is in.
+#[derive(Clone, Copy)]
+enum Scope {
+    Action,
+    Phase(usize),
+    Template(usize),
 }
 
 fn levenshtein(a: &str, b: &str) -> usize {
@@ -44,7 +73,8 @@ fn levenshtein(a: &str, b: &str) -> usize {
     matrix[len_a][len_b]
 }
 
-fn get_suggestion(input: &str, candidates: &[&str]) -> Option<String> {
+/// Returns the closest candidate within an edit distance of 2, if any.
+pub fn get_suggestion(input: &str, candidates: &[&str]) -> Option<String> {
     let mut best_match = None;
     let mut min_dist = 3; // Max allowed distance is 2
 
@@ -59,22 +89,71 @@ fn get_suggestion(input: &str, candidates: &[&str]) -> Option<String> {
 }
 
 impl Parser {
+    /// Comments are split off here, so the grammar never sees them.
     pub fn new(tokens: Vec<Token>) -> Self {
-        Self { tokens, pos: 0 }
+        let mut code = Vec::new();
+        let mut comments = Vec::new();
+        let mut errors = Vec::new();
+        for token in tokens {
+            match token.kind {
+                TokenKind::Comment(text) | TokenKind::BlockComment(text) => {
+                    comments.push(Comment {
+                        text,
+                        span: token.span,
+                    });
+                }
+                TokenKind::Identifier(ref s) if s == "/*" => {
+                    // Everything after an unclosed `/*` is commented out
+                    errors.push(ParseError::UnexpectedToken(
+                        token,
+                        "Unterminated block comment (missing '*/')".to_string(),
+                    ));
+                    break;
+                }
+                TokenKind::Identifier(ref s) if s == "\"" => {
+                    // Likewise for an unclosed string
+                    errors.push(ParseError::UnexpectedToken(
+                        token,
+                        "Unterminated string (missing '\"')".to_string(),
+                    ));
+                    break;
+                }
+                _ => code.push(token),
+            }
+        }
+        Self {
+            tokens: code,
+            comments,
+            pos: 0,
+            errors,
+            section_spans: Vec::new(),
+            entry_extents: Vec::new(),
+            block_extents: Vec::new(),
+        }
     }
 
     fn peek(&self) -> Token {
         if self.pos < self.tokens.len() {
             self.tokens[self.pos].clone()
         } else {
-            Token {
-                kind: TokenKind::EOF,
-                span: Span {
+            // Past the end: point at the end of the last token instead of line 0
+            let span = match self.tokens.last() {
+                Some(last) => Span {
+                    start: last.span.end,
+                    end: last.span.end,
+                    line: last.span.line,
+                    column: last.span.column + (last.span.end - last.span.start),
+                },
+                None => Span {
                     start: 0,
                     end: 0,
-                    line: 0,
-                    column: 0,
+                    line: 1,
+                    column: 1,
                 },
+            };
+            Token {
+                kind: TokenKind::EOF,
+                span,
             }
         }
     }
@@ -111,6 +190,11 @@ impl Parser {
         }
     }
 
+    fn expect_spanned_identifier(&mut self) -> Result<(String, Span), ParseError> {
+        let span = self.peek().span;
+        Ok((self.expect_identifier()?, span))
+    }
+
     fn consume_if(&mut self, expected_kind: TokenKind) -> bool {
         if std::mem::discriminant(&self.peek().kind) == std::mem::discriminant(&expected_kind) {
             self.advance();
@@ -120,245 +204,967 @@ impl Parser {
         }
     }
 
-    fn parse_coordinate(&mut self) -> Result<(f64, f64), ParseError> {
-        self.expect(TokenKind::LParenthesis)?;
-        let token = self.advance();
-        let x = match token.kind {
-            TokenKind::Number(n) => n,
+    /// `(x, y)`, a player or spot name, `toward(point, distance)` or `polar(distance, angle)`,
+    /// followed by any number of `+ (dx, dy)` offsets.
+    fn parse_point(&mut self) -> Result<Point, ParseError> {
+        let mut point = match self.peek().kind {
+            TokenKind::Identifier(_) => {
+                let (name, span) = self.expect_spanned_identifier()?;
+                Point::Name(name, span)
+            }
+            TokenKind::Toward => {
+                let span = self.advance().span;
+                self.expect(TokenKind::LParenthesis)?;
+                let target = self.parse_point()?;
+                self.expect(TokenKind::Comma)?;
+                let distance = self.expect_number("Expected Number for distance")?;
+                self.expect(TokenKind::RParenthesis)?;
+                Point::Toward(Box::new(target), distance, span)
+            }
+            TokenKind::Polar => {
+                self.advance();
+                self.expect(TokenKind::LParenthesis)?;
+                let distance = self.expect_number("Expected Number for distance")?;
+                self.expect(TokenKind::Comma)?;
+                let angle = self.expect_number("Expected Number for angle")?;
+                self.expect(TokenKind::RParenthesis)?;
+                Point::Polar(distance, angle)
+            }
             _ => {
-                return Err(ParseError::UnexpectedToken(
-                    token,
-                    "Expected Number for X".to_string(),
-                ));
+                let (x, y) = self.parse_coordinate()?;
+                Point::Coord(x, y)
             }
         };
-        self.expect(TokenKind::Comma)?;
+        while self.consume_if(TokenKind::Plus) {
+            let offset = self.parse_coordinate()?;
+            point = Point::Offset(Box::new(point), offset);
+        }
+        Ok(point)
+    }
+
+    fn expect_number(&mut self, message: &str) -> Result<f64, ParseError> {
+        let token = self.advance();
+        match token.kind {
+            TokenKind::Number(n) => Ok(n),
+            _ => Err(ParseError::UnexpectedToken(token, message.to_string())),
+        }
+    }
+
+    fn expect_string(&mut self) -> Result<String, ParseError> {
+        let token = self.advance();
+        match token.kind {
+            TokenKind::String(s) => Ok(s),
+            _ => Err(ParseError::UnexpectedToken(
+                token,
+                "Expected String (\"...\")".to_string(),
+            )),
+        }
+    }
+
+    fn expect_spanned_string(&mut self) -> Result<(String, Span), ParseError> {
+        let span = self.peek().span;
+        Ok((self.expect_string()?, span))
+    }
+
+    /// Parses `name = "..."` or `tags = ["...", ...]` into `meta`.
+    fn parse_meta_entry(&mut self, meta: &mut Meta) -> Result<(), ParseError> {
         let token = self.advance();
-        let y = match token.kind {
-            TokenKind::Number(n) => n,
+        let key = match &token.kind {
+            TokenKind::Identifier(key) if META_FIELDS.contains(&key.as_str()) => key.clone(),
             _ => {
-                return Err(ParseError::UnexpectedToken(
-                    token,
-                    "Expected Number for Y".to_string(),
-                ));
+                let name = match &token.kind {
+                    TokenKind::Identifier(name) => name.as_str(),
+                    _ => "",
+                };
+                let msg = match get_suggestion(name, META_FIELDS) {
+                    Some(sugg) => format!("Expected meta field. Did you mean '{}'?", sugg),
+                    None => format!("Expected meta field ({})", META_FIELDS.join(", ")),
+                };
+                return Err(ParseError::UnexpectedToken(token, msg));
             }
         };
+        self.expect(TokenKind::Equals)?;
+        if key == "tags" {
+            self.expect(TokenKind::LBracket)?;
+            meta.tags.clear();
+            while !matches!(self.peek().kind, TokenKind::RBracket | TokenKind::EOF) {
+                meta.tags.push(self.expect_string()?);
+                if !self.consume_if(TokenKind::Comma) {
+                    break;
+                }
+            }
+            return self.expect(TokenKind::RBracket);
+        }
+        let value = Some(self.expect_string()?);
+        match key.as_str() {
+            "name" => meta.name = value,
+            "call" => meta.call = value,
+            "category" => meta.category = value,
+            "formation" => meta.formation = value,
+            _ => meta.notes = value,
+        }
+        Ok(())
+    }
+
+    fn parse_coordinate(&mut self) -> Result<(f64, f64), ParseError> {
+        self.expect(TokenKind::LParenthesis)?;
+        let result = self.parse_coordinate_values();
+        if result.is_err() {
+            // Skip the rest of the tuple so its inner `,` doesn't look like an entry boundary
+            while !matches!(
+                self.peek().kind,
+                TokenKind::RBrace | TokenKind::EOF | TokenKind::LBrace
+            ) {
+                if self.advance().kind == TokenKind::RParenthesis {
+                    break;
+                }
+            }
+        }
+        result
+    }
+
+    fn parse_coordinate_values(&mut self) -> Result<(f64, f64), ParseError> {
+        let x = self.expect_number("Expected Number for X")?;
+        self.expect(TokenKind::Comma)?;
+        let y = self.expect_number("Expected Number for Y")?;
         self.expect(TokenKind::RParenthesis)?;
         Ok((x, y))
     }
 
-    pub fn parse(&mut self) -> Result<Playbook, ParseError> {
-        let mut players = Vec::new();
-        let mut state = State::default();
-        let mut action = Action::default();
-
-        while self.peek().kind != TokenKind::EOF {
-            match self.peek().kind {
-                TokenKind::Players => {
-                    self.advance(); // consume 'players'
-                    self.expect(TokenKind::Equals)?;
-                    self.expect(TokenKind::LBrace)?;
-                    while self.peek().kind != TokenKind::RBrace
-                        && self.peek().kind != TokenKind::EOF
-                    {
-                        players.push(self.expect_identifier()?);
-                        if self.peek().kind == TokenKind::Comma {
-                            self.advance();
+    /// Parses `(name, ...)`: the parameters of a template or the arguments of a call.
+    fn parse_names(&mut self) -> Result<Vec<(String, Span)>, ParseError> {
+        self.expect(TokenKind::LParenthesis)?;
+        let mut names = Vec::new();
+        while self.peek().kind != TokenKind::RParenthesis {
+            match self.expect_spanned_identifier() {
+                Ok(name) => names.push(name),
+                Err(e) => {
+                    // Skip the rest of the list so its inner `,` doesn't look like an entry boundary
+                    while !matches!(
+                        self.peek().kind,
+                        TokenKind::RBrace | TokenKind::EOF | TokenKind::LBrace
+                    ) {
+                        if self.advance().kind == TokenKind::RParenthesis {
+                            break;
                         }
                     }
-                    self.expect(TokenKind::RBrace)?;
+                    return Err(e);
                 }
-                TokenKind::State => {
-                    self.advance();
-                    self.expect(TokenKind::Equals)?;
-                    self.expect(TokenKind::LBrace)?;
-                    state = self.parse_state_block()?;
-                    self.expect(TokenKind::RBrace)?;
+            }
+            if !self.consume_if(TokenKind::Comma) {
+                break;
+            }
+        }
+        self.expect(TokenKind::RParenthesis)?;
+        Ok(names)
+    }
+
+    /// Parses `= { mirror, rotate = 90, translate = (10, 0) }` into `transform`.
+    fn parse_transform(&mut self, transform: &mut Vec<Transform>) -> Result<(), ParseError> {
+        self.parse_block(|parser| {
+            let token = parser.advance();
+            let name = match &token.kind {
+                TokenKind::Identifier(name) => name.as_str(),
+                _ => "",
+            };
+            match name {
+                "mirror" => transform.push(Transform::Mirror),
+                "rotate" => {
+                    parser.expect(TokenKind::Equals)?;
+                    let angle = parser.expect_number("Expected Number for angle")?;
+                    transform.push(Transform::Rotate(angle));
                 }
-                TokenKind::Action => {
-                    self.advance();
-                    self.expect(TokenKind::Equals)?;
-                    self.expect(TokenKind::LBrace)?;
-                    action = self.parse_action_block()?;
-                    self.expect(TokenKind::RBrace)?;
+                "translate" => {
+                    parser.expect(TokenKind::Equals)?;
+                    let (dx, dy) = parser.parse_coordinate()?;
+                    transform.push(Transform::Translate(dx, dy));
                 }
                 _ => {
-                    let token = self.peek();
-                    let mut msg = "Expected section start (players, state, action)".to_string();
-                    let TokenKind::Identifier(ref s) = token.kind else {
-                        return Err(ParseError::UnexpectedToken(token, msg));
+                    let msg = match get_suggestion(name, TRANSFORM_NAMES) {
+                        Some(sugg) => format!("Expected transform. Did you mean '{}'?", sugg),
+                        None => format!("Expected transform ({})", TRANSFORM_NAMES.join(", ")),
                     };
-                    if let Some(sugg) = get_suggestion(s, &["players", "state", "action"]) {
-                        msg = format!("Expected section start. Did you mean '{}'?", sugg);
-                    }
                     return Err(ParseError::UnexpectedToken(token, msg));
                 }
             }
-        }
-
-        Ok(Playbook {
-            players,
-            state,
-            action,
+            Ok(())
         })
     }
 
-    fn parse_state_block(&mut self) -> Result<State, ParseError> {
-        let mut state = State::default();
-        while self.peek().kind != TokenKind::RBrace && self.peek().kind != TokenKind::EOF {
+    /// Skips to the next `,` or `}` at the current nesting level.
+    /// The `,` is consumed, the `}` is left for the enclosing block.
+    fn synchronize(&mut self) {
+        let mut depth = 0usize;
+        loop {
             match self.peek().kind {
-                TokenKind::Baller => {
+                TokenKind::EOF => return,
+                TokenKind::RBrace if depth == 0 => return,
+                TokenKind::Comma if depth == 0 => {
                     self.advance();
-                    self.expect(TokenKind::Equals)?;
-                    state.baller = Some(self.expect_identifier()?);
-                    self.consume_if(TokenKind::Comma);
+                    return;
                 }
-                TokenKind::Position => {
-                    self.advance();
-                    self.expect(TokenKind::Equals)?;
-                    self.expect(TokenKind::LBrace)?;
-                    while self.peek().kind != TokenKind::RBrace {
-                        let player = self.expect_identifier()?;
-                        self.expect(TokenKind::Equals)?;
-                        let coord = self.parse_coordinate()?;
-                        state.positions.insert(player, coord);
-                        if self.peek().kind == TokenKind::Comma {
-                            self.advance();
-                        }
-                    }
-                    self.expect(TokenKind::RBrace)?;
-                    self.consume_if(TokenKind::Comma);
+                TokenKind::LBrace | TokenKind::LParenthesis | TokenKind::LBracket => depth += 1,
+                TokenKind::RBrace | TokenKind::RParenthesis | TokenKind::RBracket => {
+                    depth = depth.saturating_sub(1)
                 }
-                _ => {
-                    let token = self.peek();
-                    let mut msg = "Expected state property (baller, position)".to_string();
-                    let TokenKind::Identifier(ref s) = token.kind else {
-                        return Err(ParseError::UnexpectedToken(token, msg));
-                    };
-                    if let Some(sugg) = get_suggestion(s, &["baller", "position"]) {
-                        msg = format!("Expected state property. Did you mean '{}'?", sugg);
-                    }
-                    return Err(ParseError::UnexpectedToken(token, msg));
+                _ => {}
+            }
+            self.advance();
+        }
+    }
+
+    /// Skips to the next top-level section keyword.
+    fn synchronize_section(&mut self) {
+        let mut depth = 0usize;
+        loop {
+            match self.peek().kind {
+                TokenKind::EOF => return,
+                TokenKind::Meta
+                | TokenKind::Import
+                | TokenKind::Use
+                | TokenKind::Template
+                | TokenKind::Transform
+                | TokenKind::Players
+                | TokenKind::Defense
+                | TokenKind::State
+                | TokenKind::Action
+                | TokenKind::Phase
+                    if depth == 0 =>
+                {
+                    return;
                 }
+                TokenKind::LBrace => depth += 1,
+                TokenKind::RBrace => depth = depth.saturating_sub(1),
+                _ => {}
             }
+            self.advance();
         }
-        Ok(state)
     }
 
-    fn parse_action_block(&mut self) -> Result<Action, ParseError> {
-        let mut action = Action::default();
+    /// Parses `= { entry, entry, ... }`.
+    /// A failing entry is recorded and skipped so the rest of the block still parses.
+    fn parse_block<F>(&mut self, mut parse_entry: F) -> Result<(), ParseError>
+    where
+        F: FnMut(&mut Self) -> Result<(), ParseError>,
+    {
+        self.expect(TokenKind::Equals)?;
+        let open = self.peek().span.start;
+        self.expect(TokenKind::LBrace)?;
         while self.peek().kind != TokenKind::RBrace && self.peek().kind != TokenKind::EOF {
-            match self.peek().kind {
-                TokenKind::Move => {
-                    self.advance();
-                    self.expect(TokenKind::Equals)?;
-                    self.expect(TokenKind::LBrace)?;
-                    while self.peek().kind != TokenKind::RBrace {
-                        let player = self.expect_identifier()?;
-                        self.expect(TokenKind::Arrow)?;
-                        let target = self.parse_coordinate()?;
-                        action.moves.push(MoveAction { player, target });
-                        if self.peek().kind == TokenKind::Comma {
-                            self.advance();
-                        }
-                    }
-                    self.expect(TokenKind::RBrace)?;
+            let start = self.peek().span.start;
+            match parse_entry(self) {
+                Ok(()) => {
+                    self.record_entry(start);
                     self.consume_if(TokenKind::Comma);
                 }
-                TokenKind::Screen => {
-                    self.advance();
-                    self.expect(TokenKind::Equals)?;
-                    self.expect(TokenKind::LBrace)?;
-                    while self.peek().kind != TokenKind::RBrace {
-                        let player = self.expect_identifier()?;
-                        self.expect(TokenKind::Arrow)?;
-                        let target = self.expect_identifier()?;
-                        let mut timing = Timing::None;
-                        if self.peek().kind == TokenKind::Colon {
-                            self.advance();
-                            match self.peek().kind {
-                                TokenKind::Before => {
-                                    self.advance();
-                                    timing = Timing::Before;
-                                }
-                                TokenKind::After => {
-                                    self.advance();
-                                    timing = Timing::After;
-                                }
-                                TokenKind::Middle => {
-                                    self.advance();
-                                    timing = Timing::Middle;
-                                }
-                                _ => {
-                                    return Err(ParseError::UnexpectedToken(
-                                        self.peek(),
-                                        "Expected timing (before, after, middle)".to_string(),
-                                    ));
-                                }
+                Err(e) => {
+                    self.errors.push(e);
+                    self.synchronize();
+                }
+            }
+        }
+        let result = self.expect(TokenKind::RBrace);
+        self.block_extents.push((open, self.consumed_end()));
+        result
+    }
+
+    /// Records the entry from `start` to the last token consumed.
+    fn record_entry(&mut self, start: usize) {
+        let end = self.consumed_end();
+        self.entry_extents.push((start, end));
+    }
+
+    /// Where the last token consumed ends.
+    fn consumed_end(&self) -> usize {
+        self.pos
+            .checked_sub(1)
+            .and_then(|i| self.tokens.get(i))
+            .map_or(0, |token| token.span.end)
+    }
+
+    fn parse_timing(&mut self, allow_middle: bool) -> Result<Timing, ParseError> {
+        if !self.consume_if(TokenKind::Colon) {
+            return Ok(Timing::None);
+        }
+        let token = self.peek();
+        match token.kind {
+            TokenKind::Before => {
+                self.advance();
+                match self.peek().kind {
+                    TokenKind::Identifier(_) => Ok(Timing::BeforeEvent(self.parse_event_ref()?)),
+                    _ => Ok(Timing::Before),
+                }
+            }
+            TokenKind::After => {
+                self.advance();
+                match self.peek().kind {
+                    TokenKind::Identifier(_) => Ok(Timing::AfterEvent(self.parse_event_ref()?)),
+                    _ => Ok(Timing::After),
+                }
+            }
+            TokenKind::Middle if allow_middle => {
+                self.advance();
+                Ok(Timing::Middle)
+            }
+            TokenKind::Number(n) => {
+                if !(0.0..=1.0).contains(&n) {
+                    return Err(ParseError::UnexpectedToken(
+                        token,
+                        "Timing fraction must be between 0 and 1".to_string(),
+                    ));
+                }
+                self.advance();
+                Ok(Timing::Fraction(n))
+            }
+            _ => {
+                let expected = if allow_middle {
+                    "Expected timing (before, after, middle, 0.0-1.0)"
+                } else {
+                    "Expected timing (before, after, 0.0-1.0)"
+                };
+                Err(ParseError::UnexpectedToken(token, expected.to_string()))
+            }
+        }
+    }
+
+    /// Parses `p4.screen`.
+    fn parse_event_ref(&mut self) -> Result<EventRef, ParseError> {
+        let (player, span) = self.expect_spanned_identifier()?;
+        self.expect(TokenKind::Dot)?;
+        let token = self.advance();
+        let kind = match token.kind {
+            TokenKind::Move => EventKind::Move,
+            TokenKind::Screen => EventKind::Screen,
+            TokenKind::Pass => EventKind::Pass,
+            _ => {
+                return Err(ParseError::UnexpectedToken(
+                    token,
+                    "Expected event (move, screen, pass)".to_string(),
+                ));
+            }
+        };
+        Ok(EventRef { player, kind, span })
+    }
+
+    /// Parses the whole input, stopping at the first error.
+    pub fn parse(&mut self) -> Result<Playbook, ParseError> {
+        let (playbook, mut errors) = self.parse_with_recovery();
+        if errors.is_empty() {
+            Ok(playbook)
+        } else {
+            Err(errors.remove(0))
+        }
+    }
+
+    /// Parses the whole input, recovering at `}` / `,` boundaries.
+    /// Returns whatever parsed correctly together with every error found.
+    pub fn parse_with_recovery(&mut self) -> (Playbook, Vec<ParseError>) {
+        let mut playbook = Playbook::default();
+        let mut action_token: Option<Token> = None;
+
+        while self.peek().kind != TokenKind::EOF {
+            let start = self.peek().span.start;
+            let result = match self.peek().kind {
+                TokenKind::Meta => {
+                    let token = self.advance();
+                    self.section_spans.push(token.span);
+                    let mut meta = playbook.meta.take().unwrap_or_default();
+                    let result = self.parse_block(|parser| parser.parse_meta_entry(&mut meta));
+                    playbook.meta = Some(meta);
+                    result
+                }
+                TokenKind::Import => {
+                    let token = self.advance();
+                    self.section_spans.push(token.span);
+                    self.expect_spanned_string().map(|(path, span)| {
+                        playbook.imports.push(Import { path, span });
+                    })
+                }
+                TokenKind::Use => {
+                    let token = self.advance();
+                    self.section_spans.push(token.span);
+                    let token = self.advance();
+                    if token.kind != TokenKind::Identifier("formation".to_string()) {
+                        Err(ParseError::UnexpectedToken(
+                            token,
+                            "Expected 'formation' (use formation <name>)".to_string(),
+                        ))
+                    } else {
+                        self.expect_spanned_identifier().and_then(|(name, span)| {
+                            if playbook.formation.is_some() {
+                                return Err(ParseError::UnexpectedToken(
+                                    Token {
+                                        kind: TokenKind::Identifier(name),
+                                        span,
+                                    },
+                                    "A play can use only one formation".to_string(),
+                                ));
                             }
-                        }
-                        action.screens.push(ScreenAction {
-                            player,
-                            target,
-                            timing,
-                        });
-                        if self.peek().kind == TokenKind::Comma {
-                            self.advance();
-                        }
+                            playbook.formation = Some(FormationUse { name, span });
+                            Ok(())
+                        })
                     }
-                    self.expect(TokenKind::RBrace)?;
-                    self.consume_if(TokenKind::Comma);
                 }
-                TokenKind::Pass => {
-                    self.advance();
-                    self.expect(TokenKind::Equals)?;
-                    self.expect(TokenKind::LBrace)?;
-                    while self.peek().kind != TokenKind::RBrace {
-                        let from = self.expect_identifier()?;
-                        self.expect(TokenKind::Arrow)?;
-                        let to = self.expect_identifier()?;
-                        let mut timing = Timing::None;
-                        if self.peek().kind == TokenKind::Colon {
-                            self.advance();
-                            match self.peek().kind {
-                                TokenKind::Before => {
-                                    self.advance();
-                                    timing = Timing::Before;
-                                }
-                                TokenKind::After => {
-                                    self.advance();
-                                    timing = Timing::After;
-                                }
-                                _ => {
-                                    return Err(ParseError::UnexpectedToken(
-                                        self.peek(),
-                                        "Expected timing (before, after)".to_string(),
-                                    ));
-                                }
+                TokenKind::Players => {
+                    let token = self.advance(); // consume 'players'
+                    self.section_spans.push(token.span);
+                    self.parse_block(|parser| {
+                        let (player, span) = parser.expect_spanned_identifier()?;
+                        playbook.players.push(player);
+                        playbook.player_spans.push(span);
+                        Ok(())
+                    })
+                }
+                TokenKind::Defense => {
+                    let token = self.advance();
+                    self.section_spans.push(token.span);
+                    self.parse_block(|parser| {
+                        let (defender, span) = parser.expect_spanned_identifier()?;
+                        playbook.defense.push(defender);
+                        playbook.defense_spans.push(span);
+                        Ok(())
+                    })
+                }
+                TokenKind::Court => {
+                    let token = self.advance();
+                    self.section_spans.push(token.span);
+                    self.expect(TokenKind::Equals).and_then(|_| {
+                        let token = self.advance();
+                        let name = match &token.kind {
+                            TokenKind::Identifier(name) => name.clone(),
+                            _ => String::new(),
+                        };
+                        let Some(court) = CourtSpec::from_name(&name) else {
+                            let msg = match get_suggestion(&name, COURT_NAMES) {
+                                Some(sugg) => format!("Expected court. Did you mean '{}'?", sugg),
+                                None => format!("Expected court ({})", COURT_NAMES.join(", ")),
+                            };
+                            return Err(ParseError::UnexpectedToken(token, msg));
+                        };
+                        playbook.court = Some(court);
+                        Ok(())
+                    })
+                }
+                TokenKind::Units => {
+                    let token = self.advance();
+                    self.section_spans.push(token.span);
+                    self.expect(TokenKind::Equals).and_then(|_| {
+                        let token = self.advance();
+                        match &token.kind {
+                            TokenKind::Identifier(name) if Units::from_name(name).is_some() => {
+                                playbook.units = Units::from_name(name);
+                                Ok(())
                             }
+                            _ => Err(ParseError::UnexpectedToken(
+                                token,
+                                "Expected units (meters, feet)".to_string(),
+                            )),
                         }
-                        action.passes.push(PassAction { from, to, timing });
-                        if self.peek().kind == TokenKind::Comma {
-                            self.advance();
+                    })
+                }
+                TokenKind::View => {
+                    let token = self.advance();
+                    self.section_spans.push(token.span);
+                    self.expect(TokenKind::Equals).and_then(|_| {
+                        let token = self.advance();
+                        match &token.kind {
+                            TokenKind::Identifier(name) if CourtView::from_name(name).is_some() => {
+                                playbook.view = CourtView::from_name(name);
+                                Ok(())
+                            }
+                            _ => Err(ParseError::UnexpectedToken(
+                                token,
+                                format!("Expected view ({})", VIEW_NAMES.join(", ")),
+                            )),
                         }
-                    }
-                    self.expect(TokenKind::RBrace)?;
-                    self.consume_if(TokenKind::Comma);
+                    })
+                }
+                TokenKind::Spots => {
+                    let token = self.advance();
+                    self.section_spans.push(token.span);
+                    self.parse_block(|parser| {
+                        let (name, span) = parser.expect_spanned_identifier()?;
+                        parser.expect(TokenKind::Equals)?;
+                        let coord = parser.parse_coordinate()?;
+                        playbook.spots.insert(name.clone(), coord);
+                        playbook.spot_spans.insert(name, span);
+                        Ok(())
+                    })
+                }
+                TokenKind::State => {
+                    let token = self.advance();
+                    self.section_spans.push(token.span);
+                    self.parse_state_block(&mut playbook.state)
+                }
+                TokenKind::Action => {
+                    let token = self.advance();
+                    self.section_spans.push(token.span);
+                    action_token = Some(token);
+                    self.parse_action_block(&mut playbook.action)
+                }
+                TokenKind::Phase => self.parse_phase(&mut playbook.phases),
+                TokenKind::Template => self.parse_template(&mut playbook.templates),
+                TokenKind::Transform => {
+                    let token = self.advance();
+                    self.section_spans.push(token.span);
+                    self.parse_transform(&mut playbook.transform)
                 }
                 _ => {
-                    let token = self.peek();
-                    let mut msg = "Expected action property (move, screen, pass)".to_string();
-                    let TokenKind::Identifier(ref s) = token.kind else {
-                        return Err(ParseError::UnexpectedToken(token, msg));
-                    };
-                    if let Some(sugg) = get_suggestion(s, &["move", "screen", "pass"]) {
-                        msg = format!("Expected action property. Did you mean '{}'?", sugg);
+                    let token = self.advance();
+                    let mut msg =
+                        "Expected section start (meta, import, use, players, defense, court, units, view, spots, transform, template, state, action, phase)"
+                            .to_string();
+                    if let TokenKind::Identifier(ref s) = token.kind
+                        && let Some(sugg) = get_suggestion(
+                            s,
+                            &[
+                                "meta",
+                                "import",
+                                "use",
+                                "players",
+                                "defense",
+                                "court",
+                                "units",
+                                "view",
+                                "spots",
+                                "transform",
+                                "template",
+                                "state",
+                                "action",
+                                "phase",
+                            ],
+                        )
+                    {
+                        msg = format!("Expected section start. Did you mean '{}'?", sugg);
                     }
-                    return Err(ParseError::UnexpectedToken(token, msg));
+                    Err(ParseError::UnexpectedToken(token, msg))
                 }
+            };
+            if let Err(e) = result {
+                self.errors.push(e);
+                self.synchronize_section();
+            }
+            self.record_entry(start);
+        }
+
+        if let Some(token) = action_token
+            && !playbook.phases.is_empty()
+        {
+            self.errors.push(ParseError::UnexpectedToken(
+                token,
+                "Cannot combine 'action' with 'phase' sections. Move it into 'phase 1'".to_string(),
+            ));
+        }
+        playbook.phases.sort_by_key(|phase| phase.number);
+        self.attach_comments(&mut playbook);
+
+        (playbook, std::mem::take(&mut self.errors))
+    }
+
+    /// Gives each comment to the node it trails on the same line,
+    /// otherwise to the next node. Anything left over belongs to the playbook.
+    /// Section keywords and `baller` are anchors of the playbook itself.
+    fn attach_comments(&mut self, playbook: &mut Playbook) {
+        let mut anchors: Vec<(Span, Anchor)> = Vec::new();
+        anchors.extend(
+            self.section_spans
+                .iter()
+                .map(|span| (*span, Anchor::Playbook)),
+        );
+        if let Some(span) = playbook.state.baller_span {
+            anchors.push((span, Anchor::Playbook));
+        }
+        for (player, span) in &playbook.state.position_spans {
+            anchors.push((*span, Anchor::Position(player.clone())));
+        }
+        let mut actions = vec![(Scope::Action, &playbook.action)];
+        for (i, phase) in playbook.phases.iter().enumerate() {
+            anchors.push((phase.span, Anchor::Phase(i)));
+            actions.push((Scope::Phase(i), &phase.action));
+        }
+        for (i, template) in playbook.templates.iter().enumerate() {
+            anchors.push((template.span, Anchor::Playbook));
+            actions.push((Scope::Template(i), &template.action));
+        }
+        for (scope, action) in actions {
+            for call in &action.calls {
+                anchors.push((call.span, Anchor::Playbook));
+            }
+            for (i, m) in action.moves.iter().enumerate() {
+                anchors.push((m.span, Anchor::Move(scope, i)));
+            }
+            for (i, screen) in action.screens.iter().enumerate() {
+                anchors.push((screen.span, Anchor::Screen(scope, i)));
+            }
+            for (i, pass) in action.passes.iter().enumerate() {
+                anchors.push((pass.span, Anchor::Pass(scope, i)));
+            }
+            for (i, shot) in action.shots.iter().enumerate() {
+                anchors.push((shot.span, Anchor::Shot(scope, i)));
+            }
+            for (i, defense) in action.defense.iter().enumerate() {
+                anchors.push((defense.span, Anchor::Defense(scope, i)));
+            }
+        }
+        anchors.sort_by_key(|(span, _)| span.start);
+
+        for comment in std::mem::take(&mut self.comments) {
+            let start = comment.span.start;
+            // Inside an entry (`p2 -> /* wide */ (70, 20)`): the entry's own node,
+            // where the formatter moves it, unless it is in a block of the entry
+            let innermost = |extents: &[(usize, usize)]| {
+                extents
+                    .iter()
+                    .filter(|(from, to)| *from < start && start < *to)
+                    .map(|(from, _)| *from)
+                    .max()
+            };
+            let inside = innermost(&self.entry_extents)
+                .filter(|entry| innermost(&self.block_extents).is_none_or(|block| block < *entry));
+            // `/* note */ p2 = ...` introduces what follows on its line
+            let leads_code = self
+                .tokens
+                .iter()
+                .find(|token| token.span.start > start)
+                .is_some_and(|token| {
+                    token.span.line == comment.span.line
+                        && !matches!(token.kind, TokenKind::Comma | TokenKind::RBrace)
+                });
+            let trailed = anchors.iter().rev().find(|(span, _)| {
+                !leads_code && span.start < start && span.line == comment.span.line
+            });
+            let anchor = match inside {
+                Some(entry) => anchors.iter().find(|(span, _)| span.start >= entry),
+                None => trailed.or_else(|| anchors.iter().find(|(span, _)| span.start > start)),
+            }
+            .map(|(_, anchor)| anchor)
+            .unwrap_or(&Anchor::Playbook);
+
+            match anchor {
+                Anchor::Playbook => playbook.comments.push(comment),
+                Anchor::Position(player) => playbook
+                    .state
+                    .position_comments
+                    .entry(player.clone())
+                    .or_default()
+                    .push(comment),
+                Anchor::Phase(i) => playbook.phases[*i].comments.push(comment),
+                Anchor::Move(scope, i) => action_mut(playbook, *scope).moves[*i]
+                    .comments
+                    .push(comment),
+                Anchor::Screen(scope, i) => action_mut(playbook, *scope).screens[*i]
+                    .comments
+                    .push(comment),
+                Anchor::Pass(scope, i) => action_mut(playbook, *scope).passes[*i]
+                    .comments
+                    .push(comment),
+                Anchor::Shot(scope, i) => action_mut(playbook, *scope).shots[*i]
+                    .comments
+                    .push(comment),
+                Anchor::Defense(scope, i) => action_mut(playbook, *scope).defense[*i]
+                    .comments
+                    .push(comment),
             }
         }
-        Ok(action)
+    }
+
+    fn parse_phase(&mut self, phases: &mut Vec<Phase>) -> Result<(), ParseError> {
+        let span = self.advance().span; // consume 'phase'
+        let token = self.advance();
+        let number = match token.kind {
+            TokenKind::Number(n) if n >= 1.0 && n.fract() == 0.0 => n as u32,
+            _ => {
+                return Err(ParseError::UnexpectedToken(
+                    token,
+                    "Expected phase number (1, 2, ...)".to_string(),
+                ));
+            }
+        };
+        let mut action = Action::default();
+        let result = self.parse_action_block(&mut action);
+        if phases.iter().any(|phase| phase.number == number) {
+            return Err(ParseError::UnexpectedToken(
+                token,
+                format!("Duplicate phase {}", number),
+            ));
+        }
+        phases.push(Phase {
+            number,
+            action,
+            span,
+            comments: Vec::new(),
+        });
+        result
+    }
+
+    /// Parses `template name(param, ...) = { ... }`.
+    fn parse_template(&mut self, templates: &mut Vec<Template>) -> Result<(), ParseError> {
+        let token = self.advance(); // consume 'template'
+        self.section_spans.push(token.span);
+        let (name, span) = self.expect_spanned_identifier()?;
+        let name_token = Token {
+            kind: TokenKind::Identifier(name.clone()),
+            span,
+        };
+        let mut params: Vec<String> = Vec::new();
+        for (param, span) in self.parse_names()? {
+            if params.contains(&param) {
+                self.errors.push(ParseError::UnexpectedToken(
+                    Token {
+                        kind: TokenKind::Identifier(param.clone()),
+                        span,
+                    },
+                    format!("Duplicate parameter '{}'", param),
+                ));
+            }
+            params.push(param);
+        }
+        let mut action = Action::default();
+        let result = self.parse_action_block(&mut action);
+        if templates.iter().any(|template| template.name == name) {
+            return Err(ParseError::UnexpectedToken(
+                name_token,
+                format!("Duplicate template '{}'", name),
+            ));
+        }
+        if !action.transform.is_empty() {
+            self.errors.push(ParseError::UnexpectedToken(
+                name_token,
+                "A template cannot have a transform. Put it on the phase that uses the template"
+                    .to_string(),
+            ));
+        }
+        templates.push(Template {
+            name,
+            params,
+            action,
+            span,
+        });
+        result
+    }
+
+    fn parse_state_block(&mut self, state: &mut State) -> Result<(), ParseError> {
+        self.parse_block(|parser| match parser.peek().kind {
+            TokenKind::Baller => {
+                parser.advance();
+                parser.expect(TokenKind::Equals)?;
+                let (baller, span) = parser.expect_spanned_identifier()?;
+                state.baller = Some(baller);
+                state.baller_span = Some(span);
+                Ok(())
+            }
+            TokenKind::Position => {
+                parser.advance();
+                parser.parse_block(|parser| {
+                    let (player, span) = parser.expect_spanned_identifier()?;
+                    parser.expect(TokenKind::Equals)?;
+                    let point = parser.parse_point()?;
+                    state.positions.insert(player.clone(), point);
+                    state.position_spans.insert(player, span);
+                    Ok(())
+                })
+            }
+            TokenKind::Guards => {
+                parser.advance();
+                parser.parse_block(|parser| {
+                    let (defender, span) = parser.expect_spanned_identifier()?;
+                    parser.expect(TokenKind::Arrow)?;
+                    let (player, player_span) = parser.expect_spanned_identifier()?;
+                    state.guards.push(Matchup {
+                        defender,
+                        player,
+                        span,
+                        player_span,
+                    });
+                    Ok(())
+                })
+            }
+            _ => {
+                let token = parser.peek();
+                let mut msg = "Expected state property (baller, position, guards)".to_string();
+                let TokenKind::Identifier(ref s) = token.kind else {
+                    return Err(ParseError::UnexpectedToken(token, msg));
+                };
+                if let Some(sugg) = get_suggestion(s, &["baller", "position", "guards"]) {
+                    msg = format!("Expected state property. Did you mean '{}'?", sugg);
+                }
+                Err(ParseError::UnexpectedToken(token, msg))
+            }
+        })
+    }
+
+    fn parse_action_block(&mut self, action: &mut Action) -> Result<(), ParseError> {
+        self.parse_block(|parser| match parser.peek().kind {
+            TokenKind::Move | TokenKind::Dribble | TokenKind::Cut => {
+                let kind = match parser.advance().kind {
+                    TokenKind::Dribble => MoveKind::Dribble,
+                    TokenKind::Cut => MoveKind::Cut,
+                    _ => MoveKind::Move,
+                };
+                parser.parse_block(|parser| {
+                    let (player, span) = parser.expect_spanned_identifier()?;
+                    let mut path = Vec::new();
+                    parser.expect(TokenKind::Arrow)?;
+                    loop {
+                        let point = parser.parse_point()?;
+                        let via = if parser.consume_if(TokenKind::Via) {
+                            Some(parser.parse_point()?)
+                        } else {
+                            None
+                        };
+                        path.push(Waypoint { point, via });
+                        if !parser.consume_if(TokenKind::Arrow) {
+                            break;
+                        }
+                    }
+                    let curve = parser.consume_if(TokenKind::Curve);
+                    action.moves.push(MoveAction {
+                        kind,
+                        player,
+                        path,
+                        curve,
+                        span,
+                        comments: Vec::new(),
+                    });
+                    Ok(())
+                })
+            }
+            TokenKind::Screen => {
+                parser.advance();
+                parser.parse_block(|parser| {
+                    let (player, span) = parser.expect_spanned_identifier()?;
+                    parser.expect(TokenKind::Arrow)?;
+                    let (target, target_span) = parser.expect_spanned_identifier()?;
+                    let timing = parser.parse_timing(true)?;
+                    action.screens.push(ScreenAction {
+                        player,
+                        target,
+                        timing,
+                        span,
+                        target_span,
+                        comments: Vec::new(),
+                    });
+                    Ok(())
+                })
+            }
+            TokenKind::Pass | TokenKind::Handoff => {
+                let kind = match parser.advance().kind {
+                    TokenKind::Handoff => PassKind::Handoff,
+                    _ => PassKind::Pass,
+                };
+                parser.parse_block(|parser| {
+                    let (from, span) = parser.expect_spanned_identifier()?;
+                    parser.expect(TokenKind::Arrow)?;
+                    let (to, to_span) = parser.expect_spanned_identifier()?;
+                    let timing = parser.parse_timing(false)?;
+                    action.passes.push(PassAction {
+                        kind,
+                        from,
+                        to,
+                        timing,
+                        span,
+                        to_span,
+                        comments: Vec::new(),
+                    });
+                    Ok(())
+                })
+            }
+            TokenKind::Shot => {
+                parser.advance();
+                parser.parse_block(|parser| {
+                    let (player, span) = parser.expect_spanned_identifier()?;
+                    let timing = parser.parse_timing(false)?;
+                    action.shots.push(ShotAction {
+                        player,
+                        timing,
+                        span,
+                        comments: Vec::new(),
+                    });
+                    Ok(())
+                })
+            }
+            TokenKind::Transform => {
+                parser.advance();
+                parser.parse_transform(&mut action.transform)
+            }
+            // `pick_and_roll(p1, p5)`
+            TokenKind::Identifier(_)
+                if parser
+                    .tokens
+                    .get(parser.pos + 1)
+                    .is_some_and(|token| token.kind == TokenKind::LParenthesis) =>
+            {
+                let (name, span) = parser.expect_spanned_identifier()?;
+                let args = parser.parse_names()?;
+                action.calls.push(TemplateCall { name, args, span });
+                Ok(())
+            }
+            TokenKind::Switch
+            | TokenKind::Hedge
+            | TokenKind::Help
+            | TokenKind::Trap
+            | TokenKind::Closeout => {
+                let kind = match parser.advance().kind {
+                    TokenKind::Switch => DefenseKind::Switch,
+                    TokenKind::Hedge => DefenseKind::Hedge,
+                    TokenKind::Help => DefenseKind::Help,
+                    TokenKind::Trap => DefenseKind::Trap,
+                    _ => DefenseKind::Closeout,
+                };
+                parser.parse_block(|parser| {
+                    let (defender, span) = parser.expect_spanned_identifier()?;
+                    parser.expect(TokenKind::Arrow)?;
+                    let (target, target_span) = parser.expect_spanned_identifier()?;
+                    let timing = parser.parse_timing(true)?;
+                    action.defense.push(DefenseAction {
+                        kind,
+                        defender,
+                        target,
+                        timing,
+                        span,
+                        target_span,
+                        comments: Vec::new(),
+                    });
+                    Ok(())
+                })
+            }
+            _ => {
+                let token = parser.peek();
+                let mut msg = "Expected action property (move, dribble, cut, screen, pass, handoff, shot, switch, hedge, help, trap, closeout, transform) or template call".to_string();
+                let TokenKind::Identifier(ref s) = token.kind else {
+                    return Err(ParseError::UnexpectedToken(token, msg));
+                };
+                if let Some(sugg) = get_suggestion(
+                    s,
+                    &[
+                        "move",
+                        "dribble",
+                        "cut",
+                        "screen",
+                        "pass",
+                        "handoff",
+                        "shot",
+                        "switch",
+                        "hedge",
+                        "help",
+                        "trap",
+                        "closeout",
+                        "transform",
+                    ],
+                ) {
+                    msg = format!("Expected action property. Did you mean '{}'?", sugg);
+                }
+                Err(ParseError::UnexpectedToken(token, msg))
+            }
+        })
+    }
+}
+
+fn action_mut(playbook: &mut Playbook, scope: Scope) -> &mut Action {
+    match scope {
+        Scope::Action => &mut playbook.action,
+        Scope::Phase(i) => &mut playbook.phases[i].action,
+        Scope::Template(i) => &mut playbook.templates[i].action,
     }
 }
 
@@ -404,12 +1210,21 @@ mod tests {
 
         assert_eq!(playbook.players.len(), 2);
         assert_eq!(playbook.state.baller, Some("p1".to_string()));
-        assert_eq!(playbook.state.positions.get("p1"), Some(&(0.0, 0.0)));
-        assert_eq!(playbook.state.positions.get("p2"), Some(&(10.0, 20.0)));
+        assert_eq!(
+            playbook.state.positions.get("p1"),
+            Some(&Point::Coord(0.0, 0.0))
+        );
+        assert_eq!(
+            playbook.state.positions.get("p2"),
+            Some(&Point::Coord(10.0, 20.0))
+        );
 
         assert_eq!(playbook.action.moves.len(), 1);
         assert_eq!(playbook.action.moves[0].player, "p2");
-        assert_eq!(playbook.action.moves[0].target, (30.0, 40.0));
+        assert_eq!(
+            playbook.action.moves[0].target(),
+            Some(&Point::Coord(30.0, 40.0))
+        );
 
         assert_eq!(playbook.action.passes.len(), 1);
         assert_eq!(playbook.action.passes[0].from, "p1");
@@ -420,6 +1235,464 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_phases() {
+        let input = r#"
+        players = { p1, p2 }
+        state = { baller = p1, position = { p1 = (0, 60), p2 = (90, -80) } }
+        phase 2 = {
+            pass = { p1 -> p2 },
+        }
+        phase 1 = {
+            move = { p2 -> (70, 20) },
+        }
+        "#;
+        let mut lexer = Lexer::new(input);
+        let mut parser = Parser::new(lexer.tokenize());
+        let playbook = parser.parse().unwrap();
+
+        let numbers: Vec<u32> = playbook.phases.iter().map(|p| p.number).collect();
+        assert_eq!(numbers, vec![1, 2]);
+        assert_eq!(playbook.phases[0].action.moves.len(), 1);
+        assert_eq!(playbook.phases[1].action.passes.len(), 1);
+        assert_eq!(playbook.phase_actions().len(), 2);
+    }
+
+    #[test]
+    fn test_parse_phase_errors() {
+        let input = "phase 1 = { } phase 1 = { }";
+        let mut parser = Parser::new(Lexer::new(input).tokenize());
+        match parser.parse() {
+            Err(ParseError::UnexpectedToken(_, msg)) => assert_eq!(msg, "Duplicate phase 1"),
+            _ => panic!("Expected duplicate phase error"),
+        }
+
+        let input = "action = { } phase 1 = { }";
+        let mut parser = Parser::new(Lexer::new(input).tokenize());
+        match parser.parse() {
+            Err(ParseError::UnexpectedToken(token, _)) => assert_eq!(token.kind, TokenKind::Action),
+            _ => panic!("Expected mixed sections error"),
+        }
+    }
+
+    #[test]
+    fn test_parse_with_recovery() {
+        let input = r#"
+        players = { p1, 2, p3 }
+        staet = { baller = p1 }
+        action = {
+            move = { p1 -> (0, 0), p3 -> 5, p3 -> (10, 10) },
+            jump = { p1 },
+            pass = { p1 -> p3 },
+        }
+        "#;
+        let mut parser = Parser::new(Lexer::new(input).tokenize());
+        let (playbook, errors) = parser.parse_with_recovery();
+
+        let messages: Vec<&str> = errors
+            .iter()
+            .map(|e| match e {
+                ParseError::UnexpectedToken(_, msg) => msg.as_str(),
+                _ => "",
+            })
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                "Expected Identifier",
+                "Expected section start. Did you mean 'state'?",
+                "Expected LParenthesis",
+                "Expected action property (move, dribble, cut, screen, pass, handoff, shot, switch, hedge, help, trap, closeout, transform) or template call",
+            ]
+        );
+
+        assert_eq!(playbook.players, vec!["p1", "p3"]);
+        assert_eq!(playbook.action.moves.len(), 2);
+        assert_eq!(
+            playbook.action.moves[1].target(),
+            Some(&Point::Coord(10.0, 10.0))
+        );
+        assert_eq!(playbook.action.passes.len(), 1);
+    }
+
+    #[test]
+    fn test_parse_timings() {
+        let input = r#"
+        action = {
+            screen = { p4 -> p2:0.3 },
+            pass = { p1 -> p2:after p4.screen, p2 -> p3:before p3.move },
+        }
+        "#;
+        let mut parser = Parser::new(Lexer::new(input).tokenize());
+        let playbook = parser.parse().unwrap();
+
+        assert_eq!(playbook.action.screens[0].timing, Timing::Fraction(0.3));
+        match &playbook.action.passes[0].timing {
+            Timing::AfterEvent(event) => {
+                assert_eq!(event.player, "p4");
+                assert_eq!(event.kind, EventKind::Screen);
+                assert_eq!(event.span.line, 4);
+            }
+            other => panic!("Expected event timing, got {:?}", other),
+        }
+        assert!(matches!(
+            &playbook.action.passes[1].timing,
+            Timing::BeforeEvent(event) if event.kind == EventKind::Move
+        ));
+
+        let mut parser = Parser::new(Lexer::new("action = { pass = { p1 -> p2:1.5 } }").tokenize());
+        match parser.parse() {
+            Err(ParseError::UnexpectedToken(_, msg)) => {
+                assert_eq!(msg, "Timing fraction must be between 0 and 1")
+            }
+            _ => panic!("Expected fraction error"),
+        }
+    }
+
+    #[test]
+    fn test_parse_spots() {
+        let input = r#"
+        spots = { deep = (0, 80) }
+        state = { position = { p1 = left_wing } }
+        action = { move = { p1 -> deep via right_elbow } }
+        "#;
+        let mut parser = Parser::new(Lexer::new(input).tokenize());
+        let playbook = parser.parse().unwrap();
+
+        assert_eq!(playbook.spots.get("deep"), Some(&(0.0, 80.0)));
+        let p1 = &playbook.state.positions["p1"];
+        assert!(matches!(p1, Point::Name(name, _) if name == "left_wing"));
+        assert_eq!(playbook.spot("left_wing"), Some((-62.0, 27.0)));
+        assert_eq!(playbook.spot("deep"), Some((0.0, 80.0)));
+        let waypoint = &playbook.action.moves[0].path[0];
+        assert!(matches!(&waypoint.point, Point::Name(name, _) if name == "deep"));
+        assert!(matches!(&waypoint.via, Some(Point::Name(name, _)) if name == "right_elbow"));
+    }
+
+    #[test]
+    fn test_parse_court() {
+        let input = "court = nba\nunits = meters\nplayers = { p1 }";
+        let mut parser = Parser::new(Lexer::new(input).tokenize());
+        let playbook = parser.parse().unwrap();
+        assert_eq!(playbook.court, Some(CourtSpec::NBA));
+        assert_eq!(playbook.units, Some(Units::Meters));
+        assert_eq!(playbook.court().units, Some(Units::Meters));
+
+        let mut parser = Parser::new(Lexer::new("view = three_quarter").tokenize());
+        assert_eq!(parser.parse().unwrap().view, Some(CourtView::ThreeQuarter));
+        let mut parser = Parser::new(Lexer::new("view = quarter").tokenize());
+        match parser.parse() {
+            Err(ParseError::UnexpectedToken(_, msg)) => {
+                assert_eq!(msg, "Expected view (half, three_quarter, full)")
+            }
+            _ => panic!("Expected unknown view error"),
+        }
+
+        let mut parser = Parser::new(Lexer::new("court = fibba").tokenize());
+        match parser.parse() {
+            Err(ParseError::UnexpectedToken(_, msg)) => {
+                assert_eq!(msg, "Expected court. Did you mean 'fiba'?")
+            }
+            _ => panic!("Expected unknown court error"),
+        }
+    }
+
+    #[test]
+    fn test_parse_meta() {
+        let input = r#"
+        meta = {
+            name = "Horns Flare",
+            call = "Fist 2",
+            tags = ["ATO", "horns",],
+            notes = "Flare for the \"2\" if the defense switches",
+        }
+        players = { p1 }
+        "#;
+        let mut parser = Parser::new(Lexer::new(input).tokenize());
+        let meta = parser.parse().unwrap().meta.unwrap();
+        assert_eq!(meta.name.as_deref(), Some("Horns Flare"));
+        assert_eq!(meta.call.as_deref(), Some("Fist 2"));
+        assert_eq!(meta.tags, ["ATO", "horns"]);
+        assert_eq!(
+            meta.notes.as_deref(),
+            Some("Flare for the \"2\" if the defense switches")
+        );
+        assert_eq!(meta.formation, None);
+
+        let mut parser = Parser::new(Lexer::new("meta = { tag = [] }").tokenize());
+        match parser.parse() {
+            Err(ParseError::UnexpectedToken(_, msg)) => {
+                assert_eq!(msg, "Expected meta field. Did you mean 'tags'?")
+            }
+            _ => panic!("Expected unknown field error"),
+        }
+        let mut parser = Parser::new(Lexer::new("meta = { name = Horns }").tokenize());
+        assert!(matches!(
+            parser.parse(),
+            Err(ParseError::UnexpectedToken(_, msg)) if msg == "Expected String (\"...\")"
+        ));
+        let mut parser = Parser::new(Lexer::new("meta = { name = \"Horns }").tokenize());
+        assert!(matches!(
+            parser.parse(),
+            Err(ParseError::UnexpectedToken(_, msg)) if msg.starts_with("Unterminated string")
+        ));
+    }
+
+    #[test]
+    fn test_parse_imports() {
+        let input = r#"
+        import "formations/horns.playbook"
+        import "spots.playbook"
+        use formation horns
+        state = { baller = p2 }
+        "#;
+        let mut parser = Parser::new(Lexer::new(input).tokenize());
+        let playbook = parser.parse().unwrap();
+        let paths: Vec<&str> = playbook.imports.iter().map(|i| i.path.as_str()).collect();
+        assert_eq!(paths, ["formations/horns.playbook", "spots.playbook"]);
+        assert_eq!(playbook.imports[0].span.column, 16);
+        let formation = playbook.formation.unwrap();
+        assert_eq!(formation.name, "horns");
+        assert_eq!(formation.span.line, 4);
+
+        let mut parser = Parser::new(Lexer::new("use horns").tokenize());
+        assert!(matches!(
+            parser.parse(),
+            Err(ParseError::UnexpectedToken(_, msg)) if msg == "Expected 'formation' (use formation <name>)"
+        ));
+        let input = "use formation horns\nuse formation spain";
+        let mut parser = Parser::new(Lexer::new(input).tokenize());
+        assert!(matches!(
+            parser.parse(),
+            Err(ParseError::UnexpectedToken(token, msg))
+                if msg == "A play can use only one formation" && token.span.line == 2
+        ));
+    }
+
+    #[test]
+    fn test_parse_templates() {
+        let input = r#"
+        transform = { mirror, translate = (10, -5) }
+        template pick_and_roll(handler, screener) = {
+            // Flat screen
+            screen = { screener -> handler },
+        }
+        phase 1 = {
+            transform = { rotate = -90 },
+            pick_and_roll(p1, p5),
+            pass = { p1 -> p2 },
+        }
+        "#;
+        let mut parser = Parser::new(Lexer::new(input).tokenize());
+        let playbook = parser.parse().unwrap();
+        assert_eq!(
+            playbook.transform,
+            [Transform::Mirror, Transform::Translate(10.0, -5.0)]
+        );
+        let template = &playbook.templates[0];
+        assert_eq!(template.params, ["handler", "screener"]);
+        assert_eq!(template.action.screens[0].comments[0].text, "Flat screen");
+        let action = &playbook.phases[0].action;
+        assert_eq!(action.transform, [Transform::Rotate(-90.0)]);
+        assert_eq!(action.calls[0].name, "pick_and_roll");
+        let args: Vec<&str> = action.calls[0]
+            .args
+            .iter()
+            .map(|(a, _)| a.as_str())
+            .collect();
+        assert_eq!(args, ["p1", "p5"]);
+        assert_eq!(action.passes.len(), 1);
+
+        let errors = |input: &str| -> Vec<String> {
+            let (_, errors) = Parser::new(Lexer::new(input).tokenize()).parse_with_recovery();
+            errors
+                .into_iter()
+                .map(|e| match e {
+                    ParseError::UnexpectedToken(_, msg) => msg,
+                    _ => String::new(),
+                })
+                .collect()
+        };
+        assert_eq!(
+            errors("transform = { mirorr }"),
+            ["Expected transform. Did you mean 'mirror'?"]
+        );
+        assert_eq!(
+            errors("template a(x, x) = { shot = { x } }\ntemplate a() = { }"),
+            ["Duplicate parameter 'x'", "Duplicate template 'a'"]
+        );
+        assert_eq!(
+            errors("template a(x) = { transform = { mirror } }"),
+            ["A template cannot have a transform. Put it on the phase that uses the template"]
+        );
+    }
+
+    #[test]
+    fn test_parse_relative_points() {
+        let input = r#"
+        action = {
+            move = { p1 -> p3 + (10, 0) + (0, -5) },
+            cut = { p2 -> toward(basket, 15) -> polar(30, -45) },
+        }
+        "#;
+        let mut parser = Parser::new(Lexer::new(input).tokenize());
+        let moves = parser.parse().unwrap().action.moves;
+
+        let Point::Offset(base, (0.0, -5.0)) = &moves[0].path[0].point else {
+            panic!("Expected an offset");
+        };
+        assert!(
+            matches!(base.as_ref(), Point::Offset(p3, (10.0, 0.0)) if matches!(p3.as_ref(), Point::Name(name, _) if name == "p3"))
+        );
+        assert!(matches!(&moves[1].path[0].point, Point::Toward(_, 15.0, _)));
+        assert_eq!(moves[1].path[1].point, Point::Polar(30.0, -45.0));
+    }
+
+    #[test]
+    fn test_parse_waypoints() {
+        let input =
+            "action = { cut = { p2 -> (30, 0) -> (70, 20) via (60, -10) curve, p3 -> (0, 0) } }";
+        let mut parser = Parser::new(Lexer::new(input).tokenize());
+        let moves = parser.parse().unwrap().action.moves;
+
+        assert_eq!(
+            moves[0].path,
+            vec![
+                Waypoint {
+                    point: Point::Coord(30.0, 0.0),
+                    via: None
+                },
+                Waypoint {
+                    point: Point::Coord(70.0, 20.0),
+                    via: Some(Point::Coord(60.0, -10.0))
+                },
+            ]
+        );
+        assert!(moves[0].curve);
+        assert_eq!(moves[0].target(), Some(&Point::Coord(70.0, 20.0)));
+        assert_eq!(moves[1].path.len(), 1);
+        assert!(!moves[1].curve);
+    }
+
+    #[test]
+    fn test_parse_ball_actions() {
+        let input = r#"
+        action = {
+            dribble = { p1 -> (20, 30) },
+            cut = { p3 -> (0, -60) },
+            handoff = { p1 -> p2 },
+            pass = { p2 -> p3:after },
+            shot = { p3 },
+        }
+        "#;
+        let mut parser = Parser::new(Lexer::new(input).tokenize());
+        let action = parser.parse().unwrap().action;
+
+        let kinds: Vec<MoveKind> = action.moves.iter().map(|m| m.kind).collect();
+        assert_eq!(kinds, vec![MoveKind::Dribble, MoveKind::Cut]);
+        let kinds: Vec<PassKind> = action.passes.iter().map(|p| p.kind).collect();
+        assert_eq!(kinds, vec![PassKind::Handoff, PassKind::Pass]);
+        assert_eq!(action.shots[0].player, "p3");
+        assert_eq!(action.shots[0].timing, Timing::None);
+    }
+
+    #[test]
+    fn test_parse_defense() {
+        let input = r#"
+        players = { p1, p5 }
+        defense = { x1, x5 }
+        state = {
+            baller = p1,
+            position = { p1 = (0, 60), p5 = (0, 20), x1 = (0, 45), x5 = (0, 5) },
+            guards = { x1 -> p1, x5 -> p5 },
+        }
+        action = {
+            hedge = { x5 -> p1:middle },
+            switch = { x1 -> x5 },
+        }
+        "#;
+        let mut parser = Parser::new(Lexer::new(input).tokenize());
+        let playbook = parser.parse().unwrap();
+
+        assert_eq!(playbook.defense, vec!["x1", "x5"]);
+        assert_eq!(playbook.state.guards.len(), 2);
+        assert_eq!(playbook.state.guards[1].defender, "x5");
+        assert_eq!(playbook.state.guards[1].player, "p5");
+
+        let defense = &playbook.action.defense;
+        assert_eq!(defense.len(), 2);
+        assert_eq!(defense[0].kind, DefenseKind::Hedge);
+        assert_eq!(defense[0].target, "p1");
+        assert_eq!(defense[0].timing, Timing::Middle);
+        assert_eq!(defense[1].kind, DefenseKind::Switch);
+        assert_eq!(defense[1].timing, Timing::None);
+    }
+
+    #[test]
+    fn test_comments() {
+        let input = r#"
+        // Horns
+        players = { p1, p2 } /* two players */
+        state = {
+            baller = p1,
+            position = {
+                p1 = (0, 60), // point guard
+                p2 = (90, -80),
+            },
+        }
+        phase 1 = { // entry
+            move = {
+                /* cut hard */
+                p2 -> (70, 20),
+            },
+        }
+        phase 2 = {
+            pass = { p1 -> p2 }, // swing
+        }
+        // end
+        "#;
+        let mut parser = Parser::new(Lexer::new(input).tokenize());
+        let playbook = parser.parse().unwrap();
+        let texts = |comments: &[Comment]| -> Vec<String> {
+            comments.iter().map(|c| c.text.clone()).collect()
+        };
+
+        assert_eq!(
+            texts(&playbook.comments),
+            vec!["Horns", "two players", "end"]
+        );
+        assert_eq!(
+            texts(&playbook.state.position_comments["p1"]),
+            vec!["point guard"]
+        );
+        assert!(!playbook.state.position_comments.contains_key("p2"));
+        assert_eq!(texts(&playbook.phases[0].comments), vec!["entry"]);
+        assert_eq!(
+            texts(&playbook.phases[0].action.moves[0].comments),
+            vec!["cut hard"]
+        );
+        assert_eq!(
+            texts(&playbook.phases[1].action.passes[0].comments),
+            vec!["swing"]
+        );
+    }
+
+    #[test]
+    fn test_unterminated_block_comment() {
+        let input = "players = { p1 } /* state = { }";
+        let mut parser = Parser::new(Lexer::new(input).tokenize());
+        let (playbook, errors) = parser.parse_with_recovery();
+        assert_eq!(errors.len(), 1);
+        match &errors[0] {
+            ParseError::UnexpectedToken(token, msg) => {
+                assert_eq!(msg, "Unterminated block comment (missing '*/')");
+                assert_eq!(token.span.column, 18);
+            }
+            _ => panic!("Expected unterminated comment error"),
+        }
+        assert_eq!(playbook.players, vec!["p1"]);
+    }
+
     #[test]
     fn test_levenshtein() {
         assert_eq!(levenshtein("kitten", "sitting"), 3);
@@ -0,0 +1,419 @@
+use super::{Renderer, with_notes};
+use crate::ir::*;
+
+/// The ball is drawn next to its holder, like in the static diagram.
+const BALL_OFFSET: (f64, f64) = (10.0, -10.0);
+/// Keyframes per curved or multi-waypoint path, so players follow the bends.
+const PATH_SAMPLES: usize = 8;
+
+/// A phase placed on the animation clock, `start` beats into the play.
+struct PhaseClock<'a> {
+    scene: &'a Scene,
+    start: f64,
+}
+
+impl PhaseClock<'_> {
+    fn end(&self) -> f64 {
+        self.start + self.scene.duration
+    }
+
+    /// Beats (within the phase) at which someone changes direction.
+    fn keyframes(&self) -> Vec<f64> {
+        let mut times = vec![0.0, MOVE_BEATS, self.scene.duration];
+        for pass in self.scene.passes() {
+            times.push(pass.start);
+            times.push(pass.end);
+        }
+        for interaction in &self.scene.interactions {
+            match interaction {
+                Interaction::Move(m) | Interaction::Dribble(m) | Interaction::Cut(m)
+                    if !m.is_straight() =>
+                {
+                    let step = (m.end - m.start) / PATH_SAMPLES as f64;
+                    times.extend((1..PATH_SAMPLES).map(|i| m.start + step * i as f64));
+                }
+                Interaction::Shot(s) => times.extend([s.start, s.end]),
+                Interaction::Defense(d) => times.extend([d.start, d.end]),
+                _ => {}
+            }
+        }
+        times.sort_by(f64::total_cmp);
+        times.dedup();
+        times
+    }
+}
+
+impl Renderer {
+    /// Renders the play as a self-contained SMIL animation looping every `duration` seconds.
+    /// Phases play one after another on a single court, following the scene timelines:
+    /// players travel along their moves, screens appear when they are set and the ball
+    /// follows its holder and the passes.
+    pub fn render_animated_scenes(&self, scenes: &[Scene], duration: f64) -> String {
+        let (clocks, total) = clocks(scenes);
+        let timeline = Timeline { total, duration };
+        let mut svg = self.render_stage(scenes);
+
+        // 1. Paths of the current phase, screens once they are set
+        for clock in &clocks {
+            let end = clock.end();
+            let mut paths = String::new();
+            for interaction in &clock.scene.interactions {
+                match interaction {
+                    Interaction::Screen(s) => {
+                        svg.push_str(&timeline.visible_between(
+                            &self.render_interaction(interaction),
+                            clock.start + s.start,
+                            end,
+                        ));
+                    }
+                    _ => paths.push_str(&self.render_interaction(interaction)),
+                }
+            }
+            svg.push_str(&timeline.visible_between(&paths, clock.start, end));
+        }
+
+        // 2. Players
+        if let Some(first) = scenes.first() {
+            for entity in &first.entities {
+                let mut keyframes = Vec::new();
+                for clock in &clocks {
+                    for t in clock.keyframes() {
+                        if let Some(pos) = clock.scene.position_at(&entity.id, t) {
+                            keyframes.push((clock.start + t, pos));
+                        }
+                    }
+                }
+                svg.push_str(&with_notes(
+                    self.render_moving_player(entity, &timeline, &keyframes),
+                    &entity.notes,
+                ));
+            }
+        }
+
+        // 3. Ball, wherever someone has it. After a shot it stays at the hoop.
+        let mut keyframes: Vec<(f64, (f64, f64))> = Vec::new();
+        let mut last = None;
+        for clock in &clocks {
+            for t in clock.keyframes() {
+                if let Some((x, y)) = clock.scene.ball_at(t).or(last) {
+                    last = Some((x, y));
+                    keyframes.push((clock.start + t, (x + BALL_OFFSET.0, y + BALL_OFFSET.1)));
+                }
+            }
+        }
+        // The animation has to start at 0, even when the ball shows up later
+        if let Some(&(t, pos)) = keyframes.first()
+            && t > 0.0
+        {
+            keyframes.insert(0, (0.0, pos));
+        }
+        if !keyframes.is_empty() {
+            let (x, y) = keyframes[0].1;
+            svg.push_str(&format!(
+                "<circle cx=\"0\" cy=\"0\" r=\"4\" fill=\"{}\" stroke=\"{}\" stroke-width=\"1\" transform=\"translate({}, {})\">{}</circle>",
+                self.theme.ball.fill,
+                self.theme.ball.stroke,
+                x,
+                y,
+                timeline.translate(&keyframes)
+            ));
+        }
+
+        svg.push_str(&self.theme.render_markers());
+        svg.push_str("</svg>");
+        svg
+    }
+
+    /// Renders the play as `count` stills, evenly spread from its start to its end,
+    /// for GIFs and videos.
+    pub fn render_frames(&self, input: &str, count: usize) -> Result<Vec<String>, String> {
+        let scenes = self.compile(input)?;
+        Ok(self.render_frame_scenes(&scenes, count))
+    }
+
+    /// Stills of already compiled scenes.
+    pub fn render_frame_scenes(&self, scenes: &[Scene], count: usize) -> Vec<String> {
+        let (clocks, total) = clocks(scenes);
+        let last = count.saturating_sub(1).max(1) as f64;
+        (0..count)
+            .map(|i| self.render_frame(scenes, &clocks, total * i as f64 / last))
+            .collect()
+    }
+
+    /// The play `time` beats in: paths of the current phase, screens once set,
+    /// players and ball where they are.
+    fn render_frame(&self, scenes: &[Scene], clocks: &[PhaseClock], time: f64) -> String {
+        let mut svg = self.render_stage(scenes);
+        let Some(clock) = clocks
+            .iter()
+            .rev()
+            .find(|clock| clock.start <= time)
+            .or(clocks.first())
+        else {
+            svg.push_str("</svg>");
+            return svg;
+        };
+        let t = time - clock.start;
+
+        for interaction in &clock.scene.interactions {
+            match interaction {
+                Interaction::Screen(s) if t < s.start => {}
+                _ => svg.push_str(&self.render_interaction(interaction)),
+            }
+        }
+        for entity in &clock.scene.entities {
+            let (x, y) = clock
+                .scene
+                .position_at(&entity.id, t)
+                .unwrap_or(entity.start_pos);
+            svg.push_str(&format!(
+                "<g transform=\"translate({}, {})\">",
+                num(x),
+                num(y)
+            ));
+            svg.push_str(&self.render_marker(entity, (0.0, 0.0)));
+            svg.push_str("</g>");
+        }
+        if let Some((x, y)) = ball_at(clocks, clock, t) {
+            svg.push_str(&format!(
+                "<circle cx=\"{}\" cy=\"{}\" r=\"4\" fill=\"{}\" stroke=\"{}\" stroke-width=\"1\" />",
+                num(x + BALL_OFFSET.0),
+                num(y + BALL_OFFSET.1),
+                self.theme.ball.fill,
+                self.theme.ball.stroke
+            ));
+        }
+
+        svg.push_str(&self.theme.render_markers());
+        svg.push_str("</svg>");
+        svg
+    }
+
+    /// Opens the SVG with the background, caption and court, the same for every phase.
+    fn render_stage(&self, scenes: &[Scene]) -> String {
+        let (court, view) = self.court_of(scenes);
+        let frame = self.frame_of(scenes, &court, view);
+        let mut svg = self.open_svg(scenes, &frame, 1);
+        svg.push_str(&self.render_court(&court, view));
+        svg
+    }
+
+    fn render_moving_player(
+        &self,
+        entity: &Entity,
+        timeline: &Timeline,
+        keyframes: &[(f64, (f64, f64))],
+    ) -> String {
+        let (x, y) = keyframes.first().map_or(entity.start_pos, |(_, pos)| *pos);
+        let mut player = format!("<g transform=\"translate({}, {})\">", x, y);
+        player.push_str(&self.render_marker(entity, (0.0, 0.0)));
+        player.push_str(&timeline.translate(keyframes));
+        player.push_str("</g>");
+        player
+    }
+}
+
+/// Phases one after another, and the length of the whole play in beats.
+fn clocks(scenes: &[Scene]) -> (Vec<PhaseClock<'_>>, f64) {
+    let mut clocks = Vec::new();
+    let mut total = 0.0;
+    for scene in scenes {
+        clocks.push(PhaseClock {
+            scene,
+            start: total,
+        });
+        total += scene.duration;
+    }
+    let total = if total > 0.0 { total } else { MOVE_BEATS };
+    (clocks, total)
+}
+
+/// The ball `t` beats into `clock`, or where it was left at the end of an earlier phase.
+fn ball_at(clocks: &[PhaseClock], clock: &PhaseClock, t: f64) -> Option<(f64, f64)> {
+    clock.scene.ball_at(t).or_else(|| {
+        clocks
+            .iter()
+            .take_while(|earlier| earlier.start < clock.start)
+            .filter_map(|earlier| earlier.scene.ball_at(earlier.scene.duration))
+            .last()
+    })
+}
+
+/// Maps clock units onto the looping SMIL timeline.
+struct Timeline {
+    total: f64,
+    duration: f64,
+}
+
+impl Timeline {
+    fn key_time(&self, t: f64) -> String {
+        num((t / self.total).clamp(0.0, 1.0))
+    }
+
+    fn repeat(&self) -> String {
+        format!("dur=\"{}s\" repeatCount=\"indefinite\"", num(self.duration))
+    }
+
+    /// Shows `content` only during `[from, to]`.
+    fn visible_between(&self, content: &str, from: f64, to: f64) -> String {
+        if content.is_empty() {
+            return String::new();
+        }
+        format!(
+            "<g opacity=\"0\">{}<animate attributeName=\"opacity\" calcMode=\"discrete\" values=\"0;1;0\" keyTimes=\"0;{};{}\" {} /></g>",
+            content,
+            self.key_time(from),
+            self.key_time(to),
+            self.repeat()
+        )
+    }
+
+    /// Moves the parent element through `keyframes` (clock time, position).
+    fn translate(&self, keyframes: &[(f64, (f64, f64))]) -> String {
+        let values: Vec<String> = keyframes
+            .iter()
+            .map(|(_, (x, y))| format!("{} {}", num(*x), num(*y)))
+            .collect();
+        let times: Vec<String> = keyframes.iter().map(|(t, _)| self.key_time(*t)).collect();
+        format!(
+            "<animateTransform attributeName=\"transform\" type=\"translate\" values=\"{}\" keyTimes=\"{}\" {} />",
+            values.join(";"),
+            times.join(";"),
+            self.repeat()
+        )
+    }
+}
+
+/// Rounds to 4 decimals so key times stay readable.
+fn num(value: f64) -> String {
+    let rounded = (value * 10000.0).round() / 10000.0;
+    if rounded == 0.0 {
+        "0".to_string()
+    } else {
+        rounded.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ball_follows_passes() {
+        let renderer = Renderer::new();
+        let input = r#"
+            players = { p1, p2 }
+            state = { baller = p1, position = { p1 = (0, 60), p2 = (90, -80) } }
+            action = {
+                move = { p2 -> (70, 20) },
+                pass = { p1 -> p2:after },
+            }
+        "#;
+        let svg = renderer
+            .render_animated(input, 2.0)
+            .expect("Failed to render");
+
+        // p2 moves during the first unit, then holds while the pass flies
+        assert!(svg.contains("values=\"90 -80;70 20;70 20\" keyTimes=\"0;0.8;1\" dur=\"2s\""));
+        // The ball waits with p1, then flies to p2 after the move
+        assert!(svg.contains("values=\"10 50;10 50;80 10\" keyTimes=\"0;0.8;1\""));
+    }
+
+    #[test]
+    fn test_ball_after_shot() {
+        let renderer = Renderer::new();
+        let input = r#"
+            players = { p1, p2 }
+            state = { baller = p1, position = { p1 = (0, 60), p2 = (50, 0) } }
+            phase 1 = { pass = { p1 -> p2 }, shot = { p2 } }
+            phase 2 = { move = { p1 -> (0, 0) } }
+        "#;
+        let svg = renderer
+            .render_animated(input, 4.0)
+            .expect("Failed to render");
+
+        // Nobody has the ball in phase 2, so it stays at the hoop until the loop ends
+        let hoop = renderer.compile(input).expect("Failed to compile")[0]
+            .shot()
+            .expect("No shot")
+            .to;
+        let ball = &svg[svg.rfind("<circle").expect("No ball")..];
+        let attribute = |name: &str| {
+            let start = ball.find(&format!("{}=\"", name)).expect("No attribute") + name.len() + 2;
+            &ball[start..start + ball[start..].find('"').unwrap()]
+        };
+        assert!(attribute("keyTimes").ends_with(";1"));
+        assert!(attribute("values").ends_with(&format!(
+            ";{} {}",
+            num(hoop.0 + BALL_OFFSET.0),
+            num(hoop.1 + BALL_OFFSET.1)
+        )));
+
+        // Stills after the shot keep the ball at the hoop too
+        let frames = renderer.render_frames(input, 5).expect("Failed to render");
+        assert!(frames[4].contains(&format!(
+            "cx=\"{}\" cy=\"{}\"",
+            num(hoop.0 + BALL_OFFSET.0),
+            num(hoop.1 + BALL_OFFSET.1)
+        )));
+    }
+
+    #[test]
+    fn test_phases_play_in_order() {
+        let renderer = Renderer::new();
+        let input = r#"
+            players = { p1, p2 }
+            state = { baller = p1, position = { p1 = (0, 60), p2 = (50, 0) } }
+            phase 1 = { move = { p1 -> (0, 0) } }
+            phase 2 = { screen = { p2 -> p1:middle } }
+        "#;
+        let svg = renderer
+            .render_animated(input, 4.0)
+            .expect("Failed to render");
+
+        // Phase 1 paths are shown during the first half only
+        assert!(svg.contains("values=\"0;1;0\" keyTimes=\"0;0;0.5\""));
+        // The screen is set halfway through phase 2
+        assert!(svg.contains("values=\"0;1;0\" keyTimes=\"0;0.75;1\""));
+        assert!(svg.contains("values=\"0 60;0 0;0 0;0 0\" keyTimes=\"0;0.5;0.5;1\""));
+    }
+
+    #[test]
+    fn test_players_follow_paths() {
+        let renderer = Renderer::new();
+        let input = r#"
+            players = { p1 }
+            state = { position = { p1 = (0, 0) } }
+            action = { move = { p1 -> (40, 0) -> (40, 40) } }
+        "#;
+        let svg = renderer
+            .render_animated(input, 1.0)
+            .expect("Failed to render");
+
+        // Halfway through the move, p1 turns the corner
+        assert!(svg.contains("values=\"0 0;10 0;20 0;30 0;40 0;40 10;40 20;40 30;40 40\""));
+    }
+
+    #[test]
+    fn test_frames_step_through_the_play() {
+        let renderer = Renderer::new();
+        let input = r#"
+            players = { p1, p2 }
+            state = { baller = p1, position = { p1 = (0, 60), p2 = (50, 0) } }
+            phase 1 = { move = { p1 -> (0, 0) } }
+            phase 2 = { pass = { p1 -> p2 }, screen = { p2 -> p1:middle } }
+        "#;
+        // 2.25 beats: the move, then the pass and the screen
+        let frames = renderer.render_frames(input, 10).expect("Failed to render");
+        assert_eq!(frames.len(), 10);
+
+        assert!(frames[0].contains("translate(0, 60)"));
+        // Half a beat in, p1 is halfway with the ball
+        assert!(frames[2].contains("translate(0, 30)"));
+        assert!(frames[2].contains("cx=\"10\" cy=\"20\""));
+        // The screen shows once set, the pass ends with p2
+        assert!(!frames[5].contains("x1=\"50\" y1=\"0\""));
+        assert!(frames[9].contains("x1=\"50\" y1=\"0\""));
+        assert!(frames[9].contains("cx=\"60\" cy=\"-10\""));
+    }
+}
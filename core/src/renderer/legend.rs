@@ -0,0 +1,161 @@
+//! A key to the lines of a play, for printed playbooks.
+
+use super::{Renderer, Theme};
+use crate::ir::*;
+use std::mem::discriminant;
+
+/// Entries per row, and their size in drawing units.
+const COLUMNS: usize = 4;
+const ENTRY_WIDTH: f64 = 100.0;
+const ROW_HEIGHT: f64 = 20.0;
+/// Length of the sample line before the label.
+const SAMPLE_LENGTH: f64 = 36.0;
+
+impl Renderer {
+    /// Renders a legend of the kinds of lines drawn in the play, in the theme's styles.
+    /// `None` if the play draws no lines.
+    pub fn render_legend(&self, input: &str) -> Result<Option<String>, String> {
+        let scenes = self.compile(input)?;
+        Ok(self.render_legend_of(&scenes))
+    }
+
+    /// The legend for already compiled scenes.
+    pub fn render_legend_of(&self, scenes: &[Scene]) -> Option<String> {
+        let mut kinds: Vec<&Interaction> = Vec::new();
+        for interaction in scenes.iter().flat_map(|scene| &scene.interactions) {
+            if !kinds
+                .iter()
+                .any(|kind| discriminant(*kind) == discriminant(interaction))
+            {
+                kinds.push(interaction);
+            }
+        }
+        if kinds.is_empty() {
+            return None;
+        }
+        kinds.sort_by_key(|kind| order(kind));
+
+        let rows = kinds.len().div_ceil(COLUMNS);
+        let view_width = ENTRY_WIDTH * COLUMNS as f64;
+        let view_height = ROW_HEIGHT * rows as f64;
+        let height = (self.width as f64 * view_height / view_width).round();
+        let mut svg = format!(
+            "<svg width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\" xmlns=\"http://www.w3.org/2000/svg\">",
+            self.width, height, view_width, view_height
+        );
+        svg.push_str(&format!(
+            "<rect x=\"0\" y=\"0\" width=\"{}\" height=\"{}\" fill=\"{}\" />",
+            view_width, view_height, self.theme.background
+        ));
+        for (i, kind) in kinds.iter().enumerate() {
+            let x = ENTRY_WIDTH * (i % COLUMNS) as f64 + 6.0;
+            let y = ROW_HEIGHT * (i / COLUMNS) as f64 + ROW_HEIGHT / 2.0;
+            let sample = sample(kind, (x, y), (x + SAMPLE_LENGTH, y));
+            svg.push_str(&self.render_interaction(&sample));
+            svg.push_str(&format!(
+                "<text x=\"{}\" y=\"{}\" font-size=\"9\" dominant-baseline=\"central\" font-family=\"{}\"{}>{}</text>",
+                x + SAMPLE_LENGTH + 6.0,
+                y,
+                self.theme.font,
+                Theme::text_fill(&self.theme.text),
+                label(kind)
+            ));
+        }
+        svg.push_str(&self.theme.render_markers());
+        svg.push_str("</svg>");
+        Some(svg)
+    }
+}
+
+/// `interaction` as a straight line from `from` to `to`, without notes.
+fn sample(interaction: &Interaction, from: (f64, f64), to: (f64, f64)) -> Interaction {
+    let mut sample = interaction.clone();
+    match &mut sample {
+        Interaction::Move(m) | Interaction::Dribble(m) | Interaction::Cut(m) => {
+            m.from = from;
+            m.to = to;
+            m.path = vec![PathSegment::Line(to)];
+            m.notes.clear();
+        }
+        Interaction::Pass(p) | Interaction::Handoff(p) => {
+            p.from = from;
+            p.to = to;
+            p.notes.clear();
+        }
+        Interaction::Screen(s) => {
+            s.from = from;
+            s.to = to;
+            s.notes.clear();
+        }
+        Interaction::Shot(s) => {
+            s.from = from;
+            s.to = to;
+            s.notes.clear();
+        }
+        Interaction::Defense(d) => {
+            d.from = from;
+            d.to = to;
+            d.notes.clear();
+        }
+    }
+    sample
+}
+
+fn order(interaction: &Interaction) -> usize {
+    match interaction {
+        Interaction::Move(_) => 0,
+        Interaction::Cut(_) => 1,
+        Interaction::Dribble(_) => 2,
+        Interaction::Pass(_) => 3,
+        Interaction::Handoff(_) => 4,
+        Interaction::Screen(_) => 5,
+        Interaction::Shot(_) => 6,
+        Interaction::Defense(_) => 7,
+    }
+}
+
+fn label(interaction: &Interaction) -> &'static str {
+    match interaction {
+        Interaction::Move(_) => "Move",
+        Interaction::Cut(_) => "Cut",
+        Interaction::Dribble(_) => "Dribble",
+        Interaction::Pass(_) => "Pass",
+        Interaction::Handoff(_) => "Handoff",
+        Interaction::Screen(_) => "Screen",
+        Interaction::Shot(_) => "Shot",
+        Interaction::Defense(_) => "Defense",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_legend_lists_lines_used() {
+        let renderer = Renderer::new();
+        let input = r#"
+            players = { p1, p2, p3 }
+            state = { baller = p1, position = { p1 = (0, 60), p2 = (90, -80), p3 = (-90, -80) } }
+            phase 1 = { pass = { p1 -> p2 }, screen = { p3 -> p1 } }
+            phase 2 = { move = { p1 -> (0, 0) -> (40, 20) } }
+        "#;
+        let svg = renderer
+            .render_legend(input)
+            .expect("Failed to render")
+            .expect("No legend");
+
+        // One entry per kind, moves first, straightened out
+        let labels: Vec<&str> = ["Move", "Pass", "Screen", "Dribble"]
+            .into_iter()
+            .filter(|label| svg.contains(&format!(">{}</text>", label)))
+            .collect();
+        assert_eq!(labels, ["Move", "Pass", "Screen"]);
+        assert!(svg.find(">Move<").unwrap() < svg.find(">Pass<").unwrap());
+        assert!(svg.contains("<line x1=\"6\" y1=\"10\" x2=\"42\" y2=\"10\""));
+        assert!(svg.contains("viewBox=\"0 0 400 20\""));
+
+        let still = "players = { p1 }\nstate = { position = { p1 = (0, 0) } }";
+        assert_eq!(renderer.render_legend(still), Ok(None));
+    }
+}
@@ -1,8 +1,38 @@
+mod animation;
+mod legend;
+pub mod theme;
+pub mod viewport;
+
+use crate::diagnostic::{self, Diagnostic};
+use crate::import::FileLoader;
 use crate::ir::*;
+use std::path::PathBuf;
+use theme::LineStyle;
+pub use theme::Theme;
+pub use viewport::Region;
+use viewport::{Frame, Viewport};
+
+/// Height of the title band above plays with a name, in drawing units.
+const CAPTION_HEIGHT: f64 = 24.0;
 
+#[derive(Clone)]
 pub struct Renderer {
+    /// Pixel width of one phase.
     width: u32,
-    height: u32,
+    /// Pixel height, following the shape of the picture when not set.
+    height: Option<u32>,
+    /// Room around the picture in drawing units.
+    padding: Option<f64>,
+    viewport: Viewport,
+    /// Court for playbooks that don't choose one with `court = ...`.
+    court: Option<CourtSpec>,
+    /// View for playbooks without `view = ...`.
+    view: Option<CourtView>,
+    theme: Theme,
+    /// Whether plays with a name get a title band.
+    caption: bool,
+    /// The file rendered playbooks are read from, for their imports.
+    file: Option<PathBuf>,
 }
 
 impl Default for Renderer {
@@ -15,92 +45,568 @@ impl Renderer {
     pub fn new() -> Self {
         Self {
             width: 500,
-            height: 500,
+            height: None,
+            padding: None,
+            viewport: Viewport::Court,
+            court: None,
+            view: None,
+            theme: Theme::default(),
+            caption: true,
+            file: None,
+        }
+    }
+
+    /// Pixel width of each phase; the height follows the shape of the picture.
+    pub fn with_width(mut self, width: u32) -> Self {
+        self.width = width;
+        self
+    }
+
+    /// Pixel size of each phase. The picture is centered if its shape doesn't match.
+    pub fn with_size(mut self, width: u32, height: u32) -> Self {
+        self.width = width;
+        self.height = Some(height);
+        self
+    }
+
+    /// Room around the court, or around what is cropped or zoomed, in drawing units.
+    pub fn with_padding(mut self, padding: f64) -> Self {
+        self.padding = Some(padding);
+        self
+    }
+
+    /// Shows only the players and their actions instead of the whole court.
+    pub fn with_auto_crop(mut self) -> Self {
+        self.viewport = Viewport::Fit;
+        self
+    }
+
+    /// Shows only `region` of the court.
+    pub fn with_zoom(mut self, region: Region) -> Self {
+        self.viewport = Viewport::Zoom(region);
+        self
+    }
+
+    /// Leaves out the title band of named plays, for pages that print the name themselves.
+    pub fn without_caption(mut self) -> Self {
+        self.caption = false;
+        self
+    }
+
+    /// Renders playbooks as the content of the file at `path`: their imports are read from disk, next to it.
+    pub fn with_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.file = Some(path.into());
+        self
+    }
+
+    pub fn with_theme(mut self, theme: Theme) -> Self {
+        self.theme = theme;
+        self
+    }
+
+    /// Draws playbooks without a `court = ...` line on `court`.
+    pub fn with_court(mut self, court: CourtSpec) -> Self {
+        self.court = Some(court);
+        self
+    }
+
+    /// Draws playbooks without a `view = ...` line with `view`.
+    pub fn with_view(mut self, view: CourtView) -> Self {
+        self.view = Some(view);
+        self
+    }
+
+    /// The court `scenes` are drawn on, and how much of it.
+    fn court_of(&self, scenes: &[Scene]) -> (CourtSpec, CourtView) {
+        match scenes.first() {
+            Some(scene) => (scene.court, scene.view),
+            None => (
+                self.court.unwrap_or(CourtSpec::CLASSIC),
+                self.view.unwrap_or_default(),
+            ),
         }
     }
 
+    /// The part of the court shown for `scenes`.
+    fn frame_of(&self, scenes: &[Scene], court: &CourtSpec, view: CourtView) -> Frame {
+        Frame::new(
+            self.viewport,
+            self.padding,
+            (court, view),
+            scenes,
+            self.theme.player_radius,
+        )
+    }
+
+    /// Pixel size of `count` phases side by side.
+    fn pixel_size(&self, frame: &Frame, count: usize) -> (u32, f64) {
+        let height = match self.height {
+            Some(height) => height as f64,
+            None => (self.width as f64 * frame.height / frame.width).round(),
+        };
+        (self.width * count as u32, height)
+    }
+
     pub fn render_scene(&self, scene: &Scene) -> String {
+        self.render_scenes(std::slice::from_ref(scene))
+    }
+
+    /// Renders phases side by side, left to right, in a single SVG.
+    pub fn render_scenes(&self, scenes: &[Scene]) -> String {
+        let count = scenes.len().max(1);
+        let (court, view) = self.court_of(scenes);
+        let frame = self.frame_of(scenes, &court, view);
+        let mut svg = self.open_svg(scenes, &frame, count);
+
+        if scenes.is_empty() {
+            svg.push_str(&self.render_court(&court, view));
+        } else if let [scene] = scenes {
+            svg.push_str(&self.render_scene_body(scene));
+        } else {
+            for (i, scene) in scenes.iter().enumerate() {
+                let offset = frame.width * i as f64;
+                // Cropped phases are clipped so they don't spill into their neighbours
+                if self.viewport == Viewport::Court {
+                    svg.push_str(&format!("<g transform=\"translate({}, 0)\">", offset));
+                } else {
+                    svg.push_str(&format!(
+                        "<svg x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" viewBox=\"{} {} {} {}\">",
+                        frame.x + offset,
+                        frame.y,
+                        frame.width,
+                        frame.height,
+                        frame.x,
+                        frame.y,
+                        frame.width,
+                        frame.height
+                    ));
+                }
+                svg.push_str(&format!(
+                    "<text x=\"{}\" y=\"{}\" font-size=\"8\" font-family=\"{}\"{}>Phase {}</text>",
+                    frame.x + 5.0,
+                    frame.y + 10.0,
+                    self.theme.font,
+                    Theme::text_fill(&self.theme.text),
+                    scene.number
+                ));
+                svg.push_str(&self.render_scene_body(scene));
+                svg.push_str(if self.viewport == Viewport::Court {
+                    "</g>"
+                } else {
+                    "</svg>"
+                });
+            }
+        }
+
+        svg.push_str(&self.theme.render_markers());
+        svg.push_str("</svg>");
+        svg
+    }
+
+    /// Opens the SVG for `count` phases shown in `frame`, with the background
+    /// and, for plays with a name, a title band above them.
+    fn open_svg(&self, scenes: &[Scene], frame: &Frame, count: usize) -> String {
+        let meta = scenes.first().map(|scene| &scene.meta);
+        let caption = meta.filter(|meta| self.caption && meta.name.is_some());
+        let page = match caption {
+            Some(_) => Frame {
+                y: frame.y - CAPTION_HEIGHT,
+                height: frame.height + CAPTION_HEIGHT,
+                ..*frame
+            },
+            None => *frame,
+        };
+        let (width, height) = self.pixel_size(&page, count);
+        let total_width = page.width * count as f64;
         let mut svg = String::new();
-        svg.push_str(&self.render_court());
+        svg.push_str(&format!(
+            "<svg width=\"{}\" height=\"{}\" viewBox=\"{} {} {} {}\" xmlns=\"http://www.w3.org/2000/svg\">",
+            width, height, page.x, page.y, total_width, page.height
+        ));
+        if let Some(meta) = meta {
+            // Shown by viewers and read by screen readers and search tools
+            if let Some(name) = &meta.name {
+                svg.push_str(&format!("<title>{}</title>", escape_xml(name)));
+            }
+            if let Some(notes) = &meta.notes {
+                svg.push_str(&format!("<desc>{}</desc>", escape_xml(notes)));
+            }
+        }
+
+        // 0. Global Background (White fill for everything)
+        svg.push_str(&format!(
+            "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\" />",
+            page.x, page.y, total_width, page.height, self.theme.background
+        ));
+        if let Some(meta) = caption {
+            svg.push_str(&self.render_caption(meta, &page, total_width));
+        }
+        svg
+    }
+
+    /// The name on the left, category, formation and call on the right.
+    fn render_caption(&self, meta: &Meta, page: &Frame, total_width: f64) -> String {
+        let baseline = page.y + 16.0;
+        let mut svg = format!(
+            "<text x=\"{}\" y=\"{}\" font-size=\"12\" font-weight=\"bold\" font-family=\"{}\"{}>{}</text>",
+            page.x + 5.0,
+            baseline,
+            self.theme.font,
+            Theme::text_fill(&self.theme.text),
+            escape_xml(meta.name.as_deref().unwrap_or_default())
+        );
+        let details: Vec<String> = [
+            meta.category.clone(),
+            meta.formation.clone(),
+            meta.call.as_ref().map(|call| format!("Call: {}", call)),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        if !details.is_empty() {
+            svg.push_str(&format!(
+                "<text x=\"{}\" y=\"{}\" font-size=\"8\" text-anchor=\"end\" font-family=\"{}\"{}>{}</text>",
+                page.x + total_width - 5.0,
+                baseline,
+                self.theme.font,
+                Theme::text_fill(&self.theme.text),
+                escape_xml(&details.join(" · "))
+            ));
+        }
+        svg
+    }
+
+    fn render_scene_body(&self, scene: &Scene) -> String {
+        let mut svg = String::new();
+        svg.push_str(&self.render_court(&scene.court, scene.view));
 
         // 1. Draw Interactions
         for interaction in &scene.interactions {
-            match interaction {
-                Interaction::Move(m) => {
-                    svg.push_str(&self.render_move(m));
-                }
-                Interaction::Pass(p) => {
-                    svg.push_str(&self.render_pass(p));
-                }
-                Interaction::Screen(s) => {
-                    svg.push_str(&self.render_screen(s));
-                }
+            svg.push_str(&self.render_interaction(interaction));
+        }
+
+        // 2. Matchups: a faint line from each defender to their man
+        for defender in &scene.entities {
+            let man = defender
+                .guarding
+                .as_ref()
+                .and_then(|id| scene.entities.iter().find(|e| &e.id == id));
+            if let Some(man) = man {
+                svg.push_str(&format!(
+                    "<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" {} />",
+                    defender.end_pos.0,
+                    defender.end_pos.1,
+                    man.end_pos.0,
+                    man.end_pos.1,
+                    self.theme.matchup.attributes()
+                ));
             }
         }
 
-        // 2. Draw Entities
+        // 3. Draw Entities
         for entity in &scene.entities {
-            svg.push_str(&self.render_player(entity));
+            svg.push_str(&with_notes(self.render_player(entity), &entity.notes));
         }
 
-        svg.push_str("<defs><marker id=\"arrowhead\" markerWidth=\"10\" markerHeight=\"7\" refX=\"10\" refY=\"3.5\" orient=\"auto\"><polygon points=\"0 0, 10 3.5, 0 7\" fill=\"black\" /></marker></defs>");
-        svg.push_str("</svg>");
         svg
     }
 
-    fn render_court(&self) -> String {
-        let mut court = String::new();
-        court.push_str(&format!(
-            "<svg width=\"{}\" height=\"{}\" viewBox=\"-105 -105 210 210\" xmlns=\"http://www.w3.org/2000/svg\">",
-            self.width,
-            self.height
+    fn render_court(&self, court: &CourtSpec, view: CourtView) -> String {
+        let mut court_svg = String::new();
+        let s = court.scale();
+        let half_line = round(court.y(court.half_length));
+        let style = &self.theme.court;
+
+        // 1. Court Boundary
+        // Covers the drawn area with the floor, which may differ from the page background.
+        court_svg.push_str(&format!(
+            "<rect x=\"-100\" y=\"-90\" width=\"200\" height=\"{}\" fill=\"{}\" stroke=\"{}\" stroke-width=\"{}\" />",
+            round(view.drawn_length(court)),
+            style.floor,
+            style.lines,
+            style.boundary_width
         ));
+        court_svg.push_str(&self.render_lane_lines(court));
 
-        // 0. Global Background (White fill for everything)
-        court
-            .push_str("<rect x=\"-105\" y=\"-105\" width=\"210\" height=\"210\" fill=\"white\" />");
+        // 2. Center Circle: the half facing the basket, or the whole circle and the half-court line
+        if view == CourtView::Half {
+            if let Some(center) = court.center_circle {
+                let r = round(center * s);
+                court_svg.push_str(&format!(
+                    "<path d=\"M {} {} A {} {} 0 0 1 {} {}\" fill=\"none\" stroke=\"{}\" stroke-width=\"{}\" />",
+                    -r,
+                    half_line,
+                    r,
+                    r,
+                    r,
+                    half_line,
+                    style.lines,
+                    style.line_width
+                ));
+            }
+            court_svg.push_str(&self.render_basket(court));
+            return court_svg;
+        }
+        court_svg.push_str(&format!(
+            "<line x1=\"-100\" y1=\"{}\" x2=\"100\" y2=\"{}\" stroke=\"{}\" stroke-width=\"{}\" />",
+            half_line, half_line, style.lines, style.boundary_width
+        ));
+        if let Some(center) = court.center_circle {
+            court_svg.push_str(&format!(
+                "<circle cx=\"0\" cy=\"{}\" r=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"{}\" />",
+                half_line,
+                round(center * s),
+                style.lines,
+                style.line_width
+            ));
+        }
+        court_svg.push_str(&self.render_basket(court));
+
+        // 3. The other end: the same lines, mirrored in the half-court line
+        let far_end = format!(
+            "<g transform=\"matrix(1 0 0 -1 0 {})\">{}{}</g>",
+            round(2.0 * half_line),
+            self.render_lane_lines(court),
+            self.render_basket(court)
+        );
+        if view == CourtView::Full {
+            court_svg.push_str(&far_end);
+        } else {
+            // A nested viewport clips whatever lies past the end of the drawing
+            let height = round(view.drawn_length(court) - court.drawn_half_length());
+            court_svg.push_str(&format!(
+                "<svg x=\"-100\" y=\"{}\" width=\"200\" height=\"{}\" viewBox=\"-100 {} 200 {}\">{}</svg>",
+                half_line, height, half_line, height, far_end
+            ));
+        }
+
+        court_svg
+    }
+
+    /// Key, free-throw circle, 3-point line and restricted area at the near end.
+    fn render_lane_lines(&self, court: &CourtSpec) -> String {
+        let mut court_svg = String::new();
+        let s = court.scale();
+        let style = &self.theme.court;
+
+        // 1. Key area (Rectangle)
+        let lane = court.lane_width * s;
+        court_svg.push_str(&format!(
+            "<rect x=\"{}\" y=\"-90\" width=\"{}\" height=\"{}\" fill=\"{}\" stroke=\"{}\" stroke-width=\"{}\" />",
+            round(-lane / 2.0),
+            round(lane),
+            round(court.free_throw_line * s),
+            style.paint,
+            style.lines,
+            style.line_width
+        ));
+
+        // 2. Free-throw circle
+        court_svg.push_str(&format!(
+            "<circle cx=\"0\" cy=\"{}\" r=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"{}\" />",
+            round(court.y(court.free_throw_line)),
+            round(court.free_throw_circle * s),
+            style.lines,
+            style.line_width
+        ));
+
+        // 3. 3-point line (Straight lines + Arc)
+        // Straight lines from the baseline up to where they meet the arc.
+        // Sweep-flag=0 makes the arc curve downwards (towards Y+).
+        let radius = court.three_point_radius * s;
+        let corner = court.corner_three * s;
+        let join =
+            court.y(court.three_point_center) + (radius * radius - corner * corner).max(0.0).sqrt();
+        court_svg.push_str(&format!(
+            "<path d=\"M {} -90 L {} {} A {} {} 0 0 0 {} {} L {} -90\" fill=\"none\" stroke=\"{}\" stroke-width=\"{}\" />",
+            round(-corner),
+            round(-corner),
+            round(join),
+            round(radius),
+            round(radius),
+            round(corner),
+            round(join),
+            round(corner),
+            style.lines,
+            style.line_width
+        ));
+
+        // 4. Restricted area (no-charge semicircle)
+        if let Some(restricted) = court.restricted_area {
+            let (r, y) = (round(restricted * s), round(court.y(court.hoop)));
+            court_svg.push_str(&format!(
+                "<path d=\"M {} {} A {} {} 0 0 0 {} {}\" fill=\"none\" stroke=\"{}\" stroke-width=\"{}\" />",
+                -r,
+                y,
+                r,
+                r,
+                r,
+                y,
+                style.lines,
+                style.line_width
+            ));
+        }
 
-        // 1. Court Boundary (Half court)
-        // Black border. Covers the half court area. Fill is already white from background, but keeping fill=\"white\" ensures opacity if layers change.
-        court.push_str("<rect x=\"-100\" y=\"-90\" width=\"200\" height=\"180\" fill=\"white\" stroke=\"black\" stroke-width=\"2\" />");
+        court_svg
+    }
 
-        // 2. Key area (Rectangle)
-        court.push_str("<rect x=\"-20\" y=\"-90\" width=\"40\" height=\"65\" fill=\"none\" stroke=\"black\" stroke-width=\"1\" />");
+    /// Backboard and hoop at the near end.
+    fn render_basket(&self, court: &CourtSpec) -> String {
+        let mut court_svg = String::new();
+        let s = court.scale();
+        let style = &self.theme.court;
 
-        // 3. Free-throw circle
-        court.push_str("<circle cx=\"0\" cy=\"-25\" r=\"20\" fill=\"none\" stroke=\"black\" stroke-width=\"1\" />");
+        // 1. Backboard
+        let board = round(court.backboard_width * s / 2.0);
+        let board_y = round(court.y(court.backboard));
+        court_svg.push_str(&format!(
+            "<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"{}\" stroke-width=\"{}\" />",
+            -board, board_y, board, board_y, style.lines, style.line_width
+        ));
 
-        // 4. 3-point line (Straight lines + Arc)
-        // Straight lines from baseline (y=-90) to y=-35 at x=+/-80.
-        // Arc connects (-80, -35) to (80, -35). Sweep-flag=0 makes it curve downwards (towards Y+).
-        court.push_str("<path d=\"M -80 -90 L -80 -35 A 80 80 0 0 0 80 -35 L 80 -90\" fill=\"none\" stroke=\"black\" stroke-width=\"1\" />");
+        // 2. Hoop (Red)
+        let (x, y) = court.hoop_center();
+        court_svg.push_str(&format!(
+            "<circle cx=\"{}\" cy=\"{}\" r=\"{}\" stroke=\"{}\" stroke-width=\"{}\" fill=\"none\" />",
+            x,
+            round(y),
+            round(court.hoop_radius * s),
+            style.hoop,
+            style.line_width
+        ));
 
-        // 5. Center Circle (Half) at the opposite side (y=90)
-        court.push_str("<path d=\"M -20 90 A 20 20 0 0 1 20 90\" fill=\"none\" stroke=\"black\" stroke-width=\"1\" />");
+        court_svg
+    }
 
-        // 6. Backboard
-        court.push_str("<line x1=\"-12\" y1=\"-88\" x2=\"12\" y2=\"-88\" stroke=\"black\" stroke-width=\"1\" />");
+    /// One interaction, with its notes as a tooltip.
+    fn render_interaction(&self, interaction: &Interaction) -> String {
+        match interaction {
+            Interaction::Move(m) => {
+                with_notes(self.render_move(m, &self.theme.lines.move_), &m.notes)
+            }
+            Interaction::Cut(m) => with_notes(self.render_move(m, &self.theme.lines.cut), &m.notes),
+            Interaction::Dribble(m) => with_notes(self.render_dribble(m), &m.notes),
+            Interaction::Pass(p) => with_notes(self.render_pass(p), &p.notes),
+            Interaction::Handoff(p) => with_notes(self.render_handoff(p), &p.notes),
+            Interaction::Screen(s) => with_notes(self.render_screen(s), &s.notes),
+            Interaction::Shot(s) => with_notes(self.render_shot(s), &s.notes),
+            Interaction::Defense(d) => with_notes(self.render_defense(d), &d.notes),
+        }
+    }
 
-        // 7. Hoop (Red)
-        court.push_str("<circle cx=\"0\" cy=\"-84\" r=\"5\" stroke=\"red\" stroke-width=\"1\" fill=\"none\" />");
+    fn render_move(&self, m: &MoveLine, style: &LineStyle) -> String {
+        if !m.is_straight() {
+            return format!(
+                "<path d=\"{}\" fill=\"none\" {} marker-end=\"url(#{})\" />",
+                path_data(m),
+                style.attributes(),
+                self.theme.arrowhead(&style.color)
+            );
+        }
+        self.render_arrow(m.from, m.to, style)
+    }
 
-        court
+    /// A straight line ending in an arrowhead.
+    fn render_arrow(&self, from: (f64, f64), to: (f64, f64), style: &LineStyle) -> String {
+        format!(
+            "<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" {} marker-end=\"url(#{})\" />",
+            from.0,
+            from.1,
+            to.0,
+            to.1,
+            style.attributes(),
+            self.theme.arrowhead(&style.color)
+        )
     }
 
-    fn render_move(&self, m: &MoveLine) -> String {
+    /// A straight line without arrowhead.
+    fn render_line(&self, from: (f64, f64), to: (f64, f64), style: &LineStyle) -> String {
         format!(
-            "<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"black\" stroke-width=\"2\" marker-end=\"url(#arrowhead)\" />",
-            m.from.0, m.from.1, m.to.0, m.to.1
+            "<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" {} />",
+            from.0,
+            from.1,
+            to.0,
+            to.1,
+            style.attributes()
         )
     }
 
-    fn render_pass(&self, p: &PassLine) -> String {
+    /// A zig-zag along the path, straightening out before the arrowhead.
+    fn render_dribble(&self, m: &MoveLine) -> String {
+        let style = &self.theme.lines.dribble;
+        let len = m.length();
+        let straight = 8.0;
+        if len <= straight {
+            return self.render_move(m, style);
+        }
+
+        let amplitude = 3.0;
+        let zigzag = len - straight;
+        let steps = ((zigzag / 4.0).round() as usize).max(1);
+        let step = zigzag / steps as f64;
+        let mut points = vec![m.from];
+        for i in 1..steps {
+            let ((x, y), (nx, ny)) = m.walk(step * i as f64);
+            let side = if i % 2 == 1 { amplitude } else { -amplitude };
+            points.push((x - ny * side, y + nx * side));
+        }
+        points.push(m.walk(zigzag).0);
+        // The rest of the path, as is
+        let mut travelled = 0.0;
+        for pair in m.points().windows(2) {
+            travelled += ((pair[1].0 - pair[0].0).powi(2) + (pair[1].1 - pair[0].1).powi(2)).sqrt();
+            if travelled > zigzag {
+                points.push(pair[1]);
+            }
+        }
+
+        let points: Vec<String> = points
+            .iter()
+            .map(|(x, y)| format!("{},{}", round(*x), round(*y)))
+            .collect();
         format!(
-            "<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"black\" stroke-width=\"2\" stroke-dasharray=\"4\" marker-end=\"url(#arrowhead)\" />",
-            p.from.0, p.from.1, p.to.0, p.to.1
+            "<polyline points=\"{}\" fill=\"none\" {} marker-end=\"url(#{})\" />",
+            points.join(" "),
+            style.attributes(),
+            self.theme.arrowhead(&style.color)
         )
     }
 
+    fn render_pass(&self, p: &PassLine) -> String {
+        self.render_arrow(p.from, p.to, &self.theme.lines.pass)
+    }
+
+    /// A solid line with two bars across it where the ball changes hands.
+    fn render_handoff(&self, p: &PassLine) -> String {
+        let dx = p.to.0 - p.from.0;
+        let dy = p.to.1 - p.from.1;
+        let len = (dx * dx + dy * dy).sqrt();
+        let (nx, ny) = if len > 0.001 {
+            (dx / len, dy / len)
+        } else {
+            (0.0, 1.0)
+        };
+        let (px, py) = (-ny, nx);
+        let (mx, my) = ((p.from.0 + p.to.0) / 2.0, (p.from.1 + p.to.1) / 2.0);
+
+        let style = &self.theme.lines.handoff;
+        let mut svg = self.render_line(p.from, p.to, style);
+        for offset in [-2.0, 2.0] {
+            let (cx, cy) = (mx + nx * offset, my + ny * offset);
+            svg.push_str(&self.render_line(
+                (round(cx - px * 4.0), round(cy - py * 4.0)),
+                (round(cx + px * 4.0), round(cy + py * 4.0)),
+                style,
+            ));
+        }
+        svg
+    }
+
+    /// A dash-dot arrow from the shooter to the hoop.
+    fn render_shot(&self, s: &ShotLine) -> String {
+        self.render_arrow(s.from, s.to, &self.theme.lines.shot)
+    }
+
     fn render_screen(&self, s: &ScreenLine) -> String {
         let dx = s.to.0 - s.from.0;
         let dy = s.to.1 - s.from.1;
@@ -131,76 +637,208 @@ impl Renderer {
         let bx2 = cx + px * half_bar;
         let by2 = cy + py * half_bar;
 
+        let style = &self.theme.lines.screen;
         let mut svg = String::new();
         // Draw the movement line (stem) to the shifted center
-        svg.push_str(&format!(
-            "<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"black\" stroke-width=\"2\" />",
-            s.from.0, s.from.1, cx, cy
-        ));
+        svg.push_str(&self.render_line(s.from, (cx, cy), style));
 
         // Draw the perpendicular bar
+        svg.push_str(&self.render_line((bx1, by1), (bx2, by2), style));
+
+        svg
+    }
+
+    /// A defensive reaction: the defender's path, labelled with the action.
+    fn render_defense(&self, d: &DefenseLine) -> String {
+        let mut svg = self.render_arrow(d.from, d.to, &self.theme.lines.defense);
         svg.push_str(&format!(
-            "<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"black\" stroke-width=\"2\" />",
-            bx1, by1, bx2, by2
+            "<text x=\"{}\" y=\"{}\" font-size=\"6\" text-anchor=\"middle\" font-family=\"{}\"{}>{}</text>",
+            (d.from.0 + d.to.0) / 2.0,
+            (d.from.1 + d.to.1) / 2.0 - 3.0,
+            self.theme.font,
+            Theme::text_fill(&self.theme.text),
+            d.kind.name()
         ));
-
         svg
     }
 
     fn render_player(&self, entity: &Entity) -> String {
         let mut player = String::new();
-        player.push_str(&format!(
-                "<circle cx=\"{}\" cy=\"{}\" r=\"8\" fill=\"white\" stroke=\"gray\" stroke-width=\"1\" opacity=\"0.3\" />",
-                entity.start_pos.0, entity.start_pos.1
-            ));
-        player.push_str(&format!(
-                "<circle cx=\"{}\" cy=\"{}\" r=\"10\" fill=\"white\" stroke=\"black\" stroke-width=\"2\" />",
-                entity.end_pos.0, entity.end_pos.1
-            ));
-        player.push_str(&format!(
-                "<text x=\"{}\" y=\"{}\" font-size=\"12\" text-anchor=\"middle\" dominant-baseline=\"central\" font-family=\"Arial\">{}</text>",
-                entity.end_pos.0, entity.end_pos.1, entity.label
-            ));
+        let (x, y) = entity.start_pos;
+        let theme = &self.theme;
+        // The ghost is a little smaller than the player
+        let ghost = theme.player_radius * 0.8;
+        match entity.team {
+            Team::Offense => player.push_str(&format!(
+                "<circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"{}\" stroke=\"{}\" stroke-width=\"1\" opacity=\"0.3\" />",
+                x, y, ghost, theme.offense.fill, theme.ghost
+            )),
+            Team::Defense => {
+                let arm = ghost * 0.75;
+                player.push_str(&format!(
+                    "<path d=\"M {} {} L {} {} M {} {} L {} {}\" stroke=\"{}\" stroke-width=\"1\" opacity=\"0.3\" />",
+                    x - arm,
+                    y - arm,
+                    x + arm,
+                    y + arm,
+                    x - arm,
+                    y + arm,
+                    x + arm,
+                    y - arm,
+                    theme.ghost
+                ))
+            }
+        }
+        player.push_str(&self.render_marker(entity, entity.end_pos));
 
         if entity.is_baller {
             player.push_str(&format!(
-                    "<circle cx=\"{}\" cy=\"{}\" r=\"4\" fill=\"orange\" stroke=\"black\" stroke-width=\"1\" transform=\"translate(10, -10)\" />",
-                    entity.end_pos.0, entity.end_pos.1
-                ));
+                "<circle cx=\"{}\" cy=\"{}\" r=\"4\" fill=\"{}\" stroke=\"{}\" stroke-width=\"1\" transform=\"translate(10, -10)\" />",
+                entity.end_pos.0, entity.end_pos.1, theme.ball.fill, theme.ball.stroke
+            ));
         }
 
         player
     }
 
+    /// The player symbol centred on `(x, y)`: a numbered circle for the offense,
+    /// an X with the number beside it for the defense.
+    fn render_marker(&self, entity: &Entity, (x, y): (f64, f64)) -> String {
+        let theme = &self.theme;
+        let r = theme.player_radius;
+        match entity.team {
+            Team::Offense => format!(
+                "<circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"{}\" stroke=\"{}\" stroke-width=\"2\" /><text x=\"{}\" y=\"{}\" font-size=\"{}\" text-anchor=\"middle\" dominant-baseline=\"central\" font-family=\"{}\"{}>{}</text>",
+                x,
+                y,
+                r,
+                theme.offense.fill,
+                theme.offense.stroke,
+                x,
+                y,
+                r * 1.2,
+                theme.font,
+                Theme::text_fill(&theme.offense.text),
+                entity.label
+            ),
+            Team::Defense => format!(
+                "<path d=\"M {} {} L {} {} M {} {} L {} {}\" stroke=\"{}\" stroke-width=\"2\" /><text x=\"{}\" y=\"{}\" font-size=\"{}\" font-family=\"{}\"{}>{}</text>",
+                x - r * 0.7,
+                y - r * 0.7,
+                x + r * 0.7,
+                y + r * 0.7,
+                x - r * 0.7,
+                y + r * 0.7,
+                x + r * 0.7,
+                y - r * 0.7,
+                theme.defense.stroke,
+                x + r * 0.6,
+                y + r,
+                r * 0.8,
+                theme.font,
+                Theme::text_fill(&theme.defense.text),
+                entity.label
+            ),
+        }
+    }
+
+    /// Renders every phase into one SVG. All or nothing: if `input` has any error,
+    /// every diagnostic is returned, one per line, and nothing is drawn.
+    /// `render_partial` draws what parsed and returns the diagnostics next to it.
     pub fn render(&self, input: &str) -> Result<String, String> {
+        let scenes = self.compile(input)?;
+        Ok(self.render_scenes(&scenes))
+    }
+
+    /// Renders every phase as its own SVG document.
+    pub fn render_phases(&self, input: &str) -> Result<Vec<String>, String> {
+        let scenes = self.compile(input)?;
+        Ok(scenes
+            .iter()
+            .map(|scene| self.render_scene(scene))
+            .collect())
+    }
+
+    /// Renders an animated SVG of the whole play, looping every `duration` seconds.
+    pub fn render_animated(&self, input: &str, duration: f64) -> Result<String, String> {
+        let scenes = self.compile(input)?;
+        Ok(self.render_animated_scenes(&scenes, duration))
+    }
+
+    /// Renders whatever parsed correctly, together with every diagnostic found.
+    pub fn render_partial(&self, input: &str) -> (String, Vec<Diagnostic>) {
+        let (scenes, diagnostics) = self.compile_partial(input);
+        (self.render_scenes(&scenes), diagnostics)
+    }
+
+    fn compile(&self, input: &str) -> Result<Vec<Scene>, String> {
+        let (scenes, diagnostics) = self.compile_partial(input);
+        if diagnostics.iter().any(Diagnostic::is_error) {
+            let messages: Vec<String> = diagnostics.iter().map(|d| d.to_string()).collect();
+            Err(messages.join("\n"))
+        } else {
+            Ok(scenes)
+        }
+    }
+
+    fn compile_partial(&self, input: &str) -> (Vec<Scene>, Vec<Diagnostic>) {
         use crate::ir::IRGenerator;
-        use crate::lexer::Lexer;
-        use crate::parser::{ParseError, Parser};
 
-        let mut lexer = Lexer::new(input);
-        let tokens = lexer.tokenize();
-        let mut parser = Parser::new(tokens);
+        let (mut playbook, diagnostics) = match &self.file {
+            Some(path) => diagnostic::analyze_file(input, path, &FileLoader),
+            None => diagnostic::analyze(input),
+        };
+        if playbook.court.is_none() {
+            playbook.court = self.court;
+        }
+        if playbook.view.is_none() {
+            playbook.view = self.view;
+        }
+        (IRGenerator::generate(playbook), diagnostics)
+    }
+}
+
+/// Groups `element` with a `<title>`, which SVG viewers show as a tooltip.
+fn with_notes(element: String, notes: &[String]) -> String {
+    if notes.is_empty() {
+        return element;
+    }
+    format!(
+        "<g><title>{}</title>{}</g>",
+        escape_xml(&notes.join("\n")),
+        element
+    )
+}
 
-        match parser.parse() {
-            Ok(playbook) => {
-                let scene = IRGenerator::generate(playbook);
-                Ok(self.render_scene(&scene))
+/// SVG path data for a movement: `M`, then `L` / `Q` / `C` per segment.
+fn path_data(m: &MoveLine) -> String {
+    let point = |(x, y): (f64, f64)| format!("{} {}", round(x), round(y));
+    let mut d = format!("M {}", point(m.from));
+    for segment in &m.path {
+        let part = match segment {
+            PathSegment::Line(to) => format!(" L {}", point(*to)),
+            PathSegment::Quad { control, to } => {
+                format!(" Q {} {}", point(*control), point(*to))
             }
-            Err(e) => {
-                let error_msg = match e {
-                    ParseError::UnexpectedToken(token, msg) => {
-                        format!(
-                            "Error at line {}, column {}: {} (found {:?})",
-                            token.span.line, token.span.column, msg, token.kind
-                        )
-                    }
-                    ParseError::UnexpectedEOF => "Error: Unexpected End of File".to_string(),
-                    ParseError::InvalidSyntax(msg) => format!("Error: {}", msg),
-                };
-                Err(error_msg)
+            PathSegment::Cubic { c1, c2, to } => {
+                format!(" C {} {} {}", point(*c1), point(*c2), point(*to))
             }
-        }
+        };
+        d.push_str(&part);
     }
+    d
+}
+
+/// Rounds computed coordinates to 2 decimals.
+fn round(value: f64) -> f64 {
+    let rounded = (value * 100.0).round() / 100.0;
+    if rounded == 0.0 { 0.0 } else { rounded }
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
 }
 
 #[cfg(test)]
@@ -239,4 +877,301 @@ mod tests {
         let output = renderer.render(input).unwrap_err();
         assert!(output.contains("Did you mean 'action'?"));
     }
+
+    #[test]
+    fn test_semantic_errors() {
+        let renderer = Renderer::new();
+        let input = r#"players = { p1 }
+state = { baller = p2, position = { p1 = (0, 0) } }"#;
+        let output = renderer.render(input).unwrap_err();
+        assert_eq!(
+            output,
+            "Error at line 2, column 20: Player 'p2' is not declared in players"
+        );
+    }
+
+    #[test]
+    fn test_render_partial() {
+        let renderer = Renderer::new();
+        let input = r#"players = { p1, p2 }
+state = { baller = p1, position = { p1 = (0, 60), p2 = (x, 0) } }
+action = { move = { p1 -> (0, 0), p2 -> }, pass = { p1 -> p2:middle } }"#;
+        let (svg, diagnostics) = renderer.render_partial(input);
+        let errors: Vec<String> = diagnostics.iter().map(|d| d.to_string()).collect();
+        assert_eq!(errors.len(), 3);
+        assert!(errors[0].starts_with("Error at line 2, column 57: Expected Number for X"));
+        assert!(errors[1].starts_with("Error at line 3, column 41: Expected LParenthesis"));
+        assert!(
+            errors[2].starts_with(
+                "Error at line 3, column 62: Expected timing (before, after, 0.0-1.0)"
+            )
+        );
+        // p1's move still parsed and is drawn
+        assert!(svg.contains("<line x1=\"0\" y1=\"60\" x2=\"0\" y2=\"0\""));
+
+        let output = renderer.render(input).unwrap_err();
+        assert_eq!(output.lines().count(), 3);
+    }
+
+    #[test]
+    fn test_render_phases() {
+        let renderer = Renderer::new();
+        let input = r#"
+            players = { p1, p2 }
+            state = { baller = p1, position = { p1 = (0, 60), p2 = (90, -80) } }
+            phase 1 = { move = { p2 -> (70, 20) }, pass = { p1 -> p2 } }
+            phase 2 = { move = { p1 -> (0, 0) } }
+        "#;
+        let phases = renderer.render_phases(input).expect("Failed to render");
+        assert_eq!(phases.len(), 2);
+        assert!(phases.iter().all(|svg| svg.starts_with("<svg")));
+
+        let combined = renderer.render(input).expect("Failed to render");
+        assert!(combined.contains("viewBox=\"-105 -105 420 210\""));
+        assert!(combined.contains(">Phase 2<"));
+    }
+
+    #[test]
+    fn test_phase_labels_use_numbers() {
+        let input = r#"
+            players = { p1 }
+            state = { position = { p1 = (0, 60) } }
+            phase 3 = { move = { p1 -> (0, 0) } }
+            phase 1 = { move = { p1 -> (0, 30) } }
+        "#;
+        let svg = Renderer::new().render(input).expect("Failed to render");
+        assert!(svg.contains(">Phase 1<") && svg.contains(">Phase 3<"));
+        assert!(!svg.contains(">Phase 2<"));
+    }
+
+    #[test]
+    fn test_comments_become_tooltips() {
+        let renderer = Renderer::new();
+        let input = r#"
+            players = { p1, p2 }
+            state = {
+                baller = p1,
+                position = { p1 = (0, 60), /* ball handler */ p2 = (90, -80) },
+            }
+            action = {
+                move = { p2 -> (70, 20) }, // pop out
+                pass = { p1 -> p2:after }, // look for the <shot> & drive
+            }
+        "#;
+        let svg = renderer.render(input).expect("Failed to render");
+        assert!(svg.contains("<g><title>pop out</title><line x1=\"90\""));
+        assert!(svg.contains("<title>look for the &lt;shot&gt; &amp; drive</title>"));
+        assert!(svg.contains("<g><title>ball handler</title><circle cx=\"90\""));
+    }
+
+    #[test]
+    fn test_defenders_are_drawn_as_x() {
+        let renderer = Renderer::new();
+        let input = r#"
+            players = { p1 }
+            defense = { x1 }
+            state = {
+                baller = p1,
+                position = { p1 = (0, 60), x1 = (0, 30) },
+                guards = { x1 -> p1 },
+            }
+            action = { closeout = { x1 -> p1 } }
+        "#;
+        let svg = renderer.render(input).expect("Failed to render");
+        // Stops 12 short of p1, on the way from (0, 30)
+        assert!(svg.contains("<path d=\"M -7 41 L 7 55 M -7 55 L 7 41\" stroke=\"black\""));
+        assert!(svg.contains(">closeout</text>"));
+        assert!(svg.contains("stroke-dasharray=\"1,2\""));
+    }
+
+    #[test]
+    fn test_ball_action_conventions() {
+        let renderer = Renderer::new();
+        let input = r#"
+            players = { p1, p2 }
+            state = { baller = p1, position = { p1 = (0, 60), p2 = (40, 40) } }
+            action = {
+                dribble = { p1 -> (0, 44) },
+                handoff = { p1 -> p2 },
+                shot = { p2 },
+            }
+        "#;
+        let svg = renderer.render(input).expect("Failed to render");
+        assert!(svg.contains("<polyline points=\"0,60 3,56 0,52 0,44\""));
+        // Backboard, handoff line with its two bars, shot
+        assert_eq!(svg.matches("<line").count(), 1 + 3 + 1);
+        assert!(svg.contains(
+            "x2=\"0\" y2=\"-84\" stroke=\"black\" stroke-width=\"2\" stroke-dasharray=\"8,2,2,2\""
+        ));
+    }
+
+    #[test]
+    fn test_curved_paths() {
+        let renderer = Renderer::new();
+        let input = r#"
+            players = { p1, p2, p3 }
+            state = { baller = p3, position = { p1 = (0, 60), p2 = (-60, 0), p3 = (60, 0) } }
+            action = {
+                cut = { p1 -> (0, 0) via (30, 30) },
+                move = { p2 -> (-60, -40) -> (-20, -60) },
+                dribble = { p3 -> (60, -40) -> (20, -60) curve },
+            }
+        "#;
+        let svg = renderer.render(input).expect("Failed to render");
+        assert!(svg.contains("<path d=\"M 0 60 Q 30 30 0 0\" fill=\"none\""));
+        assert!(svg.contains("<path d=\"M -60 0 L -60 -40 L -20 -60\""));
+        // The zig-zag ends on the last waypoint with the arrowhead
+        assert!(
+            svg.contains(" 20,-60\" fill=\"none\" stroke=\"black\" stroke-width=\"2\" marker-end")
+        );
+    }
+
+    #[test]
+    fn test_court_specs() {
+        let input = r#"
+            court = fiba
+            players = { p1 }
+            state = { position = { p1 = (0, 6.75) } }
+        "#;
+        let svg = Renderer::new().render(input).expect("Failed to render");
+        // FIBA arc: 6.75 m around the hoop, 6.6 m straight corner lines
+        assert!(
+            svg.contains("<path d=\"M -88 -90 L -88 -50.13 A 90 90 0 0 0 88 -50.13 L 88 -90\"")
+        );
+        assert!(svg.contains("<circle cx=\"0\" cy=\"21\" r=\"10\""));
+        // Restricted area and a longer half court
+        assert!(svg.contains("<path d=\"M -16.67 -69 A 16.67 16.67 0 0 0 16.67 -69\""));
+        assert!(svg.contains("viewBox=\"-105 -105 210 216.67\""));
+
+        // The API court applies when the file doesn't pick one, in feet for NBA
+        let input = "players = { p1 }\nstate = { position = { p1 = (0, 23.75) } }";
+        let svg = Renderer::new()
+            .with_court(CourtSpec::NBA)
+            .render(input)
+            .expect("Failed to render");
+        assert!(svg.contains("A 95 95 0 0 0"));
+        assert!(svg.contains("<circle cx=\"0\" cy=\"26\" r=\"10\""));
+    }
+
+    #[test]
+    fn test_court_views() {
+        let input = r#"
+            view = full
+            players = { p1 }
+            state = { position = { p1 = (0, 250) } }
+        "#;
+        let svg = Renderer::new().render(input).expect("Failed to render");
+        // Both ends, the half-court line and the whole center circle
+        assert!(svg.contains("viewBox=\"-105 -105 210 390\""));
+        assert!(svg.contains("<line x1=\"-100\" y1=\"90\" x2=\"100\" y2=\"90\""));
+        assert!(svg.contains("<circle cx=\"0\" cy=\"90\" r=\"20\""));
+        assert!(svg.contains("<g transform=\"matrix(1 0 0 -1 0 180)\">"));
+        assert!(svg.contains("<circle cx=\"0\" cy=\"250\" r=\"10\""));
+
+        // Three quarters: the far end is cut off where the drawing stops
+        let input = "players = { p1 }\nstate = { position = { p1 = half_court } }";
+        let svg = Renderer::new()
+            .with_view(CourtView::ThreeQuarter)
+            .render(input)
+            .expect("Failed to render");
+        assert!(svg.contains("viewBox=\"-105 -105 210 300\""));
+        assert!(svg.contains("<svg x=\"-100\" y=\"90\" width=\"200\" height=\"90\""));
+        assert!(svg.contains("<circle cx=\"0\" cy=\"90\" r=\"10\""));
+    }
+
+    #[test]
+    fn test_themes() {
+        let input = r#"
+            players = { p1, p2 }
+            defense = { x1 }
+            state = { baller = p1, position = { p1 = (0, 60), p2 = (70, 20), x1 = (0, 45) } }
+            action = { pass = { p1 -> p2 } }
+        "#;
+        let svg = Renderer::new()
+            .with_theme(Theme::dark())
+            .render(input)
+            .expect("Failed to render");
+        assert!(svg.contains(
+            "<rect x=\"-105\" y=\"-105\" width=\"210\" height=\"210\" fill=\"#1e1e1e\" />"
+        ));
+        assert!(svg.contains("stroke=\"#e6e6e6\" stroke-width=\"2\" stroke-dasharray=\"4\""));
+        assert!(svg.contains("font-family=\"Arial\" fill=\"#e6e6e6\">1</text>"));
+        assert!(!svg.contains("black"));
+
+        let mut theme = Theme::team();
+        theme.font = "Helvetica".to_string();
+        theme.lines.pass.color = "#fdb927".to_string();
+        let svg = Renderer::new()
+            .with_theme(theme)
+            .render(input)
+            .expect("Failed to render");
+        // The pass gets an arrowhead in its own color
+        assert!(svg.contains("stroke=\"#fdb927\" stroke-width=\"2\" stroke-dasharray=\"4\" marker-end=\"url(#arrowhead-2)\""));
+        assert!(svg.contains("<marker id=\"arrowhead-2\""));
+        assert!(svg.contains("fill=\"#1d428a\" stroke=\"white\""));
+        assert!(svg.contains("font-family=\"Helvetica\""));
+    }
+
+    #[test]
+    fn test_size_and_viewport() {
+        let input = r#"
+            players = { p1, p2 }
+            state = { baller = p1, position = { p1 = (0, 60), p2 = (60, 0) } }
+            action = { move = { p2 -> (60, -40) } }
+        "#;
+        let svg = Renderer::new()
+            .with_size(800, 600)
+            .with_padding(0.0)
+            .render(input)
+            .expect("Failed to render");
+        assert!(svg.starts_with("<svg width=\"800\" height=\"600\" viewBox=\"-100 -90 200 180\""));
+
+        // Auto-crop: players and moves, with room for the markers and the padding
+        let svg = Renderer::new()
+            .with_width(300)
+            .with_auto_crop()
+            .render(input)
+            .expect("Failed to render");
+        assert!(svg.starts_with("<svg width=\"300\" height=\"409\" viewBox=\"-25 -65 110 150\""));
+
+        // Zoomed phases are clipped side by side
+        let input = r#"
+            players = { p1 }
+            state = { position = { p1 = (0, 60) } }
+            phase 1 = { move = { p1 -> (50, 0) } }
+            phase 2 = { move = { p1 -> (-50, 0) } }
+        "#;
+        let svg = Renderer::new()
+            .with_zoom(Region::Right)
+            .render(input)
+            .expect("Failed to render");
+        assert!(svg.contains("viewBox=\"-10 -100 240 200\""));
+        assert!(svg.contains(
+            "<svg x=\"110\" y=\"-100\" width=\"120\" height=\"200\" viewBox=\"-10 -100 120 200\">"
+        ));
+    }
+
+    #[test]
+    fn test_meta_caption() {
+        let input = r#"
+            meta = { name = "Horns <Flare>", call = "41", category = "ATO", notes = "Late clock" }
+            players = { p1 }
+            state = { position = { p1 = (0, 60) } }
+        "#;
+        let svg = Renderer::new().render(input).expect("Failed to render");
+        // A band above the court, as tall as the caption
+        assert!(svg.starts_with("<svg width=\"500\" height=\"557\" viewBox=\"-105 -129 210 234\""));
+        assert!(svg.contains("<title>Horns &lt;Flare&gt;</title><desc>Late clock</desc>"));
+        assert!(svg.contains("font-weight=\"bold\""));
+        assert!(svg.contains(">ATO · Call: 41</text>"));
+
+        // Left to pages that print the name themselves
+        let svg = Renderer::new()
+            .without_caption()
+            .render(input)
+            .expect("Failed to render");
+        assert!(svg.contains("viewBox=\"-105 -105 210 210\""));
+        assert!(svg.contains("<title>"));
+        assert!(!svg.contains("font-weight=\"bold\""));
+    }
 }
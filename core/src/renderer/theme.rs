@@ -0,0 +1,407 @@
+//! Colors, line styles and fonts of the drawing.
+//!
+//! Theme files (JSON, or TOML through the CLI) only need the values they change:
+//! everything else comes from the built-in theme named by `base` (`classic` if absent).
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Names of the built-in themes.
+pub const THEME_NAMES: &[&str] = &["classic", "print", "dark", "team"];
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Theme {
+    /// Page around the court.
+    pub background: String,
+    pub court: CourtStyle,
+    pub offense: PlayerStyle,
+    pub defense: PlayerStyle,
+    /// Starting positions of players that move.
+    pub ghost: String,
+    pub ball: BallStyle,
+    pub lines: LineStyles,
+    /// Dotted line from a defender to their man.
+    pub matchup: LineStyle,
+    /// Phase titles and defense labels.
+    pub text: String,
+    pub font: String,
+    pub player_radius: f64,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CourtStyle {
+    /// Inside the boundary: wood, or plain white for diagrams.
+    pub floor: String,
+    /// Inside the key.
+    pub paint: String,
+    pub lines: String,
+    pub line_width: f64,
+    /// Boundary and half-court line.
+    pub boundary_width: f64,
+    pub hoop: String,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PlayerStyle {
+    /// Inside the circle (offense only, defenders are an X).
+    pub fill: String,
+    pub stroke: String,
+    /// The player's number.
+    pub text: String,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BallStyle {
+    pub fill: String,
+    pub stroke: String,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LineStyle {
+    pub color: String,
+    pub width: f64,
+    /// SVG `stroke-dasharray`, e.g. `"4"` or `"8,2,2,2"`.
+    pub dash: Option<String>,
+}
+
+/// One line style per kind of interaction.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LineStyles {
+    #[serde(rename = "move")]
+    pub move_: LineStyle,
+    pub cut: LineStyle,
+    pub dribble: LineStyle,
+    pub pass: LineStyle,
+    pub handoff: LineStyle,
+    pub screen: LineStyle,
+    pub shot: LineStyle,
+    pub defense: LineStyle,
+}
+
+impl LineStyle {
+    fn new(color: &str, width: f64, dash: Option<&str>) -> Self {
+        Self {
+            color: color.to_string(),
+            width,
+            dash: dash.map(str::to_string),
+        }
+    }
+
+    /// `stroke`, `stroke-width` and `stroke-dasharray` attributes.
+    pub fn attributes(&self) -> String {
+        let mut attributes = format!("stroke=\"{}\" stroke-width=\"{}\"", self.color, self.width);
+        if let Some(dash) = &self.dash {
+            attributes.push_str(&format!(" stroke-dasharray=\"{}\"", dash));
+        }
+        attributes
+    }
+}
+
+impl LineStyles {
+    /// Every interaction drawn in `color`, with the usual dashes.
+    fn plain(color: &str) -> Self {
+        Self {
+            move_: LineStyle::new(color, 2.0, None),
+            cut: LineStyle::new(color, 2.0, None),
+            dribble: LineStyle::new(color, 2.0, None),
+            pass: LineStyle::new(color, 2.0, Some("4")),
+            handoff: LineStyle::new(color, 2.0, None),
+            screen: LineStyle::new(color, 2.0, None),
+            shot: LineStyle::new(color, 2.0, Some("8,2,2,2")),
+            defense: LineStyle::new(color, 2.0, None),
+        }
+    }
+
+    /// Styles with an arrowhead, in drawing order.
+    fn arrows(&self) -> [&LineStyle; 6] {
+        [
+            &self.move_,
+            &self.cut,
+            &self.dribble,
+            &self.pass,
+            &self.shot,
+            &self.defense,
+        ]
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::classic()
+    }
+}
+
+impl Theme {
+    /// Black lines on white, orange ball, red hoop.
+    pub fn classic() -> Self {
+        Self {
+            background: "white".to_string(),
+            court: CourtStyle {
+                floor: "white".to_string(),
+                paint: "none".to_string(),
+                lines: "black".to_string(),
+                line_width: 1.0,
+                boundary_width: 2.0,
+                hoop: "red".to_string(),
+            },
+            offense: PlayerStyle {
+                fill: "white".to_string(),
+                stroke: "black".to_string(),
+                text: "black".to_string(),
+            },
+            defense: PlayerStyle {
+                fill: "none".to_string(),
+                stroke: "black".to_string(),
+                text: "black".to_string(),
+            },
+            ghost: "gray".to_string(),
+            ball: BallStyle {
+                fill: "orange".to_string(),
+                stroke: "black".to_string(),
+            },
+            lines: LineStyles::plain("black"),
+            matchup: LineStyle::new("gray", 1.0, Some("1,2")),
+            text: "black".to_string(),
+            font: "Arial".to_string(),
+            player_radius: 10.0,
+        }
+    }
+
+    /// Black and white only, for photocopies.
+    pub fn print() -> Self {
+        let mut theme = Self::classic();
+        theme.court.hoop = "black".to_string();
+        theme.ball.fill = "white".to_string();
+        theme.ghost = "black".to_string();
+        theme.matchup.color = "black".to_string();
+        theme
+    }
+
+    /// Light lines on a dark court, for screens.
+    pub fn dark() -> Self {
+        let light = "#e6e6e6";
+        Self {
+            background: "#1e1e1e".to_string(),
+            court: CourtStyle {
+                floor: "#2b2b2b".to_string(),
+                paint: "#333333".to_string(),
+                lines: light.to_string(),
+                line_width: 1.0,
+                boundary_width: 2.0,
+                hoop: "#ff6b5b".to_string(),
+            },
+            offense: PlayerStyle {
+                fill: "#2b2b2b".to_string(),
+                stroke: light.to_string(),
+                text: light.to_string(),
+            },
+            defense: PlayerStyle {
+                fill: "none".to_string(),
+                stroke: light.to_string(),
+                text: light.to_string(),
+            },
+            ghost: "#8c8c8c".to_string(),
+            ball: BallStyle {
+                fill: "orange".to_string(),
+                stroke: light.to_string(),
+            },
+            lines: LineStyles::plain(light),
+            matchup: LineStyle::new("#8c8c8c", 1.0, Some("1,2")),
+            text: light.to_string(),
+            font: "Arial".to_string(),
+            player_radius: 10.0,
+        }
+    }
+
+    /// A wood floor with painted key, offense and defense in team colors.
+    /// Override `offense` and `defense` in a theme file for your own colors.
+    pub fn team() -> Self {
+        let mut theme = Self::classic();
+        theme.court.floor = "#e9c893".to_string();
+        theme.court.paint = "#c9d6ee".to_string();
+        theme.court.lines = "white".to_string();
+        theme.offense = PlayerStyle {
+            fill: "#1d428a".to_string(),
+            stroke: "white".to_string(),
+            text: "white".to_string(),
+        };
+        theme.defense = PlayerStyle {
+            fill: "none".to_string(),
+            stroke: "#c8102e".to_string(),
+            text: "#c8102e".to_string(),
+        };
+        theme.lines.defense.color = "#c8102e".to_string();
+        theme
+    }
+
+    pub fn from_name(name: &str) -> Option<Theme> {
+        match name {
+            "classic" => Some(Self::classic()),
+            "print" => Some(Self::print()),
+            "dark" => Some(Self::dark()),
+            "team" => Some(Self::team()),
+            _ => None,
+        }
+    }
+
+    /// A theme from a JSON document, on top of the built-in theme named by its `base` key.
+    pub fn from_json(input: &str) -> Result<Theme, String> {
+        let value = serde_json::from_str(input).map_err(|e| e.to_string())?;
+        Self::from_value(value)
+    }
+
+    /// A theme from parsed JSON (or anything serde reads into a `Value`, like TOML).
+    pub fn from_value(mut value: Value) -> Result<Theme, String> {
+        let Some(fields) = value.as_object_mut() else {
+            return Err("A theme must be a table of settings".to_string());
+        };
+        let base = match fields.remove("base") {
+            None => Self::classic(),
+            Some(Value::String(name)) => Self::from_name(&name).ok_or_else(|| {
+                format!("Unknown base theme '{}' ({})", name, THEME_NAMES.join(", "))
+            })?,
+            Some(_) => return Err("'base' must be the name of a theme".to_string()),
+        };
+        let mut merged = serde_json::to_value(base).map_err(|e| e.to_string())?;
+        merge(&mut merged, value);
+        check_strings(&merged, "")?;
+        serde_json::from_value(merged).map_err(|e| e.to_string())
+    }
+
+    /// Id of the arrowhead marker drawn in `color`.
+    pub(crate) fn arrowhead(&self, color: &str) -> String {
+        let index = self
+            .arrow_colors()
+            .iter()
+            .position(|c| *c == color)
+            .unwrap_or(0);
+        match index {
+            0 => "arrowhead".to_string(),
+            i => format!("arrowhead-{}", i + 1),
+        }
+    }
+
+    /// Arrowhead markers, one per line color.
+    pub(crate) fn render_markers(&self) -> String {
+        let mut defs = String::from("<defs>");
+        for color in self.arrow_colors() {
+            defs.push_str(&format!(
+                "<marker id=\"{}\" markerWidth=\"10\" markerHeight=\"7\" refX=\"10\" refY=\"3.5\" orient=\"auto\"><polygon points=\"0 0, 10 3.5, 0 7\" fill=\"{}\" /></marker>",
+                self.arrowhead(color),
+                color
+            ));
+        }
+        defs.push_str("</defs>");
+        defs
+    }
+
+    fn arrow_colors(&self) -> Vec<&str> {
+        let mut colors: Vec<&str> = Vec::new();
+        for style in self.lines.arrows() {
+            if !colors.contains(&style.color.as_str()) {
+                colors.push(&style.color);
+            }
+        }
+        colors
+    }
+
+    /// `fill` for text, left out when it is the SVG default.
+    pub(crate) fn text_fill(color: &str) -> String {
+        if color == "black" {
+            String::new()
+        } else {
+            format!(" fill=\"{}\"", color)
+        }
+    }
+}
+
+/// Theme strings are written into SVG attributes as they are, so they cannot
+/// contain the characters that would end the attribute or start markup.
+fn check_strings(value: &Value, path: &str) -> Result<(), String> {
+    match value {
+        Value::String(text) => match text.chars().find(|c| matches!(c, '"' | '&' | '<' | '>')) {
+            Some(c) => Err(format!(
+                "'{}' cannot contain '{}': use a plain color, font name or dash pattern",
+                path, c
+            )),
+            None => Ok(()),
+        },
+        Value::Object(fields) => fields.iter().try_for_each(|(key, value)| {
+            let path = if path.is_empty() {
+                key.clone()
+            } else {
+                format!("{}.{}", path, key)
+            };
+            check_strings(value, &path)
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Copies `overrides` into `base`, table by table.
+fn merge(base: &mut Value, overrides: Value) {
+    match (base, overrides) {
+        (Value::Object(base), Value::Object(overrides)) => {
+            for (key, value) in overrides {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overrides) => *base = overrides,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_theme_files() {
+        let theme = Theme::from_json(
+            r##"{ "base": "dark", "ball": { "fill": "#ff8800" }, "lines": { "pass": { "dash": "2,2" } } }"##,
+        )
+        .unwrap();
+        assert_eq!(theme.ball.fill, "#ff8800");
+        assert_eq!(theme.ball.stroke, Theme::dark().ball.stroke);
+        assert_eq!(theme.lines.pass.dash.as_deref(), Some("2,2"));
+        assert_eq!(theme.lines.pass.color, Theme::dark().lines.pass.color);
+
+        assert!(
+            Theme::from_json(r#"{ "base": "neon" }"#)
+                .unwrap_err()
+                .contains("neon")
+        );
+        // Misspelled settings are reported instead of ignored
+        assert!(Theme::from_json(r#"{ "bal": { "fill": "red" } }"#).is_err());
+    }
+
+    #[test]
+    fn test_arrowheads_follow_line_colors() {
+        assert_eq!(Theme::classic().arrowhead("black"), "arrowhead");
+        let team = Theme::team();
+        assert_eq!(team.arrowhead("#c8102e"), "arrowhead-2");
+        assert_eq!(team.render_markers().matches("<marker").count(), 2);
+    }
+
+    #[test]
+    fn test_theme_strings_stay_in_their_attribute() {
+        let error = Theme::from_json(r#"{ "font": "Arial\" onload=\"alert(1)" }"#).unwrap_err();
+        assert!(error.starts_with("'font' cannot contain '\"'"));
+        let error =
+            Theme::from_json(r#"{ "lines": { "pass": { "dash": "4</svg>" } } }"#).unwrap_err();
+        assert!(error.starts_with("'lines.pass.dash' cannot contain '<'"));
+        // Quotes that stay inside the attribute are fine
+        let theme = Theme::from_json(r#"{ "font": "'Helvetica Neue', sans-serif" }"#).unwrap();
+        assert_eq!(theme.font, "'Helvetica Neue', sans-serif");
+    }
+}
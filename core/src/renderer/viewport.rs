@@ -0,0 +1,219 @@
+//! Which part of the court ends up in the picture.
+
+use super::round;
+use crate::court::{BASELINE, CourtSpec, CourtView};
+use crate::ir::Scene;
+
+/// Names accepted by `Region::parse`, besides `x1,y1,x2,y2` corners.
+pub const REGION_NAMES: &[&str] = &["left", "right", "paint", "frontcourt", "backcourt"];
+
+/// Padding around cropped and zoomed pictures, in drawing units.
+const DEFAULT_PADDING: f64 = 10.0;
+
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub(crate) enum Viewport {
+    /// The whole drawn court.
+    #[default]
+    Court,
+    /// Just around the players and what they do.
+    Fit,
+    Zoom(Region),
+}
+
+/// Part of the court to zoom on.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Region {
+    /// Left half of the court, as seen by the offense facing the basket.
+    Left,
+    Right,
+    /// The key, up to the top of the free-throw circle.
+    Paint,
+    /// Baseline to half-court line.
+    Frontcourt,
+    /// Past the half-court line, as far as the court is drawn.
+    Backcourt,
+    /// Between two opposite corners, in playbook coordinates.
+    Rect((f64, f64), (f64, f64)),
+}
+
+impl Region {
+    /// A region name, or corners written `x1,y1,x2,y2`. Corners on the same
+    /// line would leave nothing to draw, so they are not a region.
+    pub fn parse(input: &str) -> Option<Region> {
+        match input {
+            "left" => Some(Region::Left),
+            "right" => Some(Region::Right),
+            "paint" => Some(Region::Paint),
+            "frontcourt" => Some(Region::Frontcourt),
+            "backcourt" => Some(Region::Backcourt),
+            _ => {
+                let numbers: Vec<f64> = input
+                    .split(',')
+                    .map(|n| n.trim().parse().ok())
+                    .collect::<Option<_>>()?;
+                match numbers[..] {
+                    [x1, y1, x2, y2]
+                        if numbers.iter().all(|n| n.is_finite()) && x1 != x2 && y1 != y2 =>
+                    {
+                        Some(Region::Rect((x1, y1), (x2, y2)))
+                    }
+                    _ => None,
+                }
+            }
+        }
+    }
+
+    fn bounds(&self, court: &CourtSpec, view: CourtView) -> Bounds {
+        let half_line = court.y(court.half_length);
+        match self {
+            Region::Left => Bounds::new((-100.0, BASELINE), (0.0, half_line)),
+            Region::Right => Bounds::new((0.0, BASELINE), (100.0, half_line)),
+            Region::Paint => {
+                let s = court.scale();
+                let lane = court.lane_width * s / 2.0;
+                let top = court.y(court.free_throw_line + court.free_throw_circle);
+                Bounds::new((-lane, BASELINE), (lane, top))
+            }
+            Region::Frontcourt => Bounds::new((-100.0, BASELINE), (100.0, half_line)),
+            Region::Backcourt => Bounds::new(
+                (-100.0, half_line),
+                (100.0, BASELINE + view.drawn_length(court)),
+            ),
+            Region::Rect(a, b) => Bounds::new(court.to_diagram(*a), court.to_diagram(*b)),
+        }
+    }
+}
+
+/// The rectangle shown in the picture, in drawing units.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub(crate) struct Frame {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl Frame {
+    /// What `viewport` shows of `scenes`, with `padding` all around.
+    /// Without padding, the court keeps its usual margins: 5 units on the sides, 15 at the ends.
+    pub fn new(
+        viewport: Viewport,
+        padding: Option<f64>,
+        (court, view): (&CourtSpec, CourtView),
+        scenes: &[Scene],
+        player_radius: f64,
+    ) -> Frame {
+        let length = view.drawn_length(court);
+        let whole_court = Bounds::new((-100.0, BASELINE), (100.0, BASELINE + length));
+        let bounds = match viewport {
+            Viewport::Court if padding.is_none() => {
+                return Frame {
+                    x: -105.0,
+                    y: -105.0,
+                    width: 210.0,
+                    height: round(length + 30.0),
+                };
+            }
+            Viewport::Court => whole_court,
+            // Room for the player markers and the ball beside them
+            Viewport::Fit => content(scenes)
+                .map(|bounds| bounds.grow(player_radius * 1.5))
+                .unwrap_or(whole_court),
+            Viewport::Zoom(region) => region.bounds(court, view),
+        };
+        bounds.frame(padding.unwrap_or(DEFAULT_PADDING))
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+struct Bounds {
+    min: (f64, f64),
+    max: (f64, f64),
+}
+
+impl Bounds {
+    fn new(a: (f64, f64), b: (f64, f64)) -> Bounds {
+        Bounds {
+            min: (a.0.min(b.0), a.1.min(b.1)),
+            max: (a.0.max(b.0), a.1.max(b.1)),
+        }
+    }
+
+    fn include(&mut self, (x, y): (f64, f64)) {
+        self.min = (self.min.0.min(x), self.min.1.min(y));
+        self.max = (self.max.0.max(x), self.max.1.max(y));
+    }
+
+    fn grow(self, by: f64) -> Bounds {
+        Bounds {
+            min: (self.min.0 - by, self.min.1 - by),
+            max: (self.max.0 + by, self.max.1 + by),
+        }
+    }
+
+    fn frame(self, padding: f64) -> Frame {
+        let bounds = self.grow(padding);
+        Frame {
+            x: round(bounds.min.0),
+            y: round(bounds.min.1),
+            width: round(bounds.max.0 - bounds.min.0),
+            height: round(bounds.max.1 - bounds.min.1),
+        }
+    }
+}
+
+/// Everything drawn in any of the scenes: players where they start and end, and every line.
+fn content(scenes: &[Scene]) -> Option<Bounds> {
+    let mut points = scenes.iter().flat_map(|scene| {
+        let players = scene.entities.iter().flat_map(|e| [e.start_pos, e.end_pos]);
+        let lines = scene.interactions.iter().flat_map(|i| i.points());
+        players.chain(lines)
+    });
+    let first = points.next()?;
+    let mut bounds = Bounds::new(first, first);
+    for point in points {
+        bounds.include(point);
+    }
+    Some(bounds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_regions() {
+        assert_eq!(Region::parse("left"), Some(Region::Left));
+        assert_eq!(
+            Region::parse("-50, -90, 100,0"),
+            Some(Region::Rect((-50.0, -90.0), (100.0, 0.0)))
+        );
+        assert_eq!(Region::parse("1,2,3"), None);
+        assert_eq!(Region::parse("0,0,0,0"), None);
+        assert_eq!(Region::parse("-50,0,100,0"), None);
+        assert_eq!(Region::parse("0,0,inf,10"), None);
+        assert_eq!(Region::parse("weak_side"), None);
+
+        let classic = (&CourtSpec::CLASSIC, CourtView::Half);
+        let frame = |region| Frame::new(Viewport::Zoom(region), Some(0.0), classic, &[], 10.0);
+        assert_eq!(
+            frame(Region::Right),
+            Frame {
+                x: 0.0,
+                y: -90.0,
+                width: 100.0,
+                height: 180.0
+            }
+        );
+        // The key and the free-throw circle
+        assert_eq!(
+            frame(Region::Paint),
+            Frame {
+                x: -20.0,
+                y: -90.0,
+                width: 40.0,
+                height: 85.0
+            }
+        );
+    }
+}
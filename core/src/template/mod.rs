@@ -0,0 +1,267 @@
+//! `template name(params) = { ... }`: actions written once and called with different players.
+//!
+//! Calls are expanded into the block that makes them before the play is checked,
+//! so a wrong player in a call is reported like one written out by hand.
+//! Names from the template point at the call, arguments at themselves.
+
+use crate::ast::{Action, EventRef, Playbook, Point, Template, TemplateCall, Timing};
+use crate::diagnostic::Diagnostic;
+use crate::lexer::Span;
+use crate::parser::get_suggestion;
+use std::collections::HashMap;
+
+/// Replaces every template call of `playbook` with the template's actions,
+/// added after the block's own. Calls that cannot be expanded are reported and dropped.
+pub fn expand(playbook: &mut Playbook) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let actions = std::iter::once(&mut playbook.action)
+        .chain(playbook.phases.iter_mut().map(|phase| &mut phase.action));
+    for action in actions {
+        expand_calls(
+            action,
+            &playbook.templates,
+            &mut Vec::new(),
+            &mut diagnostics,
+        );
+    }
+    diagnostics
+}
+
+/// `calling` holds the templates being expanded, each called by the one before.
+fn expand_calls(
+    action: &mut Action,
+    templates: &[Template],
+    calling: &mut Vec<String>,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    for call in std::mem::take(&mut action.calls) {
+        let Some(template) = templates.iter().find(|t| t.name == call.name) else {
+            diagnostics.push(unknown_template(&call, templates));
+            continue;
+        };
+        let (params, args) = (template.params.len(), call.args.len());
+        if args != params {
+            diagnostics.push(
+                Diagnostic::error(
+                    "E0302",
+                    format!(
+                        "Template '{}' takes {} argument{} but {} {} given",
+                        template.name,
+                        params,
+                        if params == 1 { "" } else { "s" },
+                        args,
+                        if args == 1 { "was" } else { "were" },
+                    ),
+                    call.span,
+                )
+                .with_help(format!(
+                    "{}({})",
+                    template.name,
+                    template.params.join(", ")
+                )),
+            );
+            continue;
+        }
+        if calling.contains(&call.name) {
+            let cycle: Vec<&str> = calling
+                .iter()
+                .map(String::as_str)
+                .chain(std::iter::once(call.name.as_str()))
+                .collect();
+            diagnostics.push(Diagnostic::error(
+                "E0303",
+                format!("Template calls itself: {}", cycle.join(" -> ")),
+                call.span,
+            ));
+            continue;
+        }
+
+        let mut body = template.action.clone();
+        Substitution::new(template, &call).action(&mut body);
+        calling.push(call.name.clone());
+        expand_calls(&mut body, templates, calling, diagnostics);
+        calling.pop();
+
+        action.moves.extend(body.moves);
+        action.screens.extend(body.screens);
+        action.passes.extend(body.passes);
+        action.shots.extend(body.shots);
+        action.defense.extend(body.defense);
+    }
+}
+
+fn unknown_template(call: &TemplateCall, templates: &[Template]) -> Diagnostic {
+    let names: Vec<&str> = templates.iter().map(|t| t.name.as_str()).collect();
+    let diagnostic = Diagnostic::error(
+        "E0301",
+        format!("Unknown template '{}'", call.name),
+        call.span,
+    );
+    match get_suggestion(&call.name, &names) {
+        Some(suggestion) => diagnostic.with_suggestion(suggestion),
+        None => diagnostic.with_help(format!(
+            "define it with template {}(...) = {{ ... }}, or import the file it is in",
+            call.name
+        )),
+    }
+}
+
+/// Puts a call's arguments in place of the template's parameters.
+struct Substitution<'a> {
+    args: HashMap<&'a str, &'a (String, Span)>,
+    /// The call's name.
+    span: Span,
+}
+
+impl<'a> Substitution<'a> {
+    fn new(template: &'a Template, call: &'a TemplateCall) -> Self {
+        Self {
+            args: template
+                .params
+                .iter()
+                .map(String::as_str)
+                .zip(&call.args)
+                .collect(),
+            span: call.span,
+        }
+    }
+
+    fn name(&self, name: &mut String, span: &mut Span) {
+        match self.args.get(name.as_str()) {
+            Some((arg, arg_span)) => {
+                *name = arg.clone();
+                *span = *arg_span;
+            }
+            None => *span = self.span,
+        }
+    }
+
+    fn action(&self, action: &mut Action) {
+        for m in &mut action.moves {
+            self.name(&mut m.player, &mut m.span);
+            for waypoint in &mut m.path {
+                self.point(&mut waypoint.point);
+                if let Some(via) = &mut waypoint.via {
+                    self.point(via);
+                }
+            }
+        }
+        for screen in &mut action.screens {
+            self.name(&mut screen.player, &mut screen.span);
+            self.name(&mut screen.target, &mut screen.target_span);
+            self.timing(&mut screen.timing);
+        }
+        for pass in &mut action.passes {
+            self.name(&mut pass.from, &mut pass.span);
+            self.name(&mut pass.to, &mut pass.to_span);
+            self.timing(&mut pass.timing);
+        }
+        for shot in &mut action.shots {
+            self.name(&mut shot.player, &mut shot.span);
+            self.timing(&mut shot.timing);
+        }
+        for defense in &mut action.defense {
+            self.name(&mut defense.defender, &mut defense.span);
+            self.name(&mut defense.target, &mut defense.target_span);
+            self.timing(&mut defense.timing);
+        }
+        // Calls made by the template take this call's arguments
+        for call in &mut action.calls {
+            call.span = self.span;
+            for (arg, span) in &mut call.args {
+                self.name(arg, span);
+            }
+        }
+    }
+
+    fn point(&self, point: &mut Point) {
+        match point {
+            Point::Name(name, span) => self.name(name, span),
+            Point::Offset(base, _) => self.point(base),
+            Point::Toward(target, _, span) => {
+                *span = self.span;
+                self.point(target);
+            }
+            Point::Coord(..) | Point::Polar(..) => {}
+        }
+    }
+
+    fn timing(&self, timing: &mut Timing) {
+        if let Timing::BeforeEvent(EventRef { player, span, .. })
+        | Timing::AfterEvent(EventRef { player, span, .. }) = timing
+        {
+            self.name(player, span);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::diagnostic;
+
+    #[test]
+    fn test_expand() {
+        let input = r#"
+            players = { p1, p2, p5 }
+            state = { baller = p1, position = { p1 = (0, 60), p2 = left_wing, p5 = (0, 30) } }
+            template pick_and_roll(handler, screener) = {
+                screen = { screener -> handler:before },
+                dribble = { handler -> toward(basket, 20) },
+                move = { screener -> nail },
+            }
+            template give(from, to) = { pass = { from -> to } }
+            phase 1 = { pick_and_roll(p1, p5) }
+            phase 2 = { give(p1, p2) }
+        "#;
+        let (playbook, diagnostics) = diagnostic::analyze(input);
+        assert!(diagnostics.is_empty());
+
+        let phase = &playbook.phases[0].action;
+        assert!(phase.calls.is_empty());
+        assert_eq!(phase.screens[0].player, "p5");
+        assert_eq!(phase.screens[0].target, "p1");
+        assert_eq!(phase.moves[0].player, "p1");
+        assert_eq!(phase.moves[1].player, "p5");
+        // Arguments point at themselves, the rest at the call
+        let call = input.find("pick_and_roll(p1").unwrap();
+        assert_eq!(phase.moves[0].span.start, call + "pick_and_roll(".len());
+        assert_eq!(phase.moves[1].span.start, call + "pick_and_roll(p1, ".len());
+        assert_eq!(phase.moves[1].path[0].point.names()[0].1.start, call);
+        assert_eq!(playbook.phases[1].action.passes[0].to, "p2");
+    }
+
+    #[test]
+    fn test_call_errors() {
+        let input = r#"
+            players = { p1, p2 }
+            state = { baller = p1, position = { p1 = (0, 60), p2 = (0, 30) } }
+            template give(from, to) = { pass = { from -> to } }
+            template again(a) = { again(a) }
+            action = { giv(p1, p2), give(p1), again(p1) }
+        "#;
+        let (_, diagnostics) = diagnostic::analyze(input);
+        let codes: Vec<&str> = diagnostics.iter().map(|d| d.code).collect();
+        assert_eq!(codes, ["E0301", "E0302", "E0303"]);
+        assert_eq!(diagnostics[0].suggestion.as_deref(), Some("give"));
+        assert_eq!(
+            diagnostics[1].message,
+            "Template 'give' takes 2 arguments but 1 was given"
+        );
+        assert_eq!(
+            diagnostics[2].message,
+            "Template calls itself: again -> again"
+        );
+
+        // Expanded actions are checked like any other
+        let input = input.replace(
+            "action = { giv(p1, p2), give(p1), again(p1) }",
+            "action = { give(p1, p3) }",
+        );
+        let (_, diagnostics) = diagnostic::analyze(&input);
+        assert_eq!(diagnostics[0].code, "E0101");
+        assert_eq!(
+            diagnostics[0].primary.span.start,
+            input.find("p3)").unwrap()
+        );
+    }
+}
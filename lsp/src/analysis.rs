@@ -0,0 +1,557 @@
+use lsp_types::{
+    CodeAction, CodeActionKind, CompletionItem, CompletionItemKind, DiagnosticRelatedInformation,
+    DiagnosticSeverity, Hover, HoverContents, Location, MarkupContent, MarkupKind, NumberOrString,
+    Position, Range, TextEdit, Uri, WorkspaceEdit,
+};
+use playbook_lang_core::ast::Playbook;
+use playbook_lang_core::court;
+use playbook_lang_core::diagnostic::{self, Diagnostic, Severity};
+use playbook_lang_core::import::FileLoader;
+use playbook_lang_core::ir::{IRGenerator, Scene};
+use playbook_lang_core::lexer::{Lexer, Span, Token, TokenKind};
+use playbook_lang_core::parser::get_suggestion;
+use std::collections::HashMap;
+use std::path::Path;
+
+const KEYWORDS: &[&str] = &[
+    "players",
+    "defense",
+    "state",
+    "baller",
+    "position",
+    "guards",
+    "action",
+    "phase",
+    "move",
+    "dribble",
+    "cut",
+    "screen",
+    "pass",
+    "handoff",
+    "shot",
+    "switch",
+    "hedge",
+    "help",
+    "trap",
+    "closeout",
+    "via",
+    "curve",
+    "spots",
+    "toward",
+    "polar",
+    "court",
+    "units",
+    "view",
+    "meta",
+    "import",
+    "use",
+    "name",
+    "call",
+    "category",
+    "formation",
+    "tags",
+    "notes",
+    "transform",
+    "mirror",
+    "rotate",
+    "translate",
+    "template",
+];
+const TIMINGS: &[&str] = &["before", "middle", "after"];
+
+/// Everything the server knows about one open document.
+pub struct Analysis {
+    text: String,
+    line_starts: Vec<usize>,
+    tokens: Vec<Token>,
+    playbook: Playbook,
+    diagnostics: Vec<Diagnostic>,
+    scenes: Vec<Scene>,
+}
+
+impl Analysis {
+    pub fn new(text: String) -> Self {
+        let analyzed = diagnostic::analyze(&text);
+        Self::from_analyzed(text, analyzed)
+    }
+
+    /// A document saved at `path`, whose imports are read from disk.
+    pub fn in_file(text: String, path: &Path) -> Self {
+        let analyzed = diagnostic::analyze_file(&text, path, &FileLoader);
+        Self::from_analyzed(text, analyzed)
+    }
+
+    fn from_analyzed(text: String, (playbook, diagnostics): (Playbook, Vec<Diagnostic>)) -> Self {
+        let tokens = Lexer::new(&text).tokenize();
+        let scenes = IRGenerator::generate(playbook.clone());
+        let line_starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self {
+            text,
+            line_starts,
+            tokens,
+            playbook,
+            diagnostics,
+            scenes,
+        }
+    }
+
+    pub fn diagnostics(&self, uri: &Uri) -> Vec<lsp_types::Diagnostic> {
+        self.diagnostics
+            .iter()
+            // Errors in imported files show up at the import
+            .filter(|d| d.file.is_none())
+            .map(|d| lsp_types::Diagnostic {
+                range: self.range(d.primary.span),
+                severity: Some(match d.severity {
+                    Severity::Error => DiagnosticSeverity::ERROR,
+                    Severity::Warning => DiagnosticSeverity::WARNING,
+                }),
+                code: Some(NumberOrString::String(d.code.to_string())),
+                source: Some("playbook".to_string()),
+                message: match &d.help {
+                    Some(help) => format!("{}\nhelp: {}", d.message, help),
+                    None => d.message.clone(),
+                },
+                related_information: (!d.secondary.is_empty()).then(|| {
+                    d.secondary
+                        .iter()
+                        .map(|label| DiagnosticRelatedInformation {
+                            location: Location {
+                                uri: uri.clone(),
+                                range: self.range(label.span),
+                            },
+                            message: label.message.clone(),
+                        })
+                        .collect()
+                }),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    pub fn completions(&self, position: Position) -> Vec<CompletionItem> {
+        let offset = self.offset(position);
+        let after_colon = self.text[..offset]
+            .trim_end_matches(|c: char| c.is_alphanumeric() || c == '_')
+            .ends_with(':');
+        if after_colon {
+            return TIMINGS
+                .iter()
+                .map(|timing| completion(timing, CompletionItemKind::ENUM_MEMBER, "timing"))
+                .collect();
+        }
+
+        let mut items: Vec<CompletionItem> = KEYWORDS
+            .iter()
+            .map(|keyword| completion(keyword, CompletionItemKind::KEYWORD, "keyword"))
+            .collect();
+        // A name can be both a player and a defender; it is offered once, in declaration order
+        let mut players: Vec<&String> = Vec::new();
+        for player in self.playbook.players.iter().chain(&self.playbook.defense) {
+            if !players.contains(&player) {
+                players.push(player);
+            }
+        }
+        items.extend(
+            players
+                .iter()
+                .map(|player| completion(player, CompletionItemKind::VARIABLE, "player")),
+        );
+        let mut spots: Vec<&str> = self.playbook.spots.keys().map(String::as_str).collect();
+        spots.sort();
+        spots.extend(court::SPOT_NAMES);
+        items.extend(
+            spots
+                .iter()
+                .map(|spot| completion(spot, CompletionItemKind::CONSTANT, "spot")),
+        );
+        items.extend(
+            self.playbook.templates.iter().map(|template| {
+                completion(&template.name, CompletionItemKind::FUNCTION, "template")
+            }),
+        );
+        items
+    }
+
+    pub fn hover(&self, position: Position) -> Option<Hover> {
+        let (name, span) = self.identifier_at(position)?;
+        if let Some((x, y)) = self.playbook.spot(name).filter(|_| !self.is_player(name)) {
+            return Some(Hover {
+                contents: HoverContents::Markup(MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value: format!("**{}** (spot)\n\n({}, {})", name, x, y),
+                }),
+                range: Some(self.range(span)),
+            });
+        }
+        let positions: Vec<String> = self
+            .scenes
+            .iter()
+            .filter_map(|scene| {
+                let entity = scene.entities.iter().find(|e| &e.id == name)?;
+                let fmt = |(x, y): (f64, f64)| format!("({}, {})", x, y);
+                let line = format!("{} → {}", fmt(entity.start_pos), fmt(entity.end_pos));
+                Some(if self.scenes.len() > 1 {
+                    format!("phase {}: {}", scene.number, line)
+                } else {
+                    line
+                })
+            })
+            .collect();
+        if positions.is_empty() {
+            return None;
+        }
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: format!("**{}**\n\n{}", name, positions.join("  \n")),
+            }),
+            range: Some(self.range(span)),
+        })
+    }
+
+    pub fn definition(&self, position: Position) -> Option<Range> {
+        let (name, _) = self.identifier_at(position)?;
+        let playbook = &self.playbook;
+        let span = match playbook.players.iter().position(|p| p == name) {
+            Some(index) => playbook.player_spans.get(index)?,
+            None => match playbook.defense.iter().position(|x| x == name) {
+                Some(index) => playbook.defense_spans.get(index)?,
+                None => playbook.spot_spans.get(name)?,
+            },
+        };
+        Some(self.range(*span))
+    }
+
+    /// Renames a player everywhere it is used. `None` if `new_name` is not a valid name,
+    /// or is already a player or a spot, since the play would then mean something else.
+    pub fn rename(&self, position: Position, new_name: &str) -> Option<Vec<TextEdit>> {
+        let (name, _) = self.identifier_at(position)?;
+        if !self.is_player(name)
+            || !is_identifier(new_name)
+            || self.is_player(new_name)
+            || self.playbook.spot(new_name).is_some()
+        {
+            return None;
+        }
+        // A template parameter of the same name is another thing, inside its template
+        let shadowed: Vec<(usize, usize)> = self
+            .playbook
+            .templates
+            .iter()
+            .filter(|template| template.params.contains(name))
+            .filter_map(|template| self.template_extent(template.span))
+            .collect();
+        Some(
+            self.tokens
+                .iter()
+                .enumerate()
+                .filter(|(i, token)| {
+                    matches!(&token.kind, TokenKind::Identifier(s) if s == name)
+                        // `name(...)` is a template
+                        && self.tokens.get(i + 1).map(|next| &next.kind)
+                            != Some(&TokenKind::LParenthesis)
+                        && !shadowed
+                            .iter()
+                            .any(|(start, end)| (*start..*end).contains(&token.span.start))
+                })
+                .map(|(_, token)| TextEdit {
+                    range: self.range(token.span),
+                    new_text: new_name.to_string(),
+                })
+                .collect(),
+        )
+    }
+
+    /// Quick fixes for typos, using the same suggestions as the compiler.
+    pub fn code_actions(&self, uri: &Uri, range: Range) -> Vec<CodeAction> {
+        let players: Vec<&str> = self.playbook.players.iter().map(String::as_str).collect();
+        self.diagnostics
+            .iter()
+            .filter(|d| overlaps(self.range(d.primary.span), range))
+            .filter_map(|d| {
+                let span = d.primary.span;
+                let replacement = match &d.suggestion {
+                    Some(suggestion) => suggestion.clone(),
+                    None if d.code == "E0101" => {
+                        get_suggestion(self.text.get(span.start..span.end)?, &players)?
+                    }
+                    None => return None,
+                };
+                let edit = TextEdit {
+                    range: self.range(span),
+                    new_text: replacement.clone(),
+                };
+                Some(CodeAction {
+                    title: format!("Replace with '{}'", replacement),
+                    kind: Some(CodeActionKind::QUICKFIX),
+                    edit: Some(WorkspaceEdit {
+                        changes: Some(HashMap::from([(uri.clone(), vec![edit])])),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                })
+            })
+            .collect()
+    }
+
+    /// Source offsets from a template's name to the `}` closing its body.
+    fn template_extent(&self, name_span: Span) -> Option<(usize, usize)> {
+        let first = self
+            .tokens
+            .iter()
+            .position(|token| token.span.start == name_span.start)?;
+        let mut depth = 0usize;
+        for token in &self.tokens[first..] {
+            match token.kind {
+                TokenKind::LBrace => depth += 1,
+                TokenKind::RBrace => {
+                    depth = depth.saturating_sub(1);
+                    if depth == 0 {
+                        return Some((name_span.start, token.span.end));
+                    }
+                }
+                _ => {}
+            }
+        }
+        Some((name_span.start, self.text.len()))
+    }
+
+    fn is_player(&self, name: &str) -> bool {
+        let mut declared = self.playbook.players.iter().chain(&self.playbook.defense);
+        declared.any(|p| p == name)
+    }
+
+    fn identifier_at(&self, position: Position) -> Option<(&String, Span)> {
+        let offset = self.offset(position);
+        self.tokens.iter().find_map(|token| match &token.kind {
+            TokenKind::Identifier(name)
+                if token.span.start <= offset && offset <= token.span.end =>
+            {
+                Some((name, token.span))
+            }
+            _ => None,
+        })
+    }
+
+    fn position(&self, offset: usize) -> Position {
+        let line = self.line_starts.partition_point(|&start| start <= offset) - 1;
+        let start = self.line_starts[line];
+        let character = self.text[start..offset.min(self.text.len())]
+            .encode_utf16()
+            .count();
+        Position::new(line as u32, character as u32)
+    }
+
+    fn offset(&self, position: Position) -> usize {
+        let Some(&start) = self.line_starts.get(position.line as usize) else {
+            return self.text.len();
+        };
+        let mut utf16 = 0;
+        for (i, c) in self.text[start..].char_indices() {
+            if utf16 >= position.character as usize || c == '\n' {
+                return start + i;
+            }
+            utf16 += c.len_utf16();
+        }
+        self.text.len()
+    }
+
+    fn range(&self, span: Span) -> Range {
+        Range::new(self.position(span.start), self.position(span.end))
+    }
+}
+
+fn completion(label: &str, kind: CompletionItemKind, detail: &str) -> CompletionItem {
+    CompletionItem {
+        label: label.to_string(),
+        kind: Some(kind),
+        detail: Some(detail.to_string()),
+        ..Default::default()
+    }
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(char::is_alphabetic)
+        && chars.all(|c| c.is_alphanumeric() || c == '_')
+        && !KEYWORDS.contains(&name)
+        && !TIMINGS.contains(&name)
+}
+
+fn overlaps(a: Range, b: Range) -> bool {
+    a.start <= b.end && b.start <= a.end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INPUT: &str = "players = { p1, p2 }
+state = {
+  baller = p1,
+  position = { p1 = (0, 60), p2 = (90, -80) },
+}
+action = {
+  move = { p2 -> (70, 20) },
+  pass = { p1 -> p2:after },
+}";
+
+    #[test]
+    fn test_definition_and_rename() {
+        let analysis = Analysis::new(INPUT.to_string());
+
+        // `p2` in the move
+        let definition = analysis.definition(Position::new(6, 12)).unwrap();
+        assert_eq!(
+            definition,
+            Range::new(Position::new(0, 16), Position::new(0, 18))
+        );
+
+        let edits = analysis.rename(Position::new(0, 16), "wing").unwrap();
+        assert_eq!(edits.len(), 4);
+        assert!(edits.iter().all(|e| e.new_text == "wing"));
+        assert!(analysis.rename(Position::new(0, 16), "pass").is_none());
+    }
+
+    #[test]
+    fn test_rename_keeps_meaning() {
+        let input = "players = { p1, p2 }
+defense = { p2 }
+spots = { deep = (0, 80) }
+template give(p1, to) = { pass = { p1 -> to } }
+state = { baller = p1, position = { p1 = (0, 60), p2 = deep } }
+action = { give(p1, p2) }";
+        let analysis = Analysis::new(input.to_string());
+        for taken in ["p2", "nail", "deep"] {
+            assert!(analysis.rename(Position::new(0, 12), taken).is_none());
+        }
+
+        // The template's own `p1` is left alone
+        let edits = analysis.rename(Position::new(0, 12), "lead").unwrap();
+        let lines: Vec<u32> = edits.iter().map(|e| e.range.start.line).collect();
+        assert_eq!(lines, [0, 4, 4, 5]);
+
+        let players: Vec<String> = analysis
+            .completions(Position::new(5, 0))
+            .into_iter()
+            .filter(|item| item.detail.as_deref() == Some("player"))
+            .map(|item| item.label)
+            .collect();
+        assert_eq!(players, ["p1", "p2"]);
+    }
+
+    #[test]
+    fn test_hover() {
+        let analysis = Analysis::new(INPUT.to_string());
+        let hover = analysis.hover(Position::new(7, 17)).unwrap();
+        let HoverContents::Markup(content) = hover.contents else {
+            panic!("Expected markdown hover");
+        };
+        assert_eq!(content.value, "**p2**\n\n(90, -80) → (70, 20)");
+    }
+
+    #[test]
+    fn test_completions() {
+        let analysis = Analysis::new(INPUT.to_string());
+        let labels: Vec<String> = analysis
+            .completions(Position::new(7, 20))
+            .into_iter()
+            .map(|item| item.label)
+            .collect();
+        assert_eq!(labels, vec!["before", "middle", "after"]);
+
+        let labels: Vec<String> = analysis
+            .completions(Position::new(6, 2))
+            .into_iter()
+            .map(|item| item.label)
+            .collect();
+        assert!(labels.contains(&"screen".to_string()));
+        assert!(labels.contains(&"p2".to_string()));
+    }
+
+    #[test]
+    fn test_diagnostics_and_quick_fix() {
+        let analysis = Analysis::new("players = { p1 }\nstate = { baller = p2 }".to_string());
+        let uri: Uri = "file:///play.playbook".parse().unwrap();
+        let diagnostics = analysis.diagnostics(&uri);
+        let undeclared = diagnostics
+            .iter()
+            .find(|d| d.code == Some(NumberOrString::String("E0101".to_string())))
+            .unwrap();
+        assert_eq!(
+            undeclared.range,
+            Range::new(Position::new(1, 19), Position::new(1, 21))
+        );
+
+        let actions = analysis.code_actions(&uri, undeclared.range);
+        assert_eq!(actions[0].title, "Replace with 'p1'");
+    }
+
+    #[test]
+    fn test_spots() {
+        let analysis = Analysis::new(
+            "players = { p1 }\nspots = { deep = (0, 80) }\nstate = { position = { p1 = deep } }\naction = { move = { p1 -> left_wing } }"
+                .to_string(),
+        );
+
+        // `deep` in the position jumps to the spots section
+        let definition = analysis.definition(Position::new(2, 29)).unwrap();
+        assert_eq!(
+            definition,
+            Range::new(Position::new(1, 10), Position::new(1, 14))
+        );
+
+        let hover = analysis.hover(Position::new(3, 28)).unwrap();
+        let HoverContents::Markup(content) = hover.contents else {
+            panic!("Expected markdown hover");
+        };
+        assert_eq!(content.value, "**left_wing** (spot)\n\n(-62, 27)");
+
+        let labels: Vec<String> = analysis
+            .completions(Position::new(3, 26))
+            .into_iter()
+            .map(|item| item.label)
+            .collect();
+        assert!(labels.contains(&"deep".to_string()));
+        assert!(labels.contains(&"right_corner".to_string()));
+    }
+
+    #[test]
+    fn test_imports() {
+        let dir = std::env::temp_dir().join(format!("playbook-lsp-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("horns.playbook"),
+            "players = { p1, p2 }\nstate = { baller = p1, position = { p1 = (0, 60), p2 = (-25, 30) } }",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("broken.playbook"),
+            "players = { p1 }\nstate = { baller = p9 }",
+        )
+        .unwrap();
+        let uri: Uri = "file:///play.playbook".parse().unwrap();
+
+        let text =
+            "import \"horns.playbook\"\nuse formation horns\naction = { pass = { p1 -> p2 } }";
+        let analysis = Analysis::in_file(text.to_string(), &dir.join("play.playbook"));
+        assert!(analysis.diagnostics(&uri).is_empty());
+        // Inherited players are defined by `use formation`
+        let definition = analysis.definition(Position::new(2, 26)).unwrap();
+        assert_eq!(definition.start, Position::new(1, 14));
+
+        // Errors inside the imported file are reported at the import
+        let text = "import \"broken.playbook\"";
+        let analysis = Analysis::in_file(text.to_string(), &dir.join("play.playbook"));
+        let codes: Vec<Option<NumberOrString>> = analysis
+            .diagnostics(&uri)
+            .into_iter()
+            .map(|d| d.code)
+            .collect();
+        assert_eq!(codes, [Some(NumberOrString::String("E0203".to_string()))]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,229 @@
+mod analysis;
+
+use analysis::Analysis;
+use lsp_server::{Connection, Message, Notification, Request, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument,
+    Notification as NotificationTrait, PublishDiagnostics,
+};
+use lsp_types::request::{
+    CodeActionRequest, Completion, GotoDefinition, HoverRequest, Rename, Request as RequestTrait,
+};
+use lsp_types::{
+    CodeActionOrCommand, CodeActionParams, CodeActionProviderCapability, CompletionOptions,
+    CompletionParams, CompletionResponse, DidChangeTextDocumentParams, DidCloseTextDocumentParams,
+    DidOpenTextDocumentParams, GotoDefinitionParams, GotoDefinitionResponse, HoverParams,
+    HoverProviderCapability, Location, OneOf, PublishDiagnosticsParams, RenameParams,
+    ServerCapabilities, TextDocumentSyncCapability, TextDocumentSyncKind, Uri, WorkspaceEdit,
+};
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::PathBuf;
+
+/// Open documents, keyed by URI string (`Uri` itself is not a good map key).
+type Documents = HashMap<String, Analysis>;
+
+fn main() -> Result<(), Box<dyn Error + Sync + Send>> {
+    let (connection, io_threads) = Connection::stdio();
+
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        completion_provider: Some(CompletionOptions {
+            trigger_characters: Some(vec![":".to_string()]),
+            ..Default::default()
+        }),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        definition_provider: Some(OneOf::Left(true)),
+        rename_provider: Some(OneOf::Left(true)),
+        code_action_provider: Some(CodeActionProviderCapability::Simple(true)),
+        ..Default::default()
+    };
+    connection.initialize(serde_json::to_value(capabilities)?)?;
+
+    let mut documents = Documents::new();
+    for message in &connection.receiver {
+        match message {
+            Message::Request(request) => {
+                if connection.handle_shutdown(&request)? {
+                    break;
+                }
+                let response = handle_request(&documents, request);
+                connection.sender.send(Message::Response(response))?;
+            }
+            Message::Notification(notification) => {
+                handle_notification(&connection, &mut documents, notification)?;
+            }
+            Message::Response(_) => {}
+        }
+    }
+
+    // The writer thread only exits once every sender is gone
+    drop(connection);
+    io_threads.join()?;
+    Ok(())
+}
+
+fn handle_request(documents: &Documents, request: Request) -> Response {
+    let id = request.id.clone();
+    let result = match request.method.as_str() {
+        Completion::METHOD => with_params(request, |params: CompletionParams| {
+            let position = params.text_document_position;
+            documents
+                .get(position.text_document.uri.as_str())
+                .map(|doc| CompletionResponse::Array(doc.completions(position.position)))
+        }),
+        HoverRequest::METHOD => with_params(request, |params: HoverParams| {
+            let position = params.text_document_position_params;
+            documents
+                .get(position.text_document.uri.as_str())
+                .and_then(|doc| doc.hover(position.position))
+        }),
+        GotoDefinition::METHOD => with_params(request, |params: GotoDefinitionParams| {
+            let position = params.text_document_position_params;
+            let uri = position.text_document.uri;
+            documents
+                .get(uri.as_str())
+                .and_then(|doc| doc.definition(position.position))
+                .map(|range| GotoDefinitionResponse::Scalar(Location { uri, range }))
+        }),
+        Rename::METHOD => with_params(request, |params: RenameParams| {
+            let position = params.text_document_position;
+            let uri = position.text_document.uri;
+            documents
+                .get(uri.as_str())
+                .and_then(|doc| doc.rename(position.position, &params.new_name))
+                .map(|edits| WorkspaceEdit {
+                    changes: Some(HashMap::from([(uri, edits)])),
+                    ..Default::default()
+                })
+        }),
+        CodeActionRequest::METHOD => with_params(request, |params: CodeActionParams| {
+            let uri = params.text_document.uri;
+            documents.get(uri.as_str()).map(|doc| {
+                doc.code_actions(&uri, params.range)
+                    .into_iter()
+                    .map(CodeActionOrCommand::CodeAction)
+                    .collect::<Vec<_>>()
+            })
+        }),
+        _ => Ok(serde_json::Value::Null),
+    };
+
+    match result {
+        Ok(value) => Response::new_ok(id, value),
+        Err(message) => Response::new_err(id, lsp_server::ErrorCode::InvalidParams as i32, message),
+    }
+}
+
+fn with_params<P, R>(
+    request: Request,
+    handler: impl FnOnce(P) -> R,
+) -> Result<serde_json::Value, String>
+where
+    P: serde::de::DeserializeOwned,
+    R: serde::Serialize,
+{
+    let params = serde_json::from_value(request.params).map_err(|e| e.to_string())?;
+    serde_json::to_value(handler(params)).map_err(|e| e.to_string())
+}
+
+fn handle_notification(
+    connection: &Connection,
+    documents: &mut Documents,
+    notification: Notification,
+) -> Result<(), Box<dyn Error + Sync + Send>> {
+    match notification.method.as_str() {
+        DidOpenTextDocument::METHOD => {
+            let params: DidOpenTextDocumentParams = serde_json::from_value(notification.params)?;
+            let document = params.text_document;
+            update(
+                connection,
+                documents,
+                document.uri,
+                document.text,
+                Some(document.version),
+            )?;
+        }
+        DidChangeTextDocument::METHOD => {
+            let params: DidChangeTextDocumentParams = serde_json::from_value(notification.params)?;
+            // Full sync: the last change holds the whole document
+            if let Some(change) = params.content_changes.into_iter().last() {
+                let document = params.text_document;
+                update(
+                    connection,
+                    documents,
+                    document.uri,
+                    change.text,
+                    Some(document.version),
+                )?;
+            }
+        }
+        DidCloseTextDocument::METHOD => {
+            let params: DidCloseTextDocumentParams = serde_json::from_value(notification.params)?;
+            documents.remove(params.text_document.uri.as_str());
+            publish(connection, params.text_document.uri, Vec::new(), None)?;
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn update(
+    connection: &Connection,
+    documents: &mut Documents,
+    uri: Uri,
+    text: String,
+    version: Option<i32>,
+) -> Result<(), Box<dyn Error + Sync + Send>> {
+    let analysis = match file_path(&uri) {
+        Some(path) => Analysis::in_file(text, &path),
+        None => Analysis::new(text),
+    };
+    let diagnostics = analysis.diagnostics(&uri);
+    documents.insert(uri.to_string(), analysis);
+    publish(connection, uri, diagnostics, version)
+}
+
+/// The local path of a `file://` URI, for reading imports next to it.
+fn file_path(uri: &Uri) -> Option<PathBuf> {
+    let encoded = uri.as_str().strip_prefix("file://")?.as_bytes();
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut i = 0;
+    while i < encoded.len() {
+        let escaped = (encoded[i] == b'%')
+            .then(|| encoded.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match escaped {
+            Some(byte) => {
+                bytes.push(byte);
+                i += 3;
+            }
+            None => {
+                bytes.push(encoded[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(bytes).ok().map(PathBuf::from)
+}
+
+fn publish(
+    connection: &Connection,
+    uri: Uri,
+    diagnostics: Vec<lsp_types::Diagnostic>,
+    version: Option<i32>,
+) -> Result<(), Box<dyn Error + Sync + Send>> {
+    let params = PublishDiagnosticsParams {
+        uri,
+        diagnostics,
+        version,
+    };
+    connection
+        .sender
+        .send(Message::Notification(Notification::new(
+            PublishDiagnostics::METHOD.to_string(),
+            params,
+        )))?;
+    Ok(())
+}